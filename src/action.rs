@@ -117,9 +117,9 @@ impl undo::Action for AddRegion {
 
     fn apply(&mut self, target: &mut Self::Target) -> undo::Result<Self> {
         match target {
-            Expr::Literal(Value::Project(_sr, tracks)) => {
+            Expr::Literal(Value::Project(_sr, tracks, ..)) => {
                 match tracks.get_mut(self.track_num).unwrap() {
-                    Value::Track(box Value::Array(regions, _t), _tracktype) => {
+                    Value::Track(box Value::Array(regions, _t), _tracktype, _) => {
                         regions.push(self.elem.clone());
                         assert!(!regions.is_empty());
                         self.pos = regions.len() - 1;
@@ -134,9 +134,9 @@ impl undo::Action for AddRegion {
 
     fn undo(&mut self, target: &mut Self::Target) -> undo::Result<Self> {
         match target {
-            Expr::Literal(Value::Project(_sr, tracks)) => {
+            Expr::Literal(Value::Project(_sr, tracks, ..)) => {
                 match tracks.get_mut(self.track_num).unwrap() {
-                    Value::Track(box Value::Array(regions, _t), _tracktype) => {
+                    Value::Track(box Value::Array(regions, _t), _tracktype, _) => {
                         if regions.is_empty() {
                             Err(Error::ContainerEmpty)
                         } else if regions.len() < self.pos {
@@ -176,7 +176,7 @@ impl undo::Action for AddTrack {
 
     fn apply(&mut self, target: &mut Self::Target) -> undo::Result<Self> {
         match target {
-            Expr::Literal(Value::Project(_sr, tracks)) => {
+            Expr::Literal(Value::Project(_sr, tracks, ..)) => {
                 tracks.push(self.elem.clone());
                 self.pos = tracks.len() - 1;
                 Ok(())
@@ -187,7 +187,7 @@ impl undo::Action for AddTrack {
 
     fn undo(&mut self, target: &mut Self::Target) -> undo::Result<Self> {
         match target {
-            Expr::Literal(Value::Project(_sr, tracks)) => {
+            Expr::Literal(Value::Project(_sr, tracks, ..)) => {
                 if tracks.is_empty() {
                     Err(Error::ContainerEmpty)
                 } else {
@@ -206,6 +206,106 @@ impl std::fmt::Display for AddTrack {
 }
 impl DisplayableAction for AddTrack {}
 
+#[derive(Debug)]
+pub struct AddMarker {
+    elem: data::Marker,
+    pos: usize,
+}
+
+impl AddMarker {
+    pub fn new(elem: data::Marker) -> Self {
+        Self { elem, pos: 0 }
+    }
+}
+impl undo::Action for AddMarker {
+    type Target = Expr;
+
+    type Output = ();
+
+    type Error = Error;
+
+    fn apply(&mut self, target: &mut Self::Target) -> undo::Result<Self> {
+        match target {
+            Expr::Literal(Value::Project(_sr, _tracks, _tempo, markers)) => {
+                markers.markers.push(self.elem.clone());
+                self.pos = markers.markers.len() - 1;
+                Ok(())
+            }
+            _ => Err(Error::InvalidConversion),
+        }
+    }
+
+    fn undo(&mut self, target: &mut Self::Target) -> undo::Result<Self> {
+        match target {
+            Expr::Literal(Value::Project(_sr, _tracks, _tempo, markers)) => {
+                if markers.markers.is_empty() {
+                    Err(Error::ContainerEmpty)
+                } else {
+                    markers.markers.remove(self.pos);
+                    Ok(())
+                }
+            }
+            _ => Err(Error::InvalidConversion),
+        }
+    }
+}
+impl std::fmt::Display for AddMarker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Add marker {}", self.elem.label)
+    }
+}
+impl DisplayableAction for AddMarker {}
+
+#[derive(Debug)]
+pub struct RemoveMarker {
+    elem: Option<data::Marker>,
+    pos: usize,
+}
+
+impl RemoveMarker {
+    pub fn new(pos: usize) -> Self {
+        Self { elem: None, pos }
+    }
+}
+impl undo::Action for RemoveMarker {
+    type Target = Expr;
+
+    type Output = ();
+
+    type Error = Error;
+
+    fn apply(&mut self, target: &mut Self::Target) -> undo::Result<Self> {
+        match target {
+            Expr::Literal(Value::Project(_sr, _tracks, _tempo, markers)) => {
+                if self.pos < markers.markers.len() {
+                    self.elem = Some(markers.markers.remove(self.pos));
+                    Ok(())
+                } else {
+                    Err(Error::InvalidIndex(markers.markers.len(), self.pos as i64))
+                }
+            }
+            _ => Err(Error::InvalidConversion),
+        }
+    }
+
+    fn undo(&mut self, target: &mut Self::Target) -> undo::Result<Self> {
+        match (target, self.elem.take()) {
+            (Expr::Literal(Value::Project(_sr, _tracks, _tempo, markers)), Some(elem)) => {
+                markers.markers.insert(self.pos, elem);
+                Ok(())
+            }
+            (_, None) => Err(Error::NothingToBeAdded),
+            _ => Err(Error::InvalidConversion),
+        }
+    }
+}
+impl std::fmt::Display for RemoveMarker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Remove marker {}", self.pos)
+    }
+}
+impl DisplayableAction for RemoveMarker {}
+
 // pub fn add_region(
 //     app: &mut data::AppModel,
 //     track_num: usize,
@@ -1,10 +1,12 @@
-use atomic::SimpleAtomic;
 use std::sync::{Arc, Mutex};
 
+use crate::audio::pool::RenderBatch;
+use crate::audio::region::cache::RenderCache;
 use crate::audio::renderer::{Renderer, RendererBase};
 use crate::data::Project;
 use crate::script::Expr;
-use crate::{audio, data, gui, utils::atomic};
+use crate::utils::SimpleAtomic;
+use crate::{audio, data, gui};
 
 pub(crate) mod filemanager;
 
@@ -18,75 +20,240 @@ enum EditorMode {
 pub struct Model {
     app: Arc<Mutex<data::AppModel>>,
     audio: Renderer<audio::timeline::Model>,
+    /// kept across renderers so that unchanged regions are not rendered on every play.
+    render_cache: Arc<RenderCache>,
+    /// progress of the regions rendered for the current timeline.
+    render_batch: Arc<RenderBatch>,
     compile_err: Option<serde_json::Error>,
     ui: gui::app::State,
     editor_open: bool,
     editor_mode: EditorMode,
+    /// the last error from the audio devices, shown in the settings.
+    audio_error: Option<String>,
 }
 
-fn new_renderer(app: &data::AppModel) -> Renderer<audio::timeline::Model> {
-    let timeline = audio::timeline::Model::new(app.project.clone(), Arc::clone(&app.transport));
-    audio::renderer::create_renderer(
-        timeline,
-        Some(44100),
-        Some(audio::DEFAULT_BUFFER_LEN),
+fn new_timeline(
+    app: &data::AppModel,
+    cache: &Arc<RenderCache>,
+    batch: &Arc<RenderBatch>,
+) -> audio::timeline::Model {
+    audio::timeline::Model::new(app.project.clone(), Arc::clone(&app.transport))
+        .with_cache(Arc::clone(cache))
+        .with_batch(Arc::clone(batch))
+}
+
+fn new_renderer(
+    app: &data::AppModel,
+    cache: &Arc<RenderCache>,
+    batch: &Arc<RenderBatch>,
+) -> Renderer<audio::timeline::Model> {
+    let setting = &app.global_setting.audio;
+    let mut renderer = audio::renderer::create_renderer(
+        new_timeline(app, cache, batch),
+        setting.sample_rate,
+        Some(setting.buffer_size),
         Arc::clone(&app.transport),
-    )
+        &app.launch_arg.backend,
+        &setting.devices,
+    );
+    renderer.set_project_sample_rate(app.project.sample_rate.load() as u32);
+    renderer
 }
 
 impl Model {
     pub fn new(_cc: &eframe::CreationContext<'_>, arg: Option<data::LaunchArg>) -> Self {
         let arg = arg.unwrap_or_default();
-        let mut appmodel = data::AppModel::new(data::Transport::new(), data::GlobalSetting {}, arg);
+        let setting = data::GlobalSetting::load(arg.config_dir.as_deref());
+        let mut appmodel = data::AppModel::new(data::Transport::new(), setting, arg);
         let _ = appmodel.code_to_ui();
         let ui = gui::app::State::new(&appmodel);
+        let render_cache = Arc::new(RenderCache::new(appmodel.render_cache_dir()));
         let app = Arc::new(Mutex::new(appmodel));
 
-        let mut renderer = new_renderer(&app.try_lock().unwrap());
+        let render_batch = Arc::new(RenderBatch::new());
+        let mut renderer = new_renderer(&app.try_lock().unwrap(), &render_cache, &render_batch);
 
         renderer.prepare_play();
         renderer.pause();
         Self {
             audio: renderer,
+            render_cache,
+            render_batch,
             app: Arc::clone(&app),
             compile_err: None,
             ui,
             editor_open: false,
             editor_mode: EditorMode::Code,
+            audio_error: None,
         }
     }
 
     pub fn play(&mut self) {
         self.refresh_audio();
-
-        self.audio.prepare_play();
+        // the timeline queued while running is already prepared from the current position.
+        if !self.audio.is_running() {
+            // the seek made while paused is covered by preparing from the current position.
+            self.app.try_lock().unwrap().transport.take_seek_request();
+            self.audio.prepare_play();
+        }
         self.audio.play();
     }
     pub fn pause(&mut self) {
         self.audio.pause();
     }
+    /// Starts the playback while recording the input for the armed tracks.
+    fn record(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.armed_tracks().is_empty() {
+            log::warn!("no track is armed for recording");
+        } else {
+            let dir = self.app.try_lock().unwrap().recording_dir();
+            let stamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            match dir.map(|dir| dir.join(format!("take_{}.wav", stamp))) {
+                Some(path) => {
+                    if let Err(e) = self.audio.start_recording(path) {
+                        log::error!("failed to start recording: {}", e);
+                    }
+                }
+                None => log::error!("no directory to write the recording"),
+            }
+        }
+        self.play();
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    fn armed_tracks(&self) -> Vec<usize> {
+        let app = self.app.try_lock().unwrap();
+        let armed = app
+            .project
+            .tracks
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| matches!(t, data::Track::Regions(_, param) if param.is_armed()));
+        armed.map(|(i, _)| i).collect()
+    }
+    /// Inserts the recorded take into the armed tracks at the position where the recording started.
+    fn finish_recording(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let take = match self.audio.stop_recording() {
+                Some(Ok(take)) if take.frames > 0 => take,
+                Some(Err(e)) => {
+                    log::error!("failed to write recording: {}", e);
+                    return;
+                }
+                _ => return,
+            };
+            // the playhead is in the project rate, and the file is written at the device rate.
+            let project_sr = self.app.try_lock().unwrap().project.sample_rate.load() as f64;
+            let device_sr = self.audio.get_samplerate() as f64;
+            let (start, duration) = (
+                take.start as f64 / project_sr,
+                take.frames as f64 / device_sr,
+            );
+            let path = take.path.to_string_lossy().to_string();
+            let action_tx = self.app.try_lock().unwrap().action_tx.clone();
+            for i in self.armed_tracks() {
+                let region = gui::menu::make_region_recorded(i, start, duration, path.clone());
+                let _ = action_tx.send(crate::action::AddRegion::new(region, i).into());
+            }
+        }
+    }
+    /// Sends the timeline of the current project to the renderer. The audio stream stays open.
     fn refresh_audio(&mut self) {
-        self.audio = new_renderer(&self.app.try_lock().unwrap());
-        self.audio.prepare_play();
-        self.audio.pause();
+        // the progress of the previous timeline is dropped as its jobs are cancelled.
+        self.render_batch = Arc::new(RenderBatch::new());
+        let app = self.app.try_lock().unwrap();
+        let timeline = new_timeline(&app, &self.render_cache, &self.render_batch);
+        self.audio
+            .set_project_sample_rate(app.project.sample_rate.load() as u32);
+        drop(app);
+        self.audio.replace_effector(timeline);
+    }
+
+    /// Saves the setting and opens the audio devices again with it.
+    fn apply_setting(&mut self, setting: data::GlobalSetting) {
+        let transport = self.app.try_lock().unwrap().transport.clone();
+        // the take is written before the stream is closed.
+        let restart = transport.is_playing() && !transport.is_recording();
+        if transport.is_recording() {
+            transport.request_play(data::PlayOp::Pause);
+            self.finish_recording();
+        }
+        // the devices may not be opened twice.
+        self.audio.close();
+        {
+            let mut app = self.app.try_lock().unwrap();
+            if let Err(e) = setting.save(app.launch_arg.config_dir.as_deref()) {
+                log::error!("failed to save the setting: {}", e);
+            }
+            app.global_setting = setting;
+        }
+        self.render_batch = Arc::new(RenderBatch::new());
+        self.audio = new_renderer(
+            &self.app.try_lock().unwrap(),
+            &self.render_cache,
+            &self.render_batch,
+        );
+        self.audio_error = None;
+        if restart {
+            self.play();
+        } else {
+            self.audio.pause();
+        }
+    }
+    /// Shows the settings with the error if the audio devices failed.
+    fn show_setting(&mut self, ctx: &egui::Context) {
+        if let Some(e) = self.audio.take_error() {
+            log::error!("{}", e);
+            self.audio_error = Some(e.to_string());
+            self.ui
+                .setting
+                .open(&self.app.try_lock().unwrap().global_setting);
+        }
+        if let Some(setting) = self.ui.setting.show(ctx, self.audio_error.as_deref()) {
+            self.apply_setting(setting);
+        }
+    }
+
+    fn show_render_progress(&self, ctx: &egui::Context) {
+        if self.render_batch.is_finished() {
+            return;
+        }
+        let (finished, total) = self.render_batch.get_progress();
+        egui::TopBottomPanel::bottom("Render Progress").show(ctx, |ui| {
+            let progress = egui::ProgressBar::new(finished as f32 / total as f32)
+                .text(format!("rendering regions {}/{}", finished, total));
+            ui.add(progress);
+        });
+        ctx.request_repaint();
     }
 
     fn _respawn_ui(&mut self) {
         self.ui = gui::app::State::new(&self.app.try_lock().unwrap());
     }
     fn sync_transport(&mut self) {
+        self.audio.collect_retired();
         let t = self.app.try_lock().unwrap().transport.clone();
         if let Some(b) = t.ready_to_trigger() {
             match b {
                 data::PlayOp::Play => self.play(),
-                data::PlayOp::Pause => self.pause(),
+                data::PlayOp::Record => self.record(),
+                data::PlayOp::Pause => {
+                    self.pause();
+                    self.finish_recording();
+                }
                 data::PlayOp::Halt => {
                     self.pause();
+                    self.finish_recording();
                     self.audio.rewind();
                 }
             }
-        } else {
-            //do nothing
+        } else if self.audio.is_playing() && t.take_seek_request() {
+            // the live model keeps playing while the timeline is prepared at the new position, and crossfades into it.
+            // the request made while paused is kept until the playback starts from there.
+            self.refresh_audio();
         }
     }
 }
@@ -135,13 +302,15 @@ impl eframe::App for Model {
                     egui::Modifiers::NONE,
                     egui::Key::ArrowLeft,
                 )) {
-                    app.transport.time.store(0);
-                    self.audio.prepare_play();
+                    app.transport.seek(0);
                 }
             });
         }
+        // panels need to be added before the central panel.
+        self.show_render_progress(ctx);
         let mut mainui = gui::app::Model::new(self.app.clone(), &mut self.ui);
         mainui.show_ui(ctx);
+        self.show_setting(ctx);
         self.sync_transport();
 
         let style = egui::Style {
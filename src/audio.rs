@@ -1,5 +1,7 @@
 //! The interpreter implementations for audio rendering.
 
+use crate::data::ChannelLayout;
+
 pub const DEFAULT_BUFFER_LEN: usize = 2048;
 
 #[derive(Clone)]
@@ -7,7 +9,8 @@ pub struct PlaybackInfo {
     pub sample_rate: u32,
     pub current_time: usize,
     pub frame_per_buffer: u64,
-    pub channels: u64,
+    /// layout of the output buffer passed to the components.
+    pub channels: ChannelLayout,
 }
 
 impl PlaybackInfo {
@@ -22,12 +25,38 @@ impl PlaybackInfo {
 pub trait Component: std::fmt::Debug {
     fn get_input_channels(&self) -> u64;
     fn get_output_channels(&self) -> u64;
+    /// Prepares to render from `info.current_time` so that the playback can start from any position.
     fn prepare_play(&mut self, info: &PlaybackInfo);
     fn render(&mut self, input: &[f32], output: &mut [f32], info: &PlaybackInfo);
+    /// Returns the playhead position after rendering the block from `info.current_time`.
+    /// Components that make the playhead jump like a loop playback should override this.
+    fn next_time(&self, info: &PlaybackInfo) -> usize {
+        info.current_time + info.frame_per_buffer as usize
+    }
+}
+
+/// Returns the part of the interleaved device input for the `range` of frames in the block of `frames` frames.
+/// The number of the input channels is derived from the length, as it can differ from the output.
+pub fn slice_input(input: &[f32], frames: usize, range: std::ops::Range<usize>) -> &[f32] {
+    let channels = input.len().checked_div(frames).unwrap_or(0);
+    &input[range.start * channels..range.end * channels]
 }
 
+pub mod ambisonic;
+pub mod backend;
+#[cfg(test)]
+mod fixture;
 pub mod generator;
+pub mod handoff;
+pub mod meter;
+pub mod mixer;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod offline;
+pub mod pool;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod recorder;
 pub mod region;
 pub mod renderer;
+pub mod resampler;
 pub mod timeline;
 pub mod track;
@@ -0,0 +1,129 @@
+//! Encoding and decoding of ambisonics in ACN channel order with SN3D normalization (AmbiX).
+//!
+//! Decoding takes the signal of the virtual microphones pointed at the speakers, with the in-phase weights
+//! so that the sources behind a speaker are not played from it in the opposite phase.
+
+use crate::data::DirectionParam;
+use crate::parameter::Parameter;
+
+/// Order of the spherical harmonic of the ACN channel.
+pub fn degree(acn: usize) -> usize {
+    (acn as f64).sqrt() as usize
+}
+
+/// Associated Legendre function without the Condon-Shortley phase.
+fn legendre(l: usize, m: usize, x: f64) -> f64 {
+    let mut pmm = 1.0;
+    let somx2 = (1.0 - x * x).max(0.0).sqrt();
+    for i in 0..m {
+        pmm *= (2 * i + 1) as f64 * somx2;
+    }
+    if l == m {
+        return pmm;
+    }
+    let mut pmmp1 = x * (2 * m + 1) as f64 * pmm;
+    for ll in m + 2..=l {
+        let pll = (x * (2 * ll - 1) as f64 * pmmp1 - (ll + m - 1) as f64 * pmm) / (ll - m) as f64;
+        pmm = pmmp1;
+        pmmp1 = pll;
+    }
+    pmmp1
+}
+
+/// Real spherical harmonic of the ACN channel with SN3D normalization, at the direction in degrees.
+pub fn harmonic(acn: usize, azimuth: f32, elevation: f32) -> f32 {
+    let l = degree(acn);
+    let m = acn as i64 - (l * l + l) as i64;
+    let abs_m = m.unsigned_abs() as usize;
+    // (l-|m|)! / (l+|m|)!
+    let ratio = (l - abs_m + 1..=l + abs_m).fold(1.0, |acc, k| acc / k as f64);
+    let norm = if m == 0 { 1.0 } else { (2.0 * ratio).sqrt() };
+    let (az, el) = (
+        (azimuth as f64).to_radians(),
+        (elevation as f64).to_radians(),
+    );
+    let trig = match m {
+        0 => 1.0,
+        m if m > 0 => (m as f64 * az).cos(),
+        m => (-m as f64 * az).sin(),
+    };
+    (norm * legendre(l, abs_m, el.sin()) * trig) as f32
+}
+
+/// In-phase weight of the degree `l` for decoding the `order`, including the factor of the addition theorem,
+/// normalized so that the source at the direction of the virtual microphone has unity gain.
+fn decode_weight(order: usize, l: usize) -> f32 {
+    let factorial = |n: usize| (1..=n).fold(1.0, |acc, k| acc * k as f64);
+    let in_phase = |l: usize| {
+        (2 * l + 1) as f64 * factorial(order) * factorial(order + 1)
+            / (factorial(order + l + 1) * factorial(order - l))
+    };
+    let sum = (0..=order).map(in_phase).sum::<f64>();
+    (in_phase(l) / sum) as f32
+}
+
+/// Gain of the ACN channel of the `order` to the speaker at the direction.
+pub fn decode_gain(order: u8, acn: usize, azimuth: f32, elevation: f32) -> f32 {
+    let l = degree(acn);
+    if l > order as usize {
+        return 0.0;
+    }
+    decode_weight(order as usize, l) * harmonic(acn, azimuth, elevation)
+}
+
+/// Encodes the omnidirectional channel of the interleaved B-format `buf` as a point source at the direction.
+/// The direction is taken at the beginning of the block, so a moving source steps from block to block.
+pub fn encode(buf: &mut [f32], channels: usize, direction: &DirectionParam) {
+    let (azimuth, elevation) = (direction.azimuth.get(), direction.elevation.get());
+    // the omnidirectional channel is unity in SN3D, so it is kept as the source.
+    for acn in 1..channels {
+        let gain = harmonic(acn, azimuth, elevation);
+        for frame in buf.chunks_exact_mut(channels) {
+            frame[acn] = frame[0] * gain;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn first_order_harmonics() {
+        let (az, el) = (30.0f32, 20.0f32);
+        let (a, e) = (az.to_radians(), el.to_radians());
+        let expected = [1.0, a.sin() * e.cos(), e.sin(), a.cos() * e.cos()];
+        for (acn, y) in expected.iter().enumerate() {
+            assert!((harmonic(acn, az, el) - y).abs() < 1e-6);
+        }
+    }
+    #[test]
+    fn sn3d_degrees_have_unit_energy() {
+        // the squared harmonics of each degree sum up to 1 in any direction.
+        for (az, el) in [(0.0, 0.0), (77.0, -40.0), (-150.0, 65.0)] {
+            for l in 0..=3usize {
+                let sum = (l * l..(l + 1) * (l + 1))
+                    .map(|acn| harmonic(acn, az, el).powi(2))
+                    .sum::<f32>();
+                assert!((sum - 1.0).abs() < 1e-5, "l={} sum={}", l, sum);
+            }
+        }
+    }
+    #[test]
+    fn decode_to_the_speaker() {
+        for order in 1..=3u8 {
+            let channels = (order as usize + 1).pow(2);
+            let source = (0..channels)
+                .map(|acn| harmonic(acn, 90.0, 0.0))
+                .collect::<Vec<_>>();
+            let speaker = |azimuth| {
+                (0..channels)
+                    .map(|acn| decode_gain(order, acn, azimuth, 0.0) * source[acn])
+                    .sum::<f32>()
+            };
+            assert!((speaker(90.0) - 1.0).abs() < 1e-5);
+            // in-phase decoding has no rear lobe.
+            assert!(speaker(-90.0).abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Audio I/O backends that drive the renderer.
+//!
+//! A backend owns the audio stream and calls the output callback for every block.
+//! The renderer does not know whether the block is sent to an audio device, discarded or written into a file.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub mod cpal_device;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod file;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod null;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamConfig {
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// block size in frames.
+    pub buffer_size: usize,
+}
+
+/// Audio devices chosen by the user, by their names. `None` selects the default one.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct DeviceSelection {
+    pub host: Option<String>,
+    pub output: Option<String>,
+    pub input: Option<String>,
+}
+
+/// Called for every block with the interleaved output buffer.
+pub type OutputCallback = Box<dyn FnMut(&mut [f32], &StreamConfig) + Send>;
+/// Called for every block with the interleaved input buffer.
+pub type InputCallback = Box<dyn FnMut(&[f32], &StreamConfig) + Send>;
+
+pub trait Backend {
+    fn get_config(&self) -> &StreamConfig;
+    /// Starts calling the callbacks.
+    fn play(&mut self);
+    /// Stops calling the callbacks. The stream is kept open.
+    fn pause(&mut self);
+    /// Returns the error happened in the running stream, such as the device being unplugged.
+    fn take_error(&mut self) -> Option<Error> {
+        None
+    }
+}
+
+/// Backend left after the stream is closed. The callbacks are never called again.
+pub struct Closed(pub StreamConfig);
+
+impl Backend for Closed {
+    fn get_config(&self) -> &StreamConfig {
+        &self.0
+    }
+    fn play(&mut self) {}
+    fn pause(&mut self) {}
+}
+
+/// Kind of the backend selected at launch.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Kind {
+    /// Audio devices via cpal.
+    #[default]
+    Cpal,
+    /// No audio device. The renderer is driven by a timer thread and the output is discarded.
+    Null,
+    /// Same as `Null` but the output is written into the wav file at the path.
+    File(String),
+}
+
+#[derive(Debug)]
+pub enum Error {
+    NoDevice,
+    /// The host or the device selected in the settings is not available.
+    DeviceNotFound(String),
+    Stream(String),
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NoDevice => write!(f, "no audio output device found"),
+            Error::DeviceNotFound(name) => write!(f, "audio device \"{}\" is not available", name),
+            Error::Stream(e) => write!(f, "failed to open audio stream: {}", e),
+            Error::Unsupported(e) => write!(f, "unsupported backend: {}", e),
+        }
+    }
+}
+impl std::error::Error for Error {}
+
+/// Opens the backend of `kind`. The stream is paused until [`Backend::play`] is called.
+pub fn open(
+    kind: &Kind,
+    devices: &DeviceSelection,
+    sample_rate: Option<u32>,
+    buffer_size: usize,
+    input: InputCallback,
+    output: OutputCallback,
+) -> Result<Box<dyn Backend>, Error> {
+    match kind {
+        Kind::Cpal => {
+            cpal_device::CpalBackend::new(devices, sample_rate, buffer_size, input, output)
+                .map(|b| Box::new(b) as Box<dyn Backend>)
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        Kind::Null => Ok(Box::new(null::NullBackend::new(
+            null_config(sample_rate, buffer_size),
+            output,
+            None,
+        ))),
+        #[cfg(not(target_arch = "wasm32"))]
+        Kind::File(path) => {
+            file::FileBackend::new(path, null_config(sample_rate, buffer_size), output)
+                .map(|b| Box::new(b) as Box<dyn Backend>)
+        }
+        #[cfg(target_arch = "wasm32")]
+        _ => Err(Error::Unsupported("only cpal backend is available on web")),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn null_config(sample_rate: Option<u32>, buffer_size: usize) -> StreamConfig {
+    StreamConfig {
+        sample_rate: sample_rate.unwrap_or(48000),
+        channels: 2,
+        buffer_size,
+    }
+}
@@ -0,0 +1,225 @@
+use super::{Backend, DeviceSelection, Error, InputCallback, OutputCallback, StreamConfig};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use std::sync::{Arc, Mutex};
+
+/// Sample rates offered in the settings, if the output device supports them.
+pub const COMMON_SAMPLE_RATES: [u32; 6] = [22050, 32000, 44100, 48000, 88200, 96000];
+
+/// Backend for the input/output devices of a cpal host.
+pub struct CpalBackend {
+    istream: Option<cpal::Stream>,
+    ostream: cpal::Stream,
+    config: StreamConfig,
+    /// the last error reported from the stream callbacks.
+    error: Arc<Mutex<Option<Error>>>,
+}
+
+/// Devices of a host listed for the settings.
+#[derive(Clone, Debug, Default)]
+pub struct DeviceList {
+    pub outputs: Vec<String>,
+    pub inputs: Vec<String>,
+    /// sample rates in [`COMMON_SAMPLE_RATES`] the selected output device supports.
+    pub sample_rates: Vec<u32>,
+}
+
+fn choose_sample_rate(range: &cpal::SupportedStreamConfigRange, sample_rate: Option<u32>) -> u32 {
+    let (min, max) = (range.min_sample_rate().0, range.max_sample_rate().0);
+    sample_rate
+        .filter(|sr| (min..=max).contains(sr))
+        .unwrap_or(max)
+}
+
+fn supports(range: &cpal::SupportedStreamConfigRange, sample_rate: Option<u32>) -> bool {
+    sample_rate
+        .is_none_or(|sr| (range.min_sample_rate().0..=range.max_sample_rate().0).contains(&sr))
+}
+
+pub fn host_names() -> Vec<String> {
+    cpal::available_hosts()
+        .into_iter()
+        .map(|id| id.name().to_string())
+        .collect()
+}
+
+fn find_host(name: Option<&str>) -> Result<cpal::Host, Error> {
+    match name {
+        None => Ok(cpal::default_host()),
+        Some(name) => {
+            let id = cpal::available_hosts()
+                .into_iter()
+                .find(|id| id.name() == name)
+                .ok_or_else(|| Error::DeviceNotFound(name.to_string()))?;
+            cpal::host_from_id(id).map_err(|_| Error::DeviceNotFound(name.to_string()))
+        }
+    }
+}
+
+fn find_device(
+    devices: Result<impl Iterator<Item = cpal::Device>, cpal::DevicesError>,
+    name: &str,
+) -> Result<cpal::Device, Error> {
+    devices
+        .map_err(|e| Error::Stream(e.to_string()))?
+        .find(|d| d.name().is_ok_and(|n| n == name))
+        .ok_or_else(|| Error::DeviceNotFound(name.to_string()))
+}
+
+fn output_device(host: &cpal::Host, name: Option<&str>) -> Result<cpal::Device, Error> {
+    match name {
+        None => host.default_output_device().ok_or(Error::NoDevice),
+        Some(name) => find_device(host.output_devices(), name),
+    }
+}
+
+fn input_device(host: &cpal::Host, name: Option<&str>) -> Result<Option<cpal::Device>, Error> {
+    match name {
+        None => Ok(host.default_input_device()),
+        Some(name) => find_device(host.input_devices(), name).map(Some),
+    }
+}
+
+fn device_names(
+    devices: Result<impl Iterator<Item = cpal::Device>, cpal::DevicesError>,
+) -> Vec<String> {
+    devices.map_or(vec![], |ds| ds.filter_map(|d| d.name().ok()).collect())
+}
+
+/// Lists the devices of the selected host.
+pub fn list_devices(selection: &DeviceSelection) -> Result<DeviceList, Error> {
+    let host = find_host(selection.host.as_deref())?;
+    let sample_rates = output_device(&host, selection.output.as_deref())
+        .ok()
+        .and_then(|d| d.supported_output_configs().ok())
+        .map_or(vec![], |ranges| {
+            let ranges = ranges.collect::<Vec<_>>();
+            COMMON_SAMPLE_RATES
+                .into_iter()
+                .filter(|sr| ranges.iter().any(|r| supports(r, Some(*sr))))
+                .collect()
+        });
+    Ok(DeviceList {
+        outputs: device_names(host.output_devices()),
+        inputs: device_names(host.input_devices()),
+        sample_rates,
+    })
+}
+
+fn stream_error(
+    error: &Arc<Mutex<Option<Error>>>,
+    device: Option<String>,
+) -> impl FnMut(cpal::StreamError) + Send + 'static {
+    let error = error.clone();
+    move |e| {
+        log::error!("{}", e);
+        let e = match (e, &device) {
+            (cpal::StreamError::DeviceNotAvailable, Some(name)) => {
+                Error::DeviceNotFound(name.clone())
+            }
+            (e, _) => Error::Stream(e.to_string()),
+        };
+        if let Ok(mut slot) = error.lock() {
+            *slot = Some(e);
+        }
+    }
+}
+
+impl CpalBackend {
+    pub fn new(
+        devices: &DeviceSelection,
+        sample_rate: Option<u32>,
+        buffer_size: usize,
+        mut input: InputCallback,
+        mut output: OutputCallback,
+    ) -> Result<Self, Error> {
+        let host = find_host(devices.host.as_deref())?;
+        let odevice = output_device(&host, devices.output.as_deref())?;
+        log::debug!("device {:?}", odevice.name());
+        let error = Arc::new(Mutex::new(None));
+        // prefer the config supporting the requested sample rate, and stereo output.
+        let oconfig_range = odevice
+            .supported_output_configs()
+            .map_err(|e| Error::Stream(e.to_string()))?
+            .max_by_key(|c| (supports(c, sample_rate), c.channels() == 2, c.channels()))
+            .ok_or(Error::Stream("no supported output config".to_string()))?;
+        let sr = choose_sample_rate(&oconfig_range, sample_rate);
+        let mut oconfig = oconfig_range
+            .with_sample_rate(cpal::SampleRate(sr))
+            .config();
+        oconfig.buffer_size = cpal::BufferSize::Fixed(buffer_size as u32);
+        let config = StreamConfig {
+            sample_rate: sr,
+            channels: oconfig.channels,
+            buffer_size,
+        };
+        let c = config.clone();
+        let ostream = odevice
+            .build_output_stream(
+                &oconfig,
+                move |data: &mut [f32], _s: &cpal::OutputCallbackInfo| output(data, &c),
+                stream_error(&error, odevice.name().ok()),
+                None,
+            )
+            .map_err(|e| Error::Stream(e.to_string()))?;
+        let _ = ostream.pause();
+
+        // input is optional. failing to open it does not prevent playback, but the missing device is reported.
+        let idevice = input_device(&host, devices.input.as_deref()).unwrap_or_else(|e| {
+            log::warn!("{}", e);
+            *error.lock().unwrap() = Some(e);
+            None
+        });
+        let istream = idevice.and_then(|device| {
+            let range = device
+                .supported_input_configs()
+                .ok()?
+                .max_by_key(|c| supports(c, Some(sr)))?;
+            let isr = choose_sample_rate(&range, Some(sr));
+            let iconfig = range.with_sample_rate(cpal::SampleRate(isr)).config();
+            let c = StreamConfig {
+                sample_rate: iconfig.sample_rate.0,
+                channels: iconfig.channels,
+                buffer_size,
+            };
+            let stream = device
+                .build_input_stream(
+                    &iconfig,
+                    move |data: &[f32], _s: &cpal::InputCallbackInfo| input(data, &c),
+                    stream_error(&error, device.name().ok()),
+                    None,
+                )
+                .ok()?;
+            let _ = stream.pause();
+            Some(stream)
+        });
+        Ok(Self {
+            istream,
+            ostream,
+            config,
+            error,
+        })
+    }
+}
+
+impl Backend for CpalBackend {
+    fn get_config(&self) -> &StreamConfig {
+        &self.config
+    }
+    fn play(&mut self) {
+        if let Some(is) = &self.istream {
+            let _ = is.play();
+        }
+        if let Err(e) = self.ostream.play() {
+            log::error!("{}", e);
+        }
+    }
+    fn pause(&mut self) {
+        if let Some(is) = &self.istream {
+            let _ = is.pause();
+        }
+        let _ = self.ostream.pause();
+    }
+    fn take_error(&mut self) -> Option<Error> {
+        self.error.lock().ok()?.take()
+    }
+}
@@ -0,0 +1,65 @@
+use super::null::NullBackend;
+use super::{Backend, Error, OutputCallback, StreamConfig};
+
+/// Backend which writes the output into a 32bit float wav file instead of audio devices.
+/// The file is finalized when the backend is dropped.
+pub struct FileBackend(NullBackend);
+
+impl FileBackend {
+    pub fn new(path: &str, config: StreamConfig, output: OutputCallback) -> Result<Self, Error> {
+        let spec = hound::WavSpec {
+            channels: config.channels,
+            sample_rate: config.sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer =
+            hound::WavWriter::create(path, spec).map_err(|e| Error::Stream(e.to_string()))?;
+        let sink = Box::new(move |buffer: &[f32]| {
+            for s in buffer {
+                if let Err(e) = writer.write_sample(*s) {
+                    log::error!("{}", e);
+                    break;
+                }
+            }
+        });
+        Ok(Self(NullBackend::new(config, output, Some(sink))))
+    }
+}
+
+impl Backend for FileBackend {
+    fn get_config(&self) -> &StreamConfig {
+        self.0.get_config()
+    }
+    fn play(&mut self) {
+        self.0.play()
+    }
+    fn pause(&mut self) {
+        self.0.pause()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn write_blocks() {
+        let path = std::env::temp_dir().join("otopoiesis_file_backend_test.wav");
+        let config = StreamConfig {
+            sample_rate: 48000,
+            channels: 2,
+            buffer_size: 256,
+        };
+        let output = Box::new(|data: &mut [f32], _c: &StreamConfig| data.fill(0.25));
+        let mut backend = FileBackend::new(path.to_str().unwrap(), config, output).unwrap();
+        backend.play();
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        backend.pause();
+        drop(backend);
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert!(reader.len() > 0);
+        assert_eq!(reader.len() % (256 * 2), 0);
+        assert!(reader.samples::<f32>().all(|s| s.unwrap() == 0.25));
+        let _ = std::fs::remove_file(path);
+    }
+}
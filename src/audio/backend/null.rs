@@ -0,0 +1,75 @@
+use super::{Backend, OutputCallback, StreamConfig};
+use crate::utils::atomic::{self, SimpleAtomic};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Receives every rendered block, used to write the output somewhere other than a device.
+pub type Sink = Box<dyn FnMut(&[f32]) + Send>;
+
+/// Backend without audio devices. A timer thread calls the output callback at the pace of the real time.
+pub struct NullBackend {
+    config: StreamConfig,
+    is_playing: Arc<atomic::Bool>,
+    is_alive: Arc<atomic::Bool>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl NullBackend {
+    pub fn new(config: StreamConfig, mut output: OutputCallback, mut sink: Option<Sink>) -> Self {
+        let is_playing = Arc::new(atomic::Bool::from(false));
+        let is_alive = Arc::new(atomic::Bool::from(true));
+        let (playing, alive) = (is_playing.clone(), is_alive.clone());
+        let c = config.clone();
+        let thread = std::thread::Builder::new()
+            .name("null audio backend".to_string())
+            .spawn(move || {
+                let period = Duration::from_secs_f64(c.buffer_size as f64 / c.sample_rate as f64);
+                let mut buffer = vec![0.0f32; c.buffer_size * c.channels as usize];
+                let mut next = Instant::now();
+                while alive.load() {
+                    if playing.load() {
+                        output(&mut buffer, &c);
+                        if let Some(sink) = sink.as_mut() {
+                            sink(&buffer);
+                        }
+                    }
+                    next += period;
+                    let now = Instant::now();
+                    if next > now {
+                        std::thread::sleep(next - now);
+                    } else {
+                        // do not try to catch up when the rendering is slower than the real time.
+                        next = now;
+                    }
+                }
+            })
+            .expect("failed to launch thread");
+        Self {
+            config,
+            is_playing,
+            is_alive,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Backend for NullBackend {
+    fn get_config(&self) -> &StreamConfig {
+        &self.config
+    }
+    fn play(&mut self) {
+        self.is_playing.store(true);
+    }
+    fn pause(&mut self) {
+        self.is_playing.store(false);
+    }
+}
+
+impl Drop for NullBackend {
+    fn drop(&mut self) {
+        self.is_alive.store(false);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
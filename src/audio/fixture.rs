@@ -0,0 +1,90 @@
+//! Regions, projects and generators shared by the tests of the audio modules.
+
+use super::generator::GeneratorComponent;
+use super::PlaybackInfo;
+use crate::data;
+use crate::param_float;
+use crate::parameter::{FloatParameter, Parameter, RangedNumeric};
+use crate::script::{Expr, Value};
+use crate::utils::AtomicRange;
+use std::sync::Arc;
+
+/// Region of the constant generator playing the parameter from `start` to `end` seconds.
+pub fn constant_region_of(value: &Arc<FloatParameter>, start: f64, end: f64) -> data::Region {
+    let generator = Value::new_lazy(Expr::App(
+        Expr::Literal(Value::ExtFunction("constant".to_string())).into(),
+        vec![Expr::Literal(Value::Parameter(value.clone()))],
+    ));
+    data::Region::new(
+        AtomicRange::<f64>::new(start, end),
+        data::Content::Generator(generator),
+        "constant",
+    )
+}
+/// Region of the constant `value` from `start` to `end` seconds.
+pub fn constant_region(value: f32, start: f64, end: f64) -> data::Region {
+    let value = Arc::new(param_float!(value, "value", 0.0..=1.0));
+    constant_region_of(&value, start, end)
+}
+/// Project of a track with the regions.
+pub fn project(regions: Vec<data::Region>, sample_rate: u64) -> data::Project {
+    data::Project {
+        sample_rate: sample_rate.into(),
+        tracks: vec![data::Track::Regions(regions, data::TrackParam::default())],
+        tempo: data::TempoMap::default(),
+        markers: data::Markers::default(),
+    }
+}
+
+/// Sample rate of the generators rendered in the tests.
+pub const SR: usize = 48000;
+
+/// Mono playback at [`SR`] from `current_time`.
+pub fn mono_info(current_time: usize, frame_per_buffer: usize) -> PlaybackInfo {
+    PlaybackInfo {
+        sample_rate: SR as u32,
+        current_time,
+        frame_per_buffer: frame_per_buffer as u64,
+        channels: data::ChannelLayout::Mono,
+    }
+}
+pub fn param(value: f32, label: &str) -> Arc<FloatParameter> {
+    Arc::new(FloatParameter::new(value, label))
+}
+/// Oscillator at `freq` with the amplitude of 1 from the phase 0.
+pub fn osc_param(freq: f32) -> data::OscillatorParam {
+    data::OscillatorParam {
+        freq: param(freq, "freq"),
+        amp: param(1.0, "amp"),
+        phase: param(0.0, "phase"),
+    }
+}
+/// Seeks the generator to `from` and renders `len` samples one by one.
+pub fn render_samples(
+    generator: &mut impl GeneratorComponent,
+    from: usize,
+    len: usize,
+) -> Vec<f32> {
+    let info = mono_info(from, len);
+    generator.seek(from, &info);
+    (0..len)
+        .map(|_| {
+            let mut s = 0.0;
+            generator.render_sample(&mut s, &info);
+            s
+        })
+        .collect()
+}
+/// Amplitude of the sine at `freq` Hz in the samples at [`SR`], by DFT.
+/// Exact when the samples hold the integer number of its cycles.
+pub fn amplitude(samples: &[f32], freq: f64) -> f64 {
+    let w = std::f64::consts::TAU * freq / SR as f64;
+    let (re, im) = samples
+        .iter()
+        .enumerate()
+        .fold((0.0, 0.0), |(re, im), (i, s)| {
+            let t = w * i as f64;
+            (re + *s as f64 * t.cos(), im - *s as f64 * t.sin())
+        });
+    2.0 * (re * re + im * im).sqrt() / samples.len() as f64
+}
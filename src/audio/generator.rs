@@ -2,20 +2,33 @@ use std::sync::Arc;
 
 use super::*;
 use crate::{
-    data::{FilePlayerParam, OscillatorParam},
+    data::{FilePlayerParam, NoiseColor, NoiseParam, OscillatorParam},
     parameter::{FloatParameter, Parameter, RangedNumeric, UIntParameter},
     script::{self, Expr, Value},
 };
+pub mod additive;
 pub mod constant;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod fileplayer;
+pub mod fm;
+pub mod input;
 pub mod noise;
 pub mod oscillator;
+pub mod wavetable;
 pub trait GeneratorComponent {
     type Params;
     fn get_params(&self) -> &Self::Params;
     fn reset_phase(&mut self);
     fn render_sample(&mut self, out: &mut f32, info: &PlaybackInfo);
+    /// Moves the state to `time` samples after the beginning.
+    /// The default implementation renders the samples until there and discards them.
+    fn seek(&mut self, time: usize, info: &PlaybackInfo) {
+        self.reset_phase();
+        let mut dummy = 0.0;
+        for _ in 0..time {
+            self.render_sample(&mut dummy, info);
+        }
+    }
 }
 impl<T> Component for T
 where
@@ -25,27 +38,21 @@ where
         0
     }
     fn get_output_channels(&self) -> u64 {
-        2
+        1
     }
 
-    fn prepare_play(&mut self, _info: &PlaybackInfo) {
-        self.reset_phase();
+    fn prepare_play(&mut self, info: &PlaybackInfo) {
+        self.seek(info.current_time, info);
     }
+    /// The mono samples are upmixed into the layout of the output.
     fn render(&mut self, _input: &[f32], output: &mut [f32], info: &PlaybackInfo) {
-        for (_count, out_per_channel) in output
-            .chunks_mut(self.get_output_channels() as usize)
-            .enumerate()
-        {
+        let chs = info.channels.count().max(1);
+        for out_per_channel in output.chunks_mut(chs) {
             let mut res = 0.0;
             self.render_sample(&mut res, info);
-            for (ch, s) in out_per_channel.iter_mut().enumerate() {
-                if ch == 0 {
-                    *s = res
-                } else {
-                    *s = 0.0
-                }
-            }
+            out_per_channel.fill(res);
         }
+        mixer::upmix_mono(output, info.channels);
     }
 }
 
@@ -60,56 +67,183 @@ where
 //             data::OscillatorFun::Triangular => oscillator::triangle(param.clone().as_ref().clone()),
 //         }),
 //         data::Generator::Constant(param) => Box::new(Constant(param.clone())),
-//         data::Generator::Noise() => Box::new(Noise {}),
+//         data::Generator::Noise(color, param) => Box::new(Noise::new(*color, param.as_ref().clone())),
 //         #[cfg(not(target_arch = "wasm32"))]
 //         data::Generator::FilePlayer(param) => Box::new(fileplayer::FilePlayer::new(param.clone())),
 //     }
 // }
+/// Builds the oscillator of the name. `shape` is the direction of the sawtooth or the duty ratio of
+/// the rectangular, which is omitted for the rising sawtooth and the square.
+fn get_oscillator(
+    fname: &str,
+    params: OscillatorParam,
+    shape: Option<Arc<FloatParameter>>,
+) -> oscillator::GenericOscillator {
+    let shape_or = |default: f32, label: &str| {
+        shape.unwrap_or_else(|| Arc::new(FloatParameter::new(default, label).set_range(0.0..=1.0)))
+    };
+    match fname {
+        "sawtooth" => oscillator::saw(params, shape_or(1.0, "direction")),
+        "rectangular" => oscillator::rect(params, shape_or(0.5, "duty")),
+        "triangular" => oscillator::triangle(params),
+        _ => oscillator::sinewave(params),
+    }
+}
+/// Builds the table from the path of the file, the array of the frames or the closure computing them.
+fn build_wavetable(table: &Expr) -> Result<wavetable::Wavetable, Box<dyn std::error::Error>> {
+    let invalid = |_| "invalid wavetable".into();
+    match table {
+        #[cfg(not(target_arch = "wasm32"))]
+        Expr::Literal(Value::String(path)) => wavetable::Wavetable::load(path),
+        Expr::Literal(Value::Array(frames, _)) => {
+            wavetable::Wavetable::from_array(frames).map_err(invalid)
+        }
+        Expr::Literal(closure @ Value::Closure(..)) => {
+            wavetable::Wavetable::from_closure(closure).map_err(invalid)
+        }
+        _ => Err("No matching wavetable".into()),
+    }
+}
+/// Returns the table shared with the other generators of the same table, building it if none of them is alive.
+/// The table failed to build is logged and plays silence.
+fn get_wavetable(table: &Expr) -> Arc<wavetable::Wavetable> {
+    use std::collections::HashMap;
+    use std::sync::{Mutex, OnceLock, Weak};
+    static TABLES: OnceLock<Mutex<HashMap<String, Weak<wavetable::Wavetable>>>> = OnceLock::new();
+    let mut key = serde_json::to_string(table).unwrap_or_default();
+    // the file is loaded again when it is modified.
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Expr::Literal(Value::String(path)) = table {
+        if let Ok(meta) = std::fs::metadata(path) {
+            key += &format!(":{}:{:?}", meta.len(), meta.modified().ok());
+        }
+    }
+    let mut tables = TABLES.get_or_init(Default::default).lock().unwrap();
+    if let Some(table) = tables.get(&key).and_then(Weak::upgrade) {
+        return table;
+    }
+    let res = Arc::new(build_wavetable(table).unwrap_or_else(|e| {
+        log::error!("failed to build the wavetable: {}", e);
+        wavetable::Wavetable::new(Vec::<Vec<f32>>::new())
+    }));
+    tables.retain(|_, t| t.strong_count() > 0);
+    tables.insert(key, Arc::downgrade(&res));
+    res
+}
+/// Plays silence in place of the generator failed to build from the values of the script, logging the error.
+fn silence(error: impl std::fmt::Display) -> Box<dyn Component + Send + Sync> {
+    log::error!("failed to build the generator: {}", error);
+    Box::new(constant::Constant(Arc::new(FloatParameter::new(0.0, "silence"))))
+}
 pub fn get_component_for_value(v: &script::Value) -> Box<dyn Component + Send + Sync> {
     match v {
         Value::Closure(_ids, _env,box Expr::App(box Expr::Literal(Value::ExtFunction(fname)), args)) => {
             match (fname.as_str(), &args.as_slice()) {
                 (
-                    "sinewave",
-                    &[Expr::Literal(Value::Parameter(freq)), Expr::Literal(Value::Parameter(amp)), Expr::Literal(Value::Parameter(phase))],
-                ) => Box::new(oscillator::sinewave(OscillatorParam {
-                    amp: amp.clone(),
-                    freq: freq.clone(),
-                    phase: phase.clone(),
-                })),
+                    "sinewave" | "sawtooth" | "rectangular" | "triangular",
+                    &[Expr::Literal(Value::Parameter(freq)), Expr::Literal(Value::Parameter(amp)), Expr::Literal(Value::Parameter(phase)), ref shape @ ..],
+                ) => {
+                    let params = OscillatorParam {
+                        amp: amp.clone(),
+                        freq: freq.clone(),
+                        phase: phase.clone(),
+                    };
+                    let shape = match shape {
+                        [Expr::Literal(Value::Parameter(p))] => Some(p.clone()),
+                        [] => None,
+                        _ => panic!("No matching generator"),
+                    };
+                    Box::new(get_oscillator(fname, params, shape))
+                }
+                (
+                    "whitenoise" | "pinknoise" | "brownnoise" | "velvetnoise",
+                    &[Expr::Literal(Value::Parameter(amp)), Expr::Literal(Value::Parameter(seed)), ref density @ ..],
+                ) => {
+                    let mut params = NoiseParam {
+                        amp: amp.clone(),
+                        seed: seed.clone(),
+                        ..Default::default()
+                    };
+                    match density {
+                        [Expr::Literal(Value::Parameter(p))] => params.density = p.clone(),
+                        [] => {}
+                        _ => panic!("No matching generator"),
+                    }
+                    let color = NoiseColor::from_name(fname).unwrap();
+                    Box::new(noise::Noise::new(color, params))
+                }
                 (
-                    "sawtooth",
-                    &[Expr::Literal(Value::Parameter(freq)), Expr::Literal(Value::Parameter(amp)), Expr::Literal(Value::Parameter(phase)), Expr::Literal(Value::Parameter(dir))],
-                ) => Box::new(oscillator::saw(
-                    OscillatorParam {
+                    "wavetable",
+                    &[Expr::Literal(Value::Parameter(freq)), Expr::Literal(Value::Parameter(amp)), Expr::Literal(Value::Parameter(phase)), Expr::Literal(Value::Parameter(position)), ref table],
+                ) => {
+                    let params = OscillatorParam {
                         amp: amp.clone(),
                         freq: freq.clone(),
                         phase: phase.clone(),
-                    },
-                    dir.clone(),
-                )),
+                    };
+                    let table = get_wavetable(table);
+                    Box::new(wavetable::wavetable(params, position.clone(), table))
+                }
                 (
-                    "rectangular",
-                    &[Expr::Literal(Value::Parameter(freq)), Expr::Literal(Value::Parameter(amp)), Expr::Literal(Value::Parameter(phase)), Expr::Literal(Value::Parameter(duty))],
-                ) => Box::new(oscillator::rect(
-                    OscillatorParam {
+                    "fm" | "pm",
+                    &[Expr::Literal(Value::Parameter(freq)), Expr::Literal(Value::Parameter(amp)), Expr::Literal(Value::Parameter(phase)), Expr::Literal(Value::Array(ref operators, _)), Expr::Literal(Value::Array(ref routes, _))],
+                ) => {
+                    let params = OscillatorParam {
                         amp: amp.clone(),
                         freq: freq.clone(),
                         phase: phase.clone(),
-                    },
-                    duty.clone(),
-                )),
+                    };
+                    let modulation = if fname == "fm" {
+                        fm::Modulation::Frequency
+                    } else {
+                        fm::Modulation::Phase
+                    };
+                    match fm::OperatorGraph::from_values(params, modulation, operators, routes) {
+                        Ok(graph) => Box::new(graph),
+                        Err(e) => silence(e),
+                    }
+                }
                 (
-                    "triangular",
-                    &[Expr::Literal(Value::Parameter(freq)), Expr::Literal(Value::Parameter(amp)), Expr::Literal(Value::Parameter(phase))],
-                ) => Box::new(oscillator::sinewave(OscillatorParam {
-                    amp: amp.clone(),
-                    freq: freq.clone(),
-                    phase: phase.clone(),
-                })),
+                    "am" | "ringmod",
+                    &[Expr::Literal(Value::Parameter(freq)), Expr::Literal(Value::Parameter(amp)), Expr::Literal(Value::Parameter(phase)), Expr::Literal(Value::Parameter(ratio)), ref depth @ ..],
+                ) => {
+                    let params = OscillatorParam {
+                        amp: amp.clone(),
+                        freq: freq.clone(),
+                        phase: phase.clone(),
+                    };
+                    // the ring modulation is the full depth.
+                    let depth = match (fname.as_str(), depth) {
+                        ("am", [Expr::Literal(Value::Parameter(p))]) => p.clone(),
+                        ("ringmod", []) => Arc::new(FloatParameter::new(1.0, "depth")),
+                        _ => return silence(format!("invalid depth of {}", fname)),
+                    };
+                    Box::new(fm::AmplitudeModulation::new(params, ratio.clone(), depth))
+                }
+                (
+                    "additive",
+                    &[Expr::Literal(Value::Parameter(freq)), Expr::Literal(Value::Parameter(amp)), Expr::Literal(Value::Parameter(phase)), Expr::Literal(Value::Array(ref partials, _))],
+                ) => {
+                    let params = OscillatorParam {
+                        amp: amp.clone(),
+                        freq: freq.clone(),
+                        phase: phase.clone(),
+                    };
+                    match additive::Additive::from_values(params, partials) {
+                        Ok(additive) => Box::new(additive),
+                        Err(e) => silence(e),
+                    }
+                }
                 ("constant", &[Expr::Literal(Value::Parameter(val))]) => {
                     Box::new(constant::Constant(val.clone()))
                 }
+                (
+                    "input",
+                    &[Expr::Literal(Value::Parameter(left)), Expr::Literal(Value::Parameter(right))],
+                ) => Box::new(input::LiveInput {
+                    left: left.clone(),
+                    right: right.clone(),
+                }),
                 #[cfg(not(target_arch = "wasm32"))]
                 ("fileplayer", &[Expr::Literal(Value::String(path))]) => {
                     let p = FilePlayerParam {
@@ -128,3 +262,111 @@ pub fn get_component_for_value(v: &script::Value) -> Box<dyn Component + Send +
         _ => panic!("invalid components"),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn oscillator_value(fname: &str, extra: Option<f32>) -> Value {
+        let param = |v: f32| {
+            Expr::Literal(Value::Parameter(Arc::new(
+                FloatParameter::new(v, "p").set_range(0.0..=20000.0),
+            )))
+        };
+        let args = [param(100.0), param(1.0), param(0.25)]
+            .into_iter()
+            .chain(extra.map(param))
+            .collect();
+        Value::new_lazy(Expr::App(
+            Expr::Literal(Value::ExtFunction(fname.to_string())).into(),
+            args,
+        ))
+    }
+
+    #[test]
+    fn dispatch_oscillators() {
+        let info = PlaybackInfo {
+            sample_rate: 48000,
+            current_time: 0,
+            frame_per_buffer: 1,
+            channels: ChannelLayout::Mono,
+        };
+        let first_sample = |fname: &str, extra: Option<f32>| {
+            let mut component = get_component_for_value(&oscillator_value(fname, extra));
+            component.prepare_play(&info);
+            let mut out = [0.0];
+            component.render(&[], &mut out, &info);
+            out[0]
+        };
+        // at the quarter of the period.
+        assert!((first_sample("sinewave", None) - 1.0).abs() < 1e-3);
+        assert!(first_sample("triangular", None).abs() < 1e-3);
+        assert!((first_sample("sawtooth", None) + 0.5).abs() < 1e-3);
+        assert!((first_sample("sawtooth", Some(0.0)) - 0.5).abs() < 1e-3);
+        assert!((first_sample("rectangular", None) - 1.0).abs() < 1e-3);
+        assert!((first_sample("rectangular", Some(0.2)) + 1.0).abs() < 1e-3);
+    }
+    #[test]
+    fn dispatch_operator_graph() {
+        let param = |v: f32| Value::Parameter(Arc::new(FloatParameter::new(v, "p")));
+        let array = |values| Value::Array(values, script::Type::Unknown);
+        let route = |from: f64, to: f64| array(vec![Value::Number(from), Value::Number(to)]);
+        let operators = array(vec![
+            array(vec![param(1.0), param(0.0)]),
+            array(vec![param(2.0), param(1.0)]),
+        ]);
+        let value = |routes| {
+            Value::new_lazy(Expr::App(
+                Expr::Literal(Value::ExtFunction("pm".to_string())).into(),
+                [
+                    param(100.0),
+                    param(1.0),
+                    param(0.25),
+                    operators.clone(),
+                    routes,
+                ]
+                .into_iter()
+                .map(Expr::Literal)
+                .collect(),
+            ))
+        };
+        let info = PlaybackInfo {
+            sample_rate: 48000,
+            current_time: 0,
+            frame_per_buffer: 1,
+            channels: ChannelLayout::Mono,
+        };
+        // both operators start at the quarter of the period, where the modulator shifts the carrier by 1 radian.
+        let mut component =
+            get_component_for_value(&value(array(vec![route(1.0, 0.0), route(0.0, -1.0)])));
+        component.prepare_play(&info);
+        let mut out = [0.0];
+        component.render(&[], &mut out, &info);
+        assert!((out[0] - 1.0f32.cos()).abs() < 1e-3);
+        // the loop in the routing plays silence instead of aborting.
+        let looped = array(vec![route(0.0, 1.0), route(1.0, 0.0), route(0.0, -1.0)]);
+        let mut component = get_component_for_value(&value(looped));
+        component.prepare_play(&info);
+        component.render(&[], &mut out, &info);
+        assert_eq!(out[0], 0.0);
+    }
+    #[test]
+    fn share_wavetable() {
+        let cycle = Value::Array(
+            vec![Value::Number(0.0), Value::Number(1.0)],
+            script::Type::Number,
+        );
+        let table = Expr::Literal(Value::Array(vec![cycle], script::Type::Unknown));
+        let a = get_wavetable(&table);
+        assert!(Arc::ptr_eq(&a, &get_wavetable(&table)));
+        // built again after all the generators are dropped.
+        drop(a);
+        assert_eq!(get_wavetable(&table).get_frames(), 1);
+        // the invalid table plays silence instead of aborting.
+        let invalid = Expr::Literal(Value::Array(
+            vec![Value::Number(1.0)],
+            script::Type::Unknown,
+        ));
+        assert_eq!(get_wavetable(&invalid).read(0.0, 0.25, 0.01), 0.0);
+    }
+}
@@ -0,0 +1,294 @@
+//! Additive synthesis from the list of the sine partials.
+//!
+//! Each partial is an array of `[ratio, amp, phase]` with an optional envelope, given as numbers computed by the
+//! script or as parameters shown in the UI. The envelope is an array of `[time, level]` breakpoints in seconds from
+//! the beginning of the generator, interpolated linearly and held after the last one.
+//!
+//! The partials are rendered by rotating the complex phasors, with their phases and levels updated once per block,
+//! so that hundreds of them can be played without calling `sin` for each sample.
+
+use crate::audio::{mixer, Component, PlaybackInfo};
+use crate::data::OscillatorParam;
+use crate::parameter::{FloatParameter, Parameter};
+use crate::script::{EvalError, Value};
+use std::f64::consts::TAU;
+use std::sync::Arc;
+
+/// Breakpoints of the level of a partial over time.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Envelope {
+    /// pairs of the time in seconds and the level, sorted by the time.
+    points: Vec<(f64, f32)>,
+}
+
+impl Envelope {
+    pub fn new(mut points: Vec<(f64, f32)>) -> Self {
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { points }
+    }
+    /// Level at `time` seconds. 1.0 if there are no points.
+    pub fn level_at(&self, time: f64) -> f32 {
+        let next = self.points.partition_point(|(t, _)| *t <= time);
+        match (
+            next.checked_sub(1).map(|i| self.points[i]),
+            self.points.get(next).copied(),
+        ) {
+            (None, None) => 1.0,
+            (None, Some((_, level))) | (Some((_, level)), None) => level,
+            (Some((t0, l0)), Some((t1, l1))) => {
+                let frac = ((time - t0) / (t1 - t0)) as f32;
+                l0 + (l1 - l0) * frac
+            }
+        }
+    }
+}
+
+impl TryFrom<&Value> for Envelope {
+    type Error = EvalError;
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        let points = match value {
+            Value::Array(points, _) => points
+                .iter()
+                .map(|p| match p {
+                    Value::Array(pair, _) => match pair.as_slice() {
+                        [time, level] => Ok((get_number(time)? as f64, get_number(level)?)),
+                        _ => Err(EvalError::InvalidNumArgs(2, pair.len())),
+                    },
+                    _ => Err(EvalError::TypeMismatch("Not a breakpoint".into())),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => return Err(EvalError::TypeMismatch("Not an envelope".into())),
+        };
+        Ok(Self::new(points))
+    }
+}
+
+fn get_number(v: &Value) -> Result<f32, EvalError> {
+    match v {
+        Value::Number(n) => Ok(*n as f32),
+        Value::Parameter(p) => Ok(p.get()),
+        _ => Err(EvalError::TypeMismatch("Not a number".into())),
+    }
+}
+/// Shares the parameter, or wraps the number computed by the script.
+fn get_parameter(v: &Value, label: &str) -> Result<Arc<FloatParameter>, EvalError> {
+    match v {
+        Value::Parameter(p) => Ok(p.clone()),
+        Value::Number(n) => Ok(Arc::new(FloatParameter::new(*n as f32, label))),
+        _ => Err(EvalError::TypeMismatch("Not a number".into())),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Partial {
+    /// frequency relative to the frequency of the generator.
+    pub ratio: Arc<FloatParameter>,
+    pub amp: Arc<FloatParameter>,
+    /// initial phase in 0..1, added to the phase of the generator.
+    pub phase: Arc<FloatParameter>,
+    pub envelope: Option<Envelope>,
+}
+
+impl TryFrom<&Value> for Partial {
+    type Error = EvalError;
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Array(values, _) => match values.as_slice() {
+                [ratio, amp, phase, envelope @ ..] if envelope.len() <= 1 => Ok(Self {
+                    ratio: get_parameter(ratio, "ratio")?,
+                    amp: get_parameter(amp, "amp")?,
+                    phase: get_parameter(phase, "phase")?,
+                    envelope: envelope.first().map(Envelope::try_from).transpose()?,
+                }),
+                _ => Err(EvalError::InvalidNumArgs(3, values.len())),
+            },
+            _ => Err(EvalError::TypeMismatch("Not a partial".into())),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Additive {
+    params: OscillatorParam,
+    partials: Vec<Partial>,
+    /// phases of the partials in 0..1, accumulated per block.
+    phases: Vec<f64>,
+    /// levels at the end of the last block, from which the next block ramps.
+    levels: Vec<f32>,
+    /// frames from the beginning of the generator.
+    time: usize,
+}
+
+impl Additive {
+    pub fn new(params: OscillatorParam, partials: Vec<Partial>) -> Self {
+        let n = partials.len();
+        Self {
+            params,
+            partials,
+            phases: vec![0.0; n],
+            levels: vec![0.0; n],
+            time: 0,
+        }
+    }
+    pub fn from_values(params: OscillatorParam, partials: &[Value]) -> Result<Self, EvalError> {
+        let partials = partials
+            .iter()
+            .map(Partial::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(params, partials))
+    }
+    pub fn get_partials(&self) -> &[Partial] {
+        &self.partials
+    }
+    /// Level of the partial at the frame, muted above the Nyquist frequency.
+    fn level(&self, partial: &Partial, time: usize, info: &PlaybackInfo) -> f32 {
+        let freq = self.params.freq.get() * partial.ratio.get();
+        if freq.abs() >= info.sample_rate as f32 * 0.5 {
+            return 0.0;
+        }
+        let envelope = partial
+            .envelope
+            .as_ref()
+            .map_or(1.0, |e| e.level_at(time as f64 / info.sample_rate as f64));
+        partial.amp.get() * envelope * self.params.amp.get()
+    }
+}
+
+impl Component for Additive {
+    fn get_input_channels(&self) -> u64 {
+        0
+    }
+    fn get_output_channels(&self) -> u64 {
+        1
+    }
+
+    fn prepare_play(&mut self, info: &PlaybackInfo) {
+        self.time = info.current_time;
+        let elapsed = info.current_time as f64 / info.sample_rate as f64;
+        let (freq, phase) = (
+            self.params.freq.get() as f64,
+            self.params.phase.get() as f64,
+        );
+        for (i, partial) in self.partials.iter().enumerate() {
+            let start = phase + partial.phase.get() as f64;
+            self.phases[i] = (start + freq * partial.ratio.get() as f64 * elapsed).rem_euclid(1.0);
+            self.levels[i] = self.level(partial, self.time, info);
+        }
+    }
+    /// Rotates the phasor of each partial by its increment per frame, ramping its level linearly from the end of the
+    /// previous block. The partials are summed into the first channel, then upmixed into the layout of the output.
+    fn render(&mut self, _input: &[f32], output: &mut [f32], info: &PlaybackInfo) {
+        let chs = info.channels.count().max(1);
+        let frames = output.len() / chs;
+        output.fill(0.0);
+        let freq = self.params.freq.get() as f64;
+        let end = self.time + frames;
+        for (i, partial) in self.partials.iter().enumerate() {
+            let increment = freq * partial.ratio.get() as f64 / info.sample_rate as f64;
+            let (from, to) = (self.levels[i], self.level(partial, end, info));
+            self.levels[i] = to;
+            if from != 0.0 || to != 0.0 {
+                let (c, s) = ((self.phases[i] * TAU).cos(), (self.phases[i] * TAU).sin());
+                let (wc, ws) = ((increment * TAU).cos(), (increment * TAU).sin());
+                let (mut re, mut im) = (c as f32, s as f32);
+                let (wc, ws) = (wc as f32, ws as f32);
+                let step = (to - from) / frames.max(1) as f32;
+                let mut level = from;
+                for frame in output.chunks_exact_mut(chs) {
+                    frame[0] += im * level;
+                    (re, im) = (re * wc - im * ws, re * ws + im * wc);
+                    level += step;
+                }
+            }
+            self.phases[i] = (self.phases[i] + increment * frames as f64).rem_euclid(1.0);
+        }
+        self.time = end;
+        for frame in output.chunks_exact_mut(chs) {
+            let s = frame[0];
+            frame.fill(s);
+        }
+        mixer::upmix_mono(output, info.channels);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio::fixture::{mono_info, osc_param, SR};
+    use crate::script::Type;
+
+    fn partial(ratio: f64, amp: f64, phase: f64, envelope: Option<Value>) -> Value {
+        let values = [ratio, amp, phase].map(Value::Number);
+        Value::Array(values.into_iter().chain(envelope).collect(), Type::Number)
+    }
+    fn render(additive: &mut Additive, from: usize, len: usize) -> Vec<f32> {
+        let block = 256;
+        additive.prepare_play(&mono_info(from, block));
+        let mut out = vec![0.0; len];
+        for (i, chunk) in out.chunks_mut(block).enumerate() {
+            additive.render(&[], chunk, &mono_info(from + i * block, block));
+        }
+        out
+    }
+
+    #[test]
+    fn sum_of_partials() {
+        let partials = (1..=300)
+            .map(|k| partial(k as f64, 1.0 / k as f64, 0.25, None))
+            .collect::<Vec<_>>();
+        let mut additive = Additive::from_values(osc_param(50.0), &partials)
+            .ok()
+            .unwrap();
+        let out = render(&mut additive, 0, SR / 10);
+        for (t, s) in out.iter().enumerate().step_by(97) {
+            let expected = (1..=300)
+                .filter(|k| (k * 50) < SR / 2)
+                .map(|k| {
+                    let phase = 0.25 + (k * 50 * t) as f64 / SR as f64;
+                    ((phase * TAU).sin() / k as f64) as f32
+                })
+                .sum::<f32>();
+            assert!(
+                (s - expected).abs() < 1e-3,
+                "{}: {} expected {}",
+                t,
+                s,
+                expected
+            );
+        }
+        // starting from the middle renders the same samples.
+        let from_middle = render(&mut additive, 1000, 1000);
+        for (a, b) in out[1000..2000].iter().zip(from_middle.iter()) {
+            assert!((a - b).abs() < 1e-3);
+        }
+    }
+    #[test]
+    fn envelope_of_partial() {
+        let breakpoint =
+            |t: f64, l: f64| Value::Array(vec![Value::Number(t), Value::Number(l)], Type::Number);
+        let envelope = Value::Array(
+            vec![
+                breakpoint(0.0, 0.0),
+                breakpoint(0.5, 1.0),
+                breakpoint(1.0, 0.0),
+            ],
+            Type::Unknown,
+        );
+        let env = Envelope::try_from(&envelope).ok().unwrap();
+        assert_eq!(env.level_at(0.25), 0.5);
+        assert_eq!(env.level_at(2.0), 0.0);
+        assert_eq!(Envelope::default().level_at(1.0), 1.0);
+        let mut additive =
+            Additive::from_values(osc_param(1000.0), &[partial(1.0, 1.0, 0.0, Some(envelope))])
+                .ok()
+                .unwrap();
+        let out = render(&mut additive, 0, SR);
+        let peak = |range: std::ops::Range<usize>| {
+            out[range].iter().fold(0.0f32, |acc, s| acc.max(s.abs()))
+        };
+        assert!(peak(0..48) < 0.01);
+        assert!((peak(SR / 2 - 48..SR / 2 + 48) - 1.0).abs() < 0.01);
+        assert!((peak(SR / 4 - 48..SR / 4 + 48) - 0.5).abs() < 0.01);
+        assert!(Partial::try_from(&Value::Number(1.0)).is_err());
+    }
+}
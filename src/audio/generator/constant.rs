@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use super::Component;
-use crate::audio::PlaybackInfo;
+use crate::audio::{mixer, PlaybackInfo};
 use crate::parameter::{FloatParameter, Parameter};
 #[derive(Clone, Debug)]
 pub struct Constant(pub Arc<FloatParameter>);
@@ -10,11 +10,12 @@ impl Component for Constant {
         0
     }
     fn get_output_channels(&self) -> u64 {
-        2
+        1
     }
 
     fn prepare_play(&mut self, _info: &PlaybackInfo) {}
-    fn render(&mut self, _input: &[f32], output: &mut [f32], _info: &PlaybackInfo) {
+    fn render(&mut self, _input: &[f32], output: &mut [f32], info: &PlaybackInfo) {
         output.fill(self.0.get());
+        mixer::upmix_mono(output, info.channels);
     }
 }
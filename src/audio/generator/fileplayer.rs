@@ -1,12 +1,14 @@
 use crate::app::filemanager::{self, FileManager};
+use crate::audio::mixer::Mixer;
 use crate::audio::region::RangedComponent;
+use crate::audio::resampler::Resampler;
 use crate::audio::{Component, PlaybackInfo};
-use crate::data::FilePlayerParam;
+use crate::data::{ChannelLayout, FilePlayerParam};
 use crate::parameter::Parameter;
 use std::io::ErrorKind;
 use std::sync::Arc;
 
-use symphonia::core::audio::{Layout, SampleBuffer, SignalSpec};
+use symphonia::core::audio::{Channels, SampleBuffer, SignalSpec};
 use symphonia::core::codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL};
 use symphonia::core::errors::Error;
 use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
@@ -21,7 +23,20 @@ pub struct FilePlayer {
     track_id: u32,
     format: Box<dyn FormatReader>,
     audiobuffer: SampleBuffer<f32>,
+    /// frames of the file at its own rate.
     ringbuf: ringbuf::HeapRb<f32>,
+    file_rate: Option<u32>,
+    file_channels: usize,
+    /// set while the file rate differs from the rate it is played at.
+    resampler: Option<Resampler>,
+    /// frames read from the file before resampling.
+    resample_buf: Vec<f32>,
+    /// from the channels of the file into the layout it is played in.
+    mixer: Mixer,
+    /// frames in the channels of the file, used while the mixer is not an identity.
+    mix_buf: Vec<f32>,
+    /// frames to be discarded after seeking to reach the exact position.
+    skip_frames: usize,
     is_finished_playing: bool,
 }
 
@@ -56,19 +71,51 @@ fn get_default_decoder(path: impl ToString) -> Result<DecoderSet, Box<dyn std::e
     Ok((decoder, probed, id))
 }
 
+/// Decodes the whole file into the mono samples averaged over the channels, at the rate of the file.
+pub fn read_mono(path: impl ToString) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+    let (mut decoder, mut probed, track_id) = get_default_decoder(path)?;
+    let mut res = vec![];
+    loop {
+        let packet = match probed.format.next_packet() {
+            Ok(packet) => packet,
+            Err(Error::IoError(err)) if err.kind() == ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = decoder.decode(&packet)?;
+        let chs = decoded.spec().channels.count().max(1);
+        let mut buf = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+        buf.copy_interleaved_ref(decoded);
+        res.extend(
+            buf.samples()
+                .chunks_exact(chs)
+                .map(|frame| frame.iter().sum::<f32>() / chs as f32),
+        );
+    }
+    Ok(res)
+}
+
 impl FilePlayer {
     pub fn new(param: Arc<FilePlayerParam>) -> Self {
         let buf_len = MediaSourceStreamOptions::default().buffer_len;
         let (decoder, probed, track_id) =
             get_default_decoder(param.path.clone()).expect("decoder not found");
 
-        let max_frames = decoder.codec_params().max_frames_per_packet.unwrap();
+        let codec_params = decoder.codec_params();
+        let max_frames = codec_params.max_frames_per_packet.unwrap();
+        let file_rate = codec_params.sample_rate;
+        let channels = codec_params
+            .channels
+            .unwrap_or(Channels::FRONT_LEFT | Channels::FRONT_RIGHT);
         let audiobuffer = SampleBuffer::<f32>::new(
             max_frames,
-            SignalSpec::new_with_layout(48000, Layout::Stereo),
+            SignalSpec::new(file_rate.unwrap_or(48000), channels),
         );
 
         let ringbuf = ringbuf::HeapRb::new(buf_len);
+        let file_layout = ChannelLayout::from_count(channels.count());
         Self {
             param,
             decoder,
@@ -76,6 +123,13 @@ impl FilePlayer {
             format: probed.format,
             audiobuffer,
             ringbuf,
+            file_rate,
+            file_channels: channels.count(),
+            resampler: None,
+            resample_buf: vec![],
+            mixer: Mixer::new(file_layout, file_layout),
+            mix_buf: vec![],
+            skip_frames: 0,
             is_finished_playing: false,
         }
     }
@@ -101,33 +155,98 @@ impl Component for FilePlayer {
     }
 
     fn get_output_channels(&self) -> u64 {
-        self.get_channels()
+        self.file_channels as u64
     }
 
-    fn prepare_play(&mut self, _info: &crate::audio::PlaybackInfo) {
-        let start_sec = self.param.start_sec.get();
-        let time_sec = start_sec.floor() as u64;
-        let time_frac = start_sec.fract() as f64;
-        self.format
+    fn prepare_play(&mut self, info: &crate::audio::PlaybackInfo) {
+        let file_sr = self.file_rate.unwrap_or(info.sample_rate);
+        let pos_sec =
+            self.param.start_sec.get() as f64 + info.current_time as f64 / info.sample_rate as f64;
+        let time = Time::new(pos_sec.floor() as u64, pos_sec.fract());
+        let seeked = self
+            .format
             .seek(
                 SeekMode::Accurate,
                 SeekTo::Time {
-                    time: Time::new(time_sec, time_frac),
-                    track_id: Some(0),
+                    time,
+                    track_id: Some(self.track_id),
                 },
             )
-            .unwrap_or_else(|_| panic!("failed to seek position {}", start_sec));
+            .unwrap_or_else(|_| panic!("failed to seek position {}", pos_sec));
+        // the format reader may seek to the earlier packet boundary. skip the samples until the required position.
+        let skip_ts = seeked.required_ts.saturating_sub(seeked.actual_ts);
+        self.skip_frames = match self.decoder.codec_params().time_base {
+            Some(tb) => {
+                let t = tb.calc_time(skip_ts);
+                ((t.seconds as f64 + t.frac) * file_sr as f64).round() as usize
+            }
+            None => skip_ts as usize,
+        };
+        self.decoder.reset();
+        self.ringbuf.split_ref().1.clear();
         self.is_finished_playing = false;
+        // the file of the different rate is resampled into the rate it is played at.
+        let chs = self.file_channels;
+        let block = info.frame_per_buffer as usize;
+        self.resampler = (file_sr != info.sample_rate).then(|| match self.resampler.take() {
+            Some(mut rs) if rs.get_channels() == chs => {
+                rs.reset();
+                rs
+            }
+            _ => Resampler::new(file_sr, info.sample_rate, chs, block),
+        });
+        if let Some(rs) = &self.resampler {
+            self.resample_buf.resize(rs.frames_needed(block) * chs, 0.0);
+        }
+        let file_layout = ChannelLayout::from_count(chs);
+        if self.mixer.get_from() != file_layout || self.mixer.get_to() != info.channels {
+            self.mixer = Mixer::new(file_layout, info.channels);
+        }
+        self.mix_buf.resize(block * chs, 0.0);
+    }
+
+    fn render(&mut self, _input: &[f32], output: &mut [f32], info: &crate::audio::PlaybackInfo) {
+        if self.mixer.is_identity() {
+            self.render_file(output);
+            return;
+        }
+        let frames = output.len() / info.channels.count().max(1);
+        let mut mix_buf = std::mem::take(&mut self.mix_buf);
+        let len = frames * self.file_channels;
+        if mix_buf.len() < len {
+            mix_buf.resize(len, 0.0);
+        }
+        self.render_file(&mut mix_buf[..len]);
+        self.mixer.process(&mix_buf[..len], output);
+        self.mix_buf = mix_buf;
     }
+}
 
-    fn render(&mut self, _input: &[f32], output: &mut [f32], _info: &crate::audio::PlaybackInfo) {
+impl FilePlayer {
+    /// Reads the frames in the channels of the file.
+    fn render_file(&mut self, output: &mut [f32]) {
         output.fill(0.0);
+        let chs = self.file_channels.max(1);
+        // the frames of the file needed for the output.
+        let target = match self.resampler.as_ref() {
+            Some(rs) => {
+                let len = rs.frames_needed(output.len() / chs) * chs;
+                if self.resample_buf.len() < len {
+                    self.resample_buf.resize(len, 0.0);
+                }
+                let buf = &mut self.resample_buf[..len];
+                buf.fill(0.0);
+                buf
+            }
+            None => &mut *output,
+        };
+        let target_len = target.len();
         // Get the next packet from the media format.
         let (mut prod, mut cons) = self.ringbuf.split_ref();
         let mut read_count = 0;
         let mut finished_loop = false;
         while !finished_loop {
-            let reached_eof = if cons.len() < output.len() {
+            let reached_eof = if cons.len() < target.len() {
                 match self.format.next_packet() {
                     Ok(packet) => {
                         // Consume any new metadata that has been read since the last packet.
@@ -145,8 +264,12 @@ impl Component for FilePlayer {
                         // Decode the packet into audio samples.
                         let res = self.decoder.decode(&packet).map(|decoded| {
                             // Consume the decoded audio samples (see below)
+                            let chs = decoded.spec().channels.count();
                             self.audiobuffer.copy_interleaved_ref(decoded.clone());
-                            let _nsamples = prod.push_slice(self.audiobuffer.samples());
+                            let samples = self.audiobuffer.samples();
+                            let skip = (self.skip_frames * chs).min(samples.len());
+                            self.skip_frames -= skip / chs;
+                            let _nsamples = prod.push_slice(&samples[skip..]);
                             // println!(
                             //     "frames:{}, timestamp:{}, n_samples: {}",
                             //     decoded.frames(),
@@ -172,15 +295,18 @@ impl Component for FilePlayer {
             };
             self.is_finished_playing = reached_eof.map_or(false, |res| res);
 
-            let read_len = cons.len().min(output.len());
+            let read_len = cons.len().min(target.len());
             // dbg!(read_count, cons.len(),output.len());
-            let next_read = (read_count + read_len).min(output.len());
+            let next_read = (read_count + read_len).min(target.len());
 
-            let output_buf = &mut output[read_count..next_read];
+            let output_buf = &mut target[read_count..next_read];
             read_count += read_len;
             cons.pop_slice(output_buf);
 
-            finished_loop = self.is_finished_playing || read_count > output.len() - 1;
+            finished_loop = self.is_finished_playing || read_count > target.len() - 1;
+        }
+        if let Some(rs) = self.resampler.as_mut() {
+            rs.process(&self.resample_buf[..target_len], output);
         }
     }
 }
@@ -192,14 +318,14 @@ impl RangedComponent for FilePlayer {
     }
 
     fn get_output_channels(&self) -> u64 {
-        self.param.channels.get()
+        self.file_channels as u64
     }
 
-    fn render_offline(&mut self, dest: &mut [f32], sample_rate: u32, channels: u64) {
+    fn render_offline(&mut self, dest: &mut [f32], sample_rate: u32, channels: ChannelLayout) {
         let info = PlaybackInfo {
             sample_rate,
             current_time: 0,
-            frame_per_buffer: dest.len() as u64 / channels,
+            frame_per_buffer: (dest.len() / channels.count()) as u64,
             channels,
         };
         self.prepare_play(&info);
@@ -221,7 +347,7 @@ mod test {
             sample_rate: 48000,
             current_time: 0,
             frame_per_buffer: 256,
-            channels: 2,
+            channels: ChannelLayout::Stereo,
         };
         (player, info, len_samples)
     }
@@ -260,4 +386,44 @@ mod test {
         player.render(&input_buf, output_buf.as_mut_slice(), &info);
         assert!(player.is_finished_playing());
     }
+    #[test]
+    fn resume_from_middle() {
+        let (mut player, mut info, _len_samples) = read_prep();
+        let (offset, frames) = (12345, 4096);
+        player.prepare_play(&info);
+        let mut answer = vec![0.0f32; (offset + frames) * 2];
+        let input_buf = vec![0.0f32; 1];
+        player.render(&input_buf, answer.as_mut_slice(), &info);
+
+        let (mut player, ..) = read_prep();
+        info.current_time = offset;
+        player.prepare_play(&info);
+        let mut output_buf = vec![0.0f32; frames * 2];
+        player.render(&input_buf, output_buf.as_mut_slice(), &info);
+        assert_eq!(output_buf.as_slice(), &answer[offset * 2..]);
+    }
+    #[test]
+    fn resample_to_playback_rate() {
+        let (mut player, mut info, len_samples) = read_prep();
+        info.sample_rate = 44100;
+        player.prepare_play(&info);
+        let frames = 512;
+        let mut output_buf = vec![0.0f32; frames * 2];
+        let input_buf = vec![0.0f32; 1];
+        let mut rendered = 0;
+        while !player.is_finished_playing() {
+            player.render(&input_buf, output_buf.as_mut_slice(), &info);
+            info.current_time += frames;
+            rendered += frames;
+        }
+        // the file of 48000Hz ends earlier in the frames of 44100Hz.
+        let expected = len_samples * 44100 / 48000;
+        assert!(
+            rendered.abs_diff(expected) <= frames,
+            "{} {}",
+            rendered,
+            expected
+        );
+        assert!(output_buf.iter().all(|s| s.is_finite()));
+    }
 }
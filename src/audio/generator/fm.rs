@@ -0,0 +1,454 @@
+//! Generators of the sine operators modulating each other, and the amplitude and ring modulation.
+//!
+//! The operators and their routing are given as script values so that they are saved in the project:
+//! each operator is an array of `[ratio, index]` parameters, and each route is an array of `[from, to]` numbers where
+//! `to` is -1 for the output. A route from an operator to itself is the feedback of its previous sample.
+
+use super::GeneratorComponent;
+use crate::audio::PlaybackInfo;
+use crate::data::OscillatorParam;
+use crate::parameter::{FloatParameter, Parameter};
+use crate::script::{EvalError, Value};
+use std::f32::consts::TAU;
+use std::sync::Arc;
+
+#[derive(Clone, Debug)]
+pub struct Operator {
+    /// frequency relative to the frequency of the generator.
+    pub ratio: Arc<FloatParameter>,
+    /// modulation index of the operators it modulates: the peak deviation of their phase in radians, or of their
+    /// frequency relative to the frequency of this operator.
+    pub index: Arc<FloatParameter>,
+}
+
+impl Operator {
+    pub fn new(ratio: Arc<FloatParameter>, index: Arc<FloatParameter>) -> Self {
+        Self { ratio, index }
+    }
+}
+
+impl TryFrom<&Value> for Operator {
+    type Error = EvalError;
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Array(params, _) => match params.as_slice() {
+                [Value::Parameter(ratio), Value::Parameter(index)] => {
+                    Ok(Self::new(ratio.clone(), index.clone()))
+                }
+                _ => Err(EvalError::InvalidNumArgs(2, params.len())),
+            },
+            _ => Err(EvalError::TypeMismatch("Not an operator".into())),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Route {
+    pub from: usize,
+    /// `None` for the output.
+    pub to: Option<usize>,
+}
+
+impl TryFrom<&Value> for Route {
+    type Error = EvalError;
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Array(ends, _) => match ends.as_slice() {
+                [Value::Number(from), Value::Number(to)] if *from >= 0.0 => Ok(Self {
+                    from: *from as usize,
+                    to: (*to >= 0.0).then_some(*to as usize),
+                }),
+                _ => Err(EvalError::TypeMismatch("Not a route".into())),
+            },
+            _ => Err(EvalError::TypeMismatch("Not a route".into())),
+        }
+    }
+}
+
+/// How the modulators change the operators.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Modulation {
+    /// adds to the phase, which keeps the pitch for any index.
+    Phase,
+    /// adds to the frequency.
+    Frequency,
+}
+
+/// Operators rendered in the order of the routing.
+#[derive(Clone, Debug)]
+pub struct OperatorGraph {
+    params: OscillatorParam,
+    modulation: Modulation,
+    operators: Vec<Operator>,
+    /// modulators of each operator.
+    modulators: Vec<Vec<usize>>,
+    carriers: Vec<usize>,
+    /// the modulators come before the operators they modulate.
+    order: Vec<usize>,
+    phases: Vec<f64>,
+    outputs: Vec<f32>,
+}
+
+impl OperatorGraph {
+    /// Fails when a route points to a missing operator, or the routes other than the feedback make a loop.
+    pub fn new(
+        params: OscillatorParam,
+        modulation: Modulation,
+        operators: Vec<Operator>,
+        routes: &[Route],
+    ) -> Result<Self, EvalError> {
+        let n = operators.len();
+        let mut modulators = vec![vec![]; n];
+        let mut carriers = vec![];
+        for route in routes {
+            match route.to {
+                _ if route.from >= n => return Err(EvalError::NotFound),
+                Some(to) if to >= n => return Err(EvalError::NotFound),
+                Some(to) => modulators[to].push(route.from),
+                None => carriers.push(route.from),
+            }
+        }
+        // depth first search from the carriers, so that the operators not reaching the output are not rendered.
+        fn visit(
+            op: usize,
+            modulators: &[Vec<usize>],
+            visiting: &mut [bool],
+            order: &mut Vec<usize>,
+        ) -> Result<(), EvalError> {
+            if order.contains(&op) {
+                return Ok(());
+            }
+            if visiting[op] {
+                return Err(EvalError::TypeMismatch("Loop in the routing".into()));
+            }
+            visiting[op] = true;
+            for m in modulators[op].iter().filter(|m| **m != op) {
+                visit(*m, modulators, visiting, order)?;
+            }
+            visiting[op] = false;
+            order.push(op);
+            Ok(())
+        }
+        let mut order = Vec::with_capacity(n);
+        let mut visiting = vec![false; n];
+        for c in carriers.iter() {
+            visit(*c, &modulators, &mut visiting, &mut order)?;
+        }
+        Ok(Self {
+            params,
+            modulation,
+            operators,
+            modulators,
+            carriers,
+            order,
+            phases: vec![0.0; n],
+            outputs: vec![0.0; n],
+        })
+    }
+    pub fn from_values(
+        params: OscillatorParam,
+        modulation: Modulation,
+        operators: &[Value],
+        routes: &[Value],
+    ) -> Result<Self, EvalError> {
+        let operators = operators
+            .iter()
+            .map(Operator::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        let routes = routes
+            .iter()
+            .map(Route::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(params, modulation, operators, &routes)
+    }
+}
+
+impl GeneratorComponent for OperatorGraph {
+    type Params = OscillatorParam;
+
+    fn get_params(&self) -> &Self::Params {
+        &self.params
+    }
+    fn reset_phase(&mut self) {
+        self.phases.fill(self.params.phase.get() as f64);
+        self.outputs.fill(0.0);
+    }
+    /// Sets the phases where they would be after `time` samples with the parameters held.
+    /// The deviation of the frequency is summed in closed form from the modulators not modulated themselves, and
+    /// the one from the other modulators is left out.
+    fn seek(&mut self, time: usize, info: &PlaybackInfo) {
+        self.reset_phase();
+        let freq = self.params.freq.get();
+        let sr = info.sample_rate as f32;
+        let increment = |op: usize| (freq * self.operators[op].ratio.get() / sr) as f64;
+        let start = self.phases.clone();
+        for &op in self.order.iter() {
+            let mut phase = start[op] + increment(op) * time as f64;
+            if self.modulation == Modulation::Frequency {
+                for &m in self.modulators[op].iter() {
+                    if self.modulators[m].is_empty() {
+                        let sum = sum_of_sines(start[m], increment(m), time);
+                        phase += self.operators[m].index.get() as f64 * increment(m) * sum;
+                    }
+                }
+            }
+            self.phases[op] = phase.rem_euclid(1.0);
+        }
+    }
+
+    fn render_sample(&mut self, out: &mut f32, info: &PlaybackInfo) {
+        let freq = self.params.freq.get();
+        let sr = info.sample_rate as f32;
+        for &op in self.order.iter() {
+            // feedback reads the previous output of the operator itself.
+            let deviation = |scale: &dyn Fn(&Operator) -> f32| {
+                self.modulators[op]
+                    .iter()
+                    .map(|m| {
+                        let modulator = &self.operators[*m];
+                        modulator.index.get() * scale(modulator) * self.outputs[*m]
+                    })
+                    .sum::<f32>()
+            };
+            let op_freq = freq * self.operators[op].ratio.get();
+            let phase = self.phases[op] as f32 * TAU;
+            let (output, increment) = match self.modulation {
+                Modulation::Phase => ((phase + deviation(&|_| 1.0)).sin(), op_freq / sr),
+                Modulation::Frequency => (
+                    phase.sin(),
+                    (op_freq + deviation(&|m| freq * m.ratio.get())) / sr,
+                ),
+            };
+            self.outputs[op] = output;
+            self.phases[op] = (self.phases[op] + increment as f64).rem_euclid(1.0);
+        }
+        let sum = self.carriers.iter().map(|c| self.outputs[*c]).sum::<f32>();
+        *out = sum / self.carriers.len().max(1) as f32 * self.params.amp.get();
+    }
+}
+
+/// Sum of `sin(2π(phase + increment * n))` over `n` in `0..len`.
+fn sum_of_sines(phase: f64, increment: f64, len: usize) -> f64 {
+    use std::f64::consts::PI;
+    let len = len as f64;
+    let half = (PI * increment).sin();
+    if half.abs() < 1e-12 {
+        return len * (phase * std::f64::consts::TAU).sin();
+    }
+    // the turns are wrapped before multiplying with π so that the long sums keep the precision.
+    let turns = |x: f64| x.rem_euclid(2.0) * PI;
+    turns(len * increment).sin() / half * turns(2.0 * phase + (len - 1.0) * increment).sin()
+}
+
+/// Sine carrier multiplied by a sine modulator at `ratio` of its frequency.
+/// `depth` of 0 is the plain carrier, and 1 is the ring modulation without the carrier.
+#[derive(Clone, Debug)]
+pub struct AmplitudeModulation {
+    params: OscillatorParam,
+    ratio: Arc<FloatParameter>,
+    depth: Arc<FloatParameter>,
+    carrier_phase: f64,
+    modulator_phase: f64,
+}
+
+impl AmplitudeModulation {
+    pub fn new(
+        params: OscillatorParam,
+        ratio: Arc<FloatParameter>,
+        depth: Arc<FloatParameter>,
+    ) -> Self {
+        let carrier_phase = params.phase.get() as f64;
+        Self {
+            params,
+            ratio,
+            depth,
+            carrier_phase,
+            modulator_phase: 0.0,
+        }
+    }
+}
+
+impl GeneratorComponent for AmplitudeModulation {
+    type Params = OscillatorParam;
+
+    fn get_params(&self) -> &Self::Params {
+        &self.params
+    }
+    fn reset_phase(&mut self) {
+        self.carrier_phase = self.params.phase.get() as f64;
+        self.modulator_phase = 0.0;
+    }
+    fn seek(&mut self, time: usize, info: &PlaybackInfo) {
+        let elapsed = time as f64 / info.sample_rate as f64;
+        let freq = self.params.freq.get() as f64;
+        self.carrier_phase = (self.params.phase.get() as f64 + freq * elapsed).rem_euclid(1.0);
+        self.modulator_phase = (freq * self.ratio.get() as f64 * elapsed).rem_euclid(1.0);
+    }
+
+    fn render_sample(&mut self, out: &mut f32, info: &PlaybackInfo) {
+        let freq = self.params.freq.get() as f64;
+        let sr = info.sample_rate as f64;
+        let depth = self.depth.get().clamp(0.0, 1.0);
+        let carrier = (self.carrier_phase as f32 * TAU).sin();
+        let modulator = (self.modulator_phase as f32 * TAU).sin();
+        // keeps the peak of the carrier while the depth moves towards the ring modulation.
+        let gain = 1.0 - depth + depth * modulator;
+        *out = carrier * gain * self.params.amp.get();
+        self.carrier_phase = (self.carrier_phase + freq / sr).rem_euclid(1.0);
+        self.modulator_phase =
+            (self.modulator_phase + freq * self.ratio.get() as f64 / sr).rem_euclid(1.0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio::fixture::{amplitude, osc_param, param, render_samples, SR};
+
+    fn render(mut generator: impl GeneratorComponent) -> Vec<f32> {
+        render_samples(&mut generator, 0, SR)
+    }
+    /// Samples after `from`, rendered from the beginning and by seeking there.
+    fn render_after(
+        mut generator: impl GeneratorComponent + Clone,
+        from: usize,
+    ) -> (Vec<f32>, Vec<f32>) {
+        let rendered = render_samples(&mut generator.clone(), 0, from + 512);
+        (
+            rendered[from..].to_vec(),
+            render_samples(&mut generator, from, 512),
+        )
+    }
+    /// A carrier at 1000Hz modulated by 100Hz with the index of 1.
+    fn two_operators(modulation: Modulation) -> OperatorGraph {
+        let operators = vec![
+            Operator::new(param(1.0, "ratio"), param(0.0, "index")),
+            Operator::new(param(0.1, "ratio"), param(1.0, "index")),
+        ];
+        let routes = [
+            Route {
+                from: 1,
+                to: Some(0),
+            },
+            Route { from: 0, to: None },
+        ];
+        OperatorGraph::new(osc_param(1000.0), modulation, operators, &routes)
+            .ok()
+            .unwrap()
+    }
+
+    #[test]
+    fn bessel_sidebands() {
+        // J0(1), J1(1), J2(1), J3(1)
+        let bessel = [0.7652, 0.4401, 0.1149, 0.0196];
+        for modulation in [Modulation::Phase, Modulation::Frequency] {
+            let samples = render(two_operators(modulation));
+            for (n, j) in bessel.iter().enumerate() {
+                for freq in [1000 + n * 100, 1000 - n * 100] {
+                    let amp = amplitude(&samples, freq as f64);
+                    assert!(
+                        (amp - j).abs() < 5e-3,
+                        "{:?} {}Hz: {}",
+                        modulation,
+                        freq,
+                        amp
+                    );
+                }
+            }
+        }
+    }
+    #[test]
+    fn seek_without_rendering() {
+        let from = SR * 10 + 123;
+        let am =
+            AmplitudeModulation::new(osc_param(440.0), param(0.3, "ratio"), param(0.5, "depth"));
+        let pairs = [
+            render_after(two_operators(Modulation::Phase), from),
+            render_after(two_operators(Modulation::Frequency), from),
+            render_after(am, from),
+        ];
+        // the frequency modulation drifts by the rounding of the increments summed for every sample.
+        for (rendered, seeked) in pairs {
+            let error = rendered
+                .iter()
+                .zip(seeked.iter())
+                .fold(0.0f32, |acc, (a, b)| acc.max((a - b).abs()));
+            assert!(error < 1e-2, "{}", error);
+        }
+    }
+    #[test]
+    fn routing() {
+        let op = |ratio| Operator::new(param(ratio, "ratio"), param(1.0, "index"));
+        let params = osc_param(100.0);
+        // feedback is allowed, and the operators not reaching the output are skipped.
+        let routes = [
+            Route {
+                from: 0,
+                to: Some(0),
+            },
+            Route { from: 0, to: None },
+            Route {
+                from: 2,
+                to: Some(1),
+            },
+        ];
+        let graph = OperatorGraph::new(
+            params.clone(),
+            Modulation::Phase,
+            vec![op(1.0), op(2.0), op(3.0)],
+            &routes,
+        )
+        .ok()
+        .unwrap();
+        assert_eq!(graph.order, vec![0]);
+        // a loop through the other operators is rejected.
+        let looped = [
+            Route {
+                from: 0,
+                to: Some(1),
+            },
+            Route {
+                from: 1,
+                to: Some(0),
+            },
+            Route { from: 0, to: None },
+        ];
+        assert!(OperatorGraph::new(
+            params.clone(),
+            Modulation::Phase,
+            vec![op(1.0), op(2.0)],
+            &looped
+        )
+        .is_err());
+        let missing = [Route { from: 3, to: None }];
+        assert!(
+            OperatorGraph::new(params.clone(), Modulation::Phase, vec![op(1.0)], &missing).is_err()
+        );
+        // two carriers are mixed at the half.
+        let parallel = [Route { from: 0, to: None }, Route { from: 1, to: None }];
+        let graph =
+            OperatorGraph::new(params, Modulation::Phase, vec![op(1.0), op(1.0)], &parallel)
+                .ok()
+                .unwrap();
+        let samples = render(graph);
+        assert!((amplitude(&samples, 100.0) - 1.0).abs() < 1e-3);
+    }
+    #[test]
+    fn ring_and_amplitude_modulation() {
+        let ring = render(AmplitudeModulation::new(
+            osc_param(1000.0),
+            param(0.2, "ratio"),
+            param(1.0, "depth"),
+        ));
+        assert!(amplitude(&ring, 1000.0) < 1e-3);
+        assert!((amplitude(&ring, 800.0) - 0.5).abs() < 1e-3);
+        assert!((amplitude(&ring, 1200.0) - 0.5).abs() < 1e-3);
+        let am = render(AmplitudeModulation::new(
+            osc_param(1000.0),
+            param(0.2, "ratio"),
+            param(0.5, "depth"),
+        ));
+        assert!((amplitude(&am, 1000.0) - 0.5).abs() < 1e-3);
+        assert!((amplitude(&am, 1200.0) - 0.25).abs() < 1e-3);
+    }
+}
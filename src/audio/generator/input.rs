@@ -0,0 +1,75 @@
+//! Generator passing the input from the audio device through.
+
+use crate::audio::{mixer, Component, PlaybackInfo};
+use crate::parameter::{FloatParameter, Parameter};
+use crate::script::{Expr, Value};
+use std::sync::Arc;
+
+/// Picks two channels of the device input into the stereo output.
+/// Channels are counted from 0, and the channels the device does not have are silent.
+#[derive(Clone, Debug)]
+pub struct LiveInput {
+    pub left: Arc<FloatParameter>,
+    pub right: Arc<FloatParameter>,
+}
+
+impl Component for LiveInput {
+    fn get_input_channels(&self) -> u64 {
+        2
+    }
+    fn get_output_channels(&self) -> u64 {
+        2
+    }
+    fn prepare_play(&mut self, _info: &PlaybackInfo) {}
+    /// The picked pair is mixed as stereo into the layout of the output.
+    fn render(&mut self, input: &[f32], output: &mut [f32], info: &PlaybackInfo) {
+        let frames = output.len() / info.channels.count().max(1);
+        // the number of channels of the device is not known by the components.
+        let in_channels = input.len().checked_div(frames).unwrap_or(0);
+        let channel = |p: &FloatParameter| p.get().max(0.0).round() as usize;
+        let (left, right) = (channel(&self.left), channel(&self.right));
+        mixer::upmix_stereo(output, info.channels, |i| {
+            let frame = input.get(i * in_channels..(i + 1) * in_channels);
+            let sample = |ch: usize| frame.and_then(|f| f.get(ch)).copied().unwrap_or(0.0);
+            (sample(left), sample(right))
+        });
+    }
+}
+
+/// Whether the generator value takes the live input, which cannot be rendered ahead of time.
+pub fn is_live(generator: &Value) -> bool {
+    matches!(
+        generator,
+        Value::Closure(_, _, box Expr::App(box Expr::Literal(Value::ExtFunction(fname)), _))
+            if fname == "input"
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::ChannelLayout;
+    use crate::parameter::RangedNumeric;
+
+    #[test]
+    fn pick_channels() {
+        let mut input = LiveInput {
+            left: Arc::new(FloatParameter::new(2.0, "left").set_range(0.0..=15.0)),
+            right: Arc::new(FloatParameter::new(0.0, "right").set_range(0.0..=15.0)),
+        };
+        let info = PlaybackInfo {
+            sample_rate: 1000,
+            current_time: 0,
+            frame_per_buffer: 2,
+            channels: ChannelLayout::Stereo,
+        };
+        // 3 channels device.
+        let device = [0.0, 0.1, 0.2, 1.0, 1.1, 1.2];
+        let mut output = [0.0; 4];
+        input.render(&device, &mut output, &info);
+        assert_eq!(output, [0.2, 0.0, 1.2, 1.0]);
+        input.left.set(5.0);
+        input.render(&device, &mut output, &info);
+        assert_eq!(output, [0.0, 0.0, 0.0, 1.0]);
+    }
+}
@@ -1,22 +1,285 @@
-use super::Component;
+use super::GeneratorComponent;
 use crate::audio::PlaybackInfo;
+use crate::data::{NoiseColor, NoiseParam};
+use crate::parameter::Parameter;
 
+/// Samples rendered through the filters after a seek, long enough for the slowest pole of the pink noise to decay.
+const SETTLE_FRAMES: usize = 1 << 14;
+
+/// Pseudo random number generator (xorshift64*) to render the same noise for the same seed on every platform.
 #[derive(Clone, Debug)]
-pub struct Noise {}
-impl Component for Noise {
-    fn get_input_channels(&self) -> u64 {
-        0
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // scramble the seed with splitmix64 so that the neighbouring seeds start from unrelated states.
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        // the state must not be zero.
+        Self(z.max(1))
+    }
+    fn shift(mut x: u64) -> u64 {
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^ (x >> 27)
+    }
+    fn next_u64(&mut self) -> u64 {
+        self.0 = Self::shift(self.0);
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+    /// Advances the state by `n` values at once.
+    /// The shifts are linear over the bits, so the steps are squared as 64x64 bit matrices, each column being the
+    /// image of a bit.
+    fn skip(&mut self, mut n: u64) {
+        let apply = |m: &[u64; 64], x: u64| {
+            (0..64)
+                .filter(|i| (x >> i) & 1 == 1)
+                .fold(0, |acc, i| acc ^ m[i])
+        };
+        let mut step: [u64; 64] = std::array::from_fn(|i| Self::shift(1 << i));
+        while n > 0 {
+            if n & 1 == 1 {
+                self.0 = apply(&step, self.0);
+            }
+            step = step.map(|col| apply(&step, col));
+            n >>= 1;
+        }
+    }
+    /// Uniform in 0..1.
+    fn next_unit(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+    /// Uniform in -1..1.
+    fn next_bipolar(&mut self) -> f32 {
+        self.next_unit() * 2.0 - 1.0
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Noise {
+    pub color: NoiseColor,
+    pub params: NoiseParam,
+    rng: Rng,
+    /// states of the filters for the pink and brown noise.
+    filter: [f32; 7],
+    /// samples to the next impulse of the velvet noise, and to the beginning of the next period.
+    until_impulse: f32,
+    until_period: f32,
+    sign: f32,
+}
+
+impl Noise {
+    pub fn new(color: NoiseColor, params: NoiseParam) -> Self {
+        let mut res = Self {
+            color,
+            params,
+            rng: Rng::new(0),
+            filter: [0.0; 7],
+            until_impulse: 0.0,
+            until_period: 0.0,
+            sign: 1.0,
+        };
+        res.reset_phase();
+        res
+    }
+    /// Pink noise filtered from the white noise, with the coefficients by Paul Kellet.
+    fn pink(&mut self, white: f32) -> f32 {
+        let b = &mut self.filter;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let res = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        // keeps the peak in about -1..1.
+        res * 0.11
+    }
+    /// Brown noise integrated from the white noise with a leak so that it does not drift away.
+    fn brown(&mut self, white: f32) -> f32 {
+        let b = &mut self.filter[0];
+        *b = (*b + 0.02 * white) / 1.02;
+        *b * 3.5
+    }
+    /// Places the impulse in the period just started, with a random sign.
+    fn draw_impulse(&mut self) {
+        self.until_impulse = (self.rng.next_unit() * self.until_period).floor();
+        self.sign = if self.rng.next_unit() < 0.5 {
+            -1.0
+        } else {
+            1.0
+        };
+    }
+    fn period(&self, info: &PlaybackInfo) -> f32 {
+        (info.sample_rate as f32 / self.params.density.get()).max(1.0)
+    }
+    fn velvet(&mut self, info: &PlaybackInfo) -> f32 {
+        if self.until_period <= 0.0 {
+            self.until_period += self.period(info);
+            self.draw_impulse();
+        }
+        let res = if self.until_impulse == 0.0 {
+            self.sign
+        } else {
+            0.0
+        };
+        self.until_impulse -= 1.0;
+        self.until_period -= 1.0;
+        res
+    }
+}
+
+impl GeneratorComponent for Noise {
+    type Params = NoiseParam;
+
+    fn get_params(&self) -> &Self::Params {
+        &self.params
     }
-    fn get_output_channels(&self) -> u64 {
-        2
+    /// Restarts the sequence from the seed.
+    fn reset_phase(&mut self) {
+        self.rng = Rng::new(self.params.seed.get().max(0.0).round() as u64);
+        self.filter = [0.0; 7];
+        self.until_impulse = 0.0;
+        self.until_period = 0.0;
     }
+    /// Skips the random values in bulk instead of rendering the samples.
+    /// The filters of the pink and brown noise are run only for the last samples, as the older input has decayed
+    /// below the precision by then.
+    fn seek(&mut self, time: usize, info: &PlaybackInfo) {
+        self.reset_phase();
+        let settle = match self.color {
+            NoiseColor::White => 0,
+            NoiseColor::Pink | NoiseColor::Brown => time.min(SETTLE_FRAMES),
+            NoiseColor::Velvet => {
+                // walks the periods, which take 2 random values each, to the one containing `time`.
+                let period = self.period(info);
+                let (mut start, mut periods) = (0, 0);
+                loop {
+                    self.until_period += period;
+                    let len = self.until_period.ceil() as usize;
+                    if start + len > time {
+                        break;
+                    }
+                    start += len;
+                    self.until_period -= len as f32;
+                    periods += 1;
+                }
+                self.rng.skip(2 * periods);
+                self.draw_impulse();
+                let elapsed = (time - start) as f32;
+                self.until_impulse -= elapsed;
+                self.until_period -= elapsed;
+                return;
+            }
+        };
+        self.rng.skip((time - settle) as u64);
+        let mut dummy = 0.0;
+        for _ in 0..settle {
+            self.render_sample(&mut dummy, info);
+        }
+    }
+
+    fn render_sample(&mut self, out: &mut f32, info: &PlaybackInfo) {
+        let res = match self.color {
+            NoiseColor::White => self.rng.next_bipolar(),
+            NoiseColor::Pink => {
+                let white = self.rng.next_bipolar();
+                self.pink(white)
+            }
+            NoiseColor::Brown => {
+                let white = self.rng.next_bipolar();
+                self.brown(white)
+            }
+            NoiseColor::Velvet => self.velvet(info),
+        };
+        *out = res * self.params.amp.get();
+    }
+}
 
-    fn prepare_play(&mut self, _info: &PlaybackInfo) {}
-    fn render(&mut self, _input: &[f32], output: &mut [f32], _info: &PlaybackInfo) {
-        #[cfg(not(target_arch = "wasm32"))]
-        for o in output.iter_mut() {
-            *o = rand::random::<f32>();
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio::fixture::{param, render_samples, SR};
+
+    fn render(color: NoiseColor, seed: f32, from: usize, len: usize) -> Vec<f32> {
+        let params = NoiseParam::default();
+        params.seed.set(seed);
+        render_samples(&mut Noise::new(color, params), from, len)
+    }
+
+    #[test]
+    fn reproducible_with_seed() {
+        for color in NoiseColor::ALL {
+            let a = render(color, 42.0, 0, 4096);
+            assert_eq!(a, render(color, 42.0, 0, 4096));
+            assert_ne!(a, render(color, 43.0, 0, 4096));
+            // starting from the middle renders the same samples as from the beginning.
+            assert_eq!(a[1000..], render(color, 42.0, 1000, 3096));
+        }
+    }
+    #[test]
+    fn seek_far() {
+        let mut rng = Rng::new(7);
+        let mut skipped = rng.clone();
+        for _ in 0..1000 {
+            rng.next_u64();
+        }
+        skipped.skip(1000);
+        assert_eq!(rng.0, skipped.0);
+        let from = 100_000;
+        for color in NoiseColor::ALL {
+            let a = render(color, 42.0, 0, from + 512);
+            let b = render(color, 42.0, from, 512);
+            for (x, y) in a[from..].iter().zip(b.iter()) {
+                assert!((x - y).abs() < 1e-5, "{:?}: {} expected {}", color, y, x);
+            }
+            if matches!(color, NoiseColor::White | NoiseColor::Velvet) {
+                assert_eq!(a[from..], b);
+            }
+        }
+        // the periods not dividing the sample rate.
+        let params = NoiseParam::default();
+        params.density.set(7.0);
+        let mut noise = Noise::new(NoiseColor::Velvet, params);
+        let a = render_samples(&mut noise, 0, from + 20_000);
+        assert_eq!(a[from..], render_samples(&mut noise, from, 20_000));
+    }
+    #[test]
+    fn spectral_tilt() {
+        // the power of the difference relative to the signal gets lower as the spectrum tilts to the low end.
+        let ratio = |color| {
+            let s = render(color, 1.0, 0, SR);
+            let power = s.iter().map(|x| x * x).sum::<f32>();
+            let diff = s.windows(2).map(|w| (w[1] - w[0]).powi(2)).sum::<f32>();
+            diff / power
+        };
+        let (white, pink, brown) = (
+            ratio(NoiseColor::White),
+            ratio(NoiseColor::Pink),
+            ratio(NoiseColor::Brown),
+        );
+        assert!((white - 2.0).abs() < 0.05, "white {}", white);
+        assert!(pink < white * 0.5, "pink {}", pink);
+        assert!(brown < pink * 0.2, "brown {}", brown);
+        let s = render(NoiseColor::White, 1.0, 0, SR);
+        assert!(s.iter().all(|x| (-1.0..1.0).contains(x)));
+    }
+    #[test]
+    fn velvet_impulse_per_period() {
+        let params = NoiseParam {
+            density: param(1000.0, "density"),
+            ..Default::default()
+        };
+        let out = render_samples(&mut Noise::new(NoiseColor::Velvet, params), 0, SR);
+        for period in out.chunks(48) {
+            let impulses = period.iter().filter(|x| **x != 0.0).collect::<Vec<_>>();
+            assert_eq!(impulses.len(), 1);
+            assert_eq!(impulses[0].abs(), 1.0);
         }
-        output.fill(1.0);
+        let positive = out.iter().filter(|x| **x > 0.0).count();
+        assert!((400..600).contains(&positive));
     }
 }
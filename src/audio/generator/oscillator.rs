@@ -13,7 +13,8 @@ pub trait Oscillator {
     fn set_phase(&mut self, init: f32);
     fn phase(&self) -> f32;
 
-    fn map(&self, phase: f32) -> f32;
+    /// Value at the `phase` in 0..1. `increment` is the phase advanced per sample, used for band-limiting.
+    fn map(&self, phase: f32, increment: f32) -> f32;
 }
 impl<T: Oscillator> GeneratorComponent for T {
     type Params = OscillatorParam;
@@ -27,17 +28,23 @@ impl<T: Oscillator> GeneratorComponent for T {
     }
 
     fn render_sample(&mut self, out: &mut f32, info: &PlaybackInfo) {
-        *out = self.map(self.phase()) * self.get_params().amp.get();
-        self.set_phase(
-            (self.phase() + self.get_params().freq.get() / info.sample_rate as f32) % 1.0,
-        );
+        let increment = self.get_params().freq.get() / info.sample_rate as f32;
+        *out = self.map(self.phase(), increment) * self.get_params().amp.get();
+        self.set_phase((self.phase() + increment) % 1.0);
+    }
+    fn seek(&mut self, time: usize, info: &PlaybackInfo) {
+        // calculate in f64 because accumulating the phase increment loses precision for long time.
+        let params = self.get_params();
+        let phase = params.phase.get() as f64
+            + params.freq.get() as f64 * time as f64 / info.sample_rate as f64;
+        self.set_phase(phase.fract() as f32);
     }
 }
 
 pub struct GenericOscillator {
     pub params: data::OscillatorParam,
     phase_internal: f32,
-    map_fn: Arc<dyn Fn(f32) -> f32 + 'static + Send + Sync>,
+    map_fn: Arc<dyn Fn(f32, f32) -> f32 + 'static + Send + Sync>,
 }
 impl Clone for GenericOscillator {
     fn clone(&self) -> Self {
@@ -63,7 +70,7 @@ impl std::fmt::Debug for GenericOscillator {
 impl GenericOscillator {
     pub fn new<F>(params: data::OscillatorParam, map_fn: F) -> Self
     where
-        F: Fn(f32) -> f32 + 'static + Send + Sync,
+        F: Fn(f32, f32) -> f32 + 'static + Send + Sync,
     {
         Self {
             params: params.clone(),
@@ -86,34 +93,159 @@ impl Oscillator for GenericOscillator {
         self.phase_internal
     }
 
-    fn map(&self, phase: f32) -> f32 {
-        (self.map_fn)(phase)
+    fn map(&self, phase: f32, increment: f32) -> f32 {
+        (self.map_fn)(phase, increment)
+    }
+}
+
+/// Residual of the band-limited step from -1 to 1 at the phase 0, approximated by the polynomial (PolyBLEP).
+/// `t` is the phase in 0..1 and `dt` is the phase increment per sample.
+fn poly_blep(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let x = t / dt;
+        2.0 * x - x * x - 1.0
+    } else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt;
+        x * x + 2.0 * x + 1.0
+    } else {
+        0.0
     }
 }
+/// Integral of `poly_blep`, the residual of the band-limited corner where the slope per sample increases by 2 (PolyBLAMP).
+fn poly_blamp(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let x = t / dt - 1.0;
+        -x * x * x / 3.0
+    } else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt + 1.0;
+        x * x * x / 3.0
+    } else {
+        0.0
+    }
+}
+/// Phase shifted by `offset`, wrapped into 0..1.
+fn shift(phase: f32, offset: f32) -> f32 {
+    (phase - offset).rem_euclid(1.0)
+}
 
 pub fn sinewave(params: data::OscillatorParam) -> GenericOscillator {
-    GenericOscillator::new(params, move |phase: f32| (phase * TWOPI).sin())
+    GenericOscillator::new(params, move |phase: f32, _| (phase * TWOPI).sin())
 }
+/// Rises from -1 to 1 when the direction is 0.5 or above, falls otherwise.
 pub fn saw(params: data::OscillatorParam, direction: Arc<FloatParameter>) -> GenericOscillator {
-    GenericOscillator::new(params, move |phase: f32| {
-        let dir = direction.get() > 1.0;
-        (phase * 2.0 - 1.0) * if dir { 1.0 } else { -1.0 }
+    GenericOscillator::new(params, move |phase: f32, dt: f32| {
+        let up = direction.get() >= 0.5;
+        let rising = phase * 2.0 - 1.0 - poly_blep(phase, dt);
+        if up {
+            rising
+        } else {
+            -rising
+        }
     })
 }
+/// 1 for the `duty` ratio of the period from the beginning, -1 for the rest.
 pub fn rect(params: data::OscillatorParam, duty: Arc<FloatParameter>) -> GenericOscillator {
-    GenericOscillator::new(
-        params,
-        move |phase: f32| {
-            if phase > duty.get() {
-                1.0
-            } else {
-                -1.0
-            }
-        },
-    )
+    GenericOscillator::new(params, move |phase: f32, dt: f32| {
+        let duty = duty.get().clamp(0.0, 1.0);
+        let naive = if phase < duty { 1.0 } else { -1.0 };
+        naive + poly_blep(phase, dt) - poly_blep(shift(phase, duty), dt)
+    })
 }
+/// Starts from 1, reaches -1 at the half of the period.
 pub fn triangle(params: data::OscillatorParam) -> GenericOscillator {
-    GenericOscillator::new(params, move |phase: f32| {
-        (phase * 2.0 - 1.0).abs() * 2.0 - 1.0
+    GenericOscillator::new(params, move |phase: f32, dt: f32| {
+        let naive = (phase * 2.0 - 1.0).abs() * 2.0 - 1.0;
+        // the slope changes by 8 per period at the corners, and the residual is for the change of 2.
+        let slope = 4.0 * dt;
+        naive - slope * poly_blamp(phase, dt) + slope * poly_blamp(shift(phase, 0.5), dt)
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio::fixture::{amplitude, param, render_samples, SR};
+
+    /// 1010Hz fits in 1 sec by the integer cycles, and its aliases do not fall on the harmonics.
+    const FREQ: usize = 1010;
+
+    fn render(mut osc: GenericOscillator) -> Vec<f32> {
+        osc.params.freq.set(FREQ as f32);
+        render_samples(&mut osc, 0, SR)
+    }
+    fn harmonic(samples: &[f32], k: usize) -> f64 {
+        amplitude(samples, (k * FREQ) as f64)
+    }
+    /// Power outside of the harmonics below the Nyquist frequency, relative to the whole power.
+    fn alias_ratio(samples: &[f32]) -> f64 {
+        let total = samples.iter().map(|s| (*s as f64).powi(2)).sum::<f64>() / samples.len() as f64;
+        let harmonics = (1..=SR / 2 / FREQ)
+            .map(|k| harmonic(samples, k).powi(2) / 2.0)
+            .sum::<f64>();
+        (total - harmonics) / total
+    }
+    /// Compares the lower harmonics with the Fourier series of the ideal waveform.
+    /// PolyBLEP slightly attenuates the higher ones, so 5% of error is allowed.
+    fn assert_spectrum(samples: &[f32], reference: impl Fn(usize) -> f64) {
+        for k in 1..=5 {
+            let (amp, expected) = (harmonic(samples, k), reference(k));
+            assert!(
+                (amp - expected).abs() <= expected * 0.05 + 1e-3,
+                "harmonic {}: {} expected {}",
+                k,
+                amp,
+                expected
+            );
+        }
+    }
+    fn naive(map_fn: impl Fn(f32) -> f32 + Send + Sync + 'static) -> GenericOscillator {
+        GenericOscillator::new(OscillatorParam::default(), move |phase, _| map_fn(phase))
+    }
+
+    #[test]
+    fn saw_spectrum() {
+        let pi = std::f64::consts::PI;
+        let up = render(saw(OscillatorParam::default(), param(1.0, "direction")));
+        assert_spectrum(&up, |k| 2.0 / (pi * k as f64));
+        assert!(up[10] > up[5]);
+        let down = render(saw(OscillatorParam::default(), param(0.0, "direction")));
+        assert!(down[10] < down[5]);
+        let aliased = render(naive(|phase| phase * 2.0 - 1.0));
+        assert!(alias_ratio(&up) < alias_ratio(&aliased) * 0.2);
+    }
+    #[test]
+    fn rect_spectrum_and_duty() {
+        let pi = std::f64::consts::PI;
+        let square = render(rect(OscillatorParam::default(), param(0.5, "duty")));
+        assert_spectrum(&square, |k| {
+            if k % 2 == 1 {
+                4.0 / (pi * k as f64)
+            } else {
+                0.0
+            }
+        });
+        let aliased = render(naive(|phase| if phase < 0.5 { 1.0 } else { -1.0 }));
+        assert!(alias_ratio(&square) < alias_ratio(&aliased) * 0.2);
+        // high for the quarter of the period.
+        let pulse = render(rect(OscillatorParam::default(), param(0.25, "duty")));
+        let mean = pulse.iter().map(|s| *s as f64).sum::<f64>() / pulse.len() as f64;
+        assert!((mean + 0.5).abs() < 0.01);
+        assert_spectrum(&pulse, |k| {
+            4.0 / (pi * k as f64) * (pi * k as f64 * 0.25).sin().abs()
+        });
+    }
+    #[test]
+    fn triangle_spectrum() {
+        let pi = std::f64::consts::PI;
+        let tri = render(triangle(OscillatorParam::default()));
+        assert_spectrum(&tri, |k| {
+            if k % 2 == 1 {
+                8.0 / (pi * pi * (k * k) as f64)
+            } else {
+                0.0
+            }
+        });
+        let aliased = render(naive(|phase| (phase * 2.0 - 1.0).abs() * 2.0 - 1.0));
+        assert!(alias_ratio(&tri) < alias_ratio(&aliased) * 0.2);
+    }
+}
@@ -0,0 +1,313 @@
+//! Wavetable oscillator morphing between the single-cycle frames.
+//!
+//! Each frame is kept as the mipmap of the band-limited copies, each with the half of the harmonics of the previous one,
+//! and the copy for the playing frequency is read so that the harmonics above the Nyquist frequency are not folded back.
+
+use super::oscillator::GenericOscillator;
+use crate::data::OscillatorParam;
+use crate::parameter::{FloatParameter, Parameter};
+use crate::script::{extend_env, EvalError, Value};
+use std::sync::Arc;
+
+/// Samples of a frame. The frames of the other length are resampled into this.
+pub const TABLE_SIZE: usize = 2048;
+/// Frames computed from the closure taking the position as the second argument.
+pub const CLOSURE_FRAMES: usize = 16;
+/// Mipmap levels down to the fundamental only.
+const LEVELS: usize = TABLE_SIZE.ilog2() as usize;
+
+type Complex = (f64, f64);
+
+/// In-place radix-2 FFT. The inverse is not scaled.
+fn fft(buf: &mut [Complex], inverse: bool) {
+    let n = buf.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            buf.swap(i, j);
+        }
+    }
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let w = sign * std::f64::consts::TAU / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (wr, wi) = ((w * k as f64).cos(), (w * k as f64).sin());
+                let (ar, ai) = buf[start + k];
+                let (br, bi) = buf[start + k + len / 2];
+                let (tr, ti) = (br * wr - bi * wi, br * wi + bi * wr);
+                buf[start + k] = (ar + tr, ai + ti);
+                buf[start + k + len / 2] = (ar - tr, ai - ti);
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// The highest harmonic kept in the mipmap level.
+fn max_harmonic(level: usize) -> usize {
+    match level {
+        0 => TABLE_SIZE / 2 - 1,
+        l => (TABLE_SIZE / 2) >> l,
+    }
+}
+
+/// Level of the mipmap without the harmonics above the Nyquist frequency, for the phase `increment` per sample.
+fn level_for(increment: f32) -> usize {
+    (0..LEVELS)
+        .find(|l| (max_harmonic(*l) as f32) * increment.abs() < 0.5)
+        .unwrap_or(LEVELS - 1)
+}
+
+/// Reads the table by the phase in 0..1 with the linear interpolation.
+/// The table has a copy of the first sample at the end.
+fn read_table(table: &[f32], phase: f32) -> f32 {
+    let pos = phase.rem_euclid(1.0) * TABLE_SIZE as f32;
+    let i = (pos as usize).min(TABLE_SIZE - 1);
+    let frac = pos - i as f32;
+    table[i] + (table[i + 1] - table[i]) * frac
+}
+
+#[derive(Clone, Debug)]
+struct Frame {
+    levels: Vec<Vec<f32>>,
+}
+
+impl Frame {
+    fn new(samples: &[f32]) -> Self {
+        let mut spectrum = resample(samples)
+            .into_iter()
+            .map(|s| (s as f64, 0.0))
+            .collect::<Vec<_>>();
+        fft(&mut spectrum, false);
+        let levels = (0..LEVELS)
+            .map(|level| {
+                let limit = max_harmonic(level);
+                let mut buf = spectrum
+                    .iter()
+                    .enumerate()
+                    .map(|(k, bin)| {
+                        let harmonic = k.min(TABLE_SIZE - k);
+                        if harmonic <= limit {
+                            *bin
+                        } else {
+                            (0.0, 0.0)
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                fft(&mut buf, true);
+                let mut table = buf
+                    .iter()
+                    .map(|(re, _)| (*re / TABLE_SIZE as f64) as f32)
+                    .collect::<Vec<_>>();
+                table.push(table[0]);
+                table
+            })
+            .collect();
+        Self { levels }
+    }
+}
+
+/// Resamples a cycle into `TABLE_SIZE` samples with the linear interpolation.
+fn resample(samples: &[f32]) -> Vec<f32> {
+    if samples.len() == TABLE_SIZE || samples.is_empty() {
+        let mut res = samples.to_vec();
+        res.resize(TABLE_SIZE, 0.0);
+        return res;
+    }
+    let len = samples.len();
+    (0..TABLE_SIZE)
+        .map(|i| {
+            let pos = i as f32 * len as f32 / TABLE_SIZE as f32;
+            let j = pos as usize;
+            let frac = pos - j as f32;
+            samples[j] + (samples[(j + 1) % len] - samples[j]) * frac
+        })
+        .collect()
+}
+
+/// Frames of single cycles with their mipmaps, shared by the oscillators playing them.
+#[derive(Clone, Debug)]
+pub struct Wavetable {
+    frames: Vec<Frame>,
+}
+
+impl Wavetable {
+    /// Makes the table from the cycles. An empty table plays silence.
+    pub fn new<T: AsRef<[f32]>>(frames: impl IntoIterator<Item = T>) -> Self {
+        let mut frames = frames
+            .into_iter()
+            .map(|f| Frame::new(f.as_ref()))
+            .collect::<Vec<_>>();
+        if frames.is_empty() {
+            frames.push(Frame::new(&[]));
+        }
+        Self { frames }
+    }
+    /// Splits the samples into the frames of `frame_size`. The remainder shorter than a frame is dropped.
+    pub fn from_samples(samples: &[f32], frame_size: usize) -> Self {
+        Self::new(samples.chunks_exact(frame_size.max(1)))
+    }
+    /// Loads the file of the frames in `TABLE_SIZE` samples, the common format of the wavetable synthesizers.
+    /// The channels are mixed down into mono.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: impl ToString) -> Result<Self, Box<dyn std::error::Error>> {
+        let samples = super::fileplayer::read_mono(path)?;
+        Ok(Self::from_samples(&samples, TABLE_SIZE))
+    }
+    /// Computes the frames from the closure of the phase in 0..1, and optionally of the position in 0..1 as the
+    /// second argument, which makes `CLOSURE_FRAMES` frames to morph between.
+    pub fn from_closure(f: &Value) -> Result<Self, EvalError> {
+        let (ids, env, body) = match f {
+            Value::Closure(ids, env, body) if !ids.is_empty() && ids.len() <= 2 => (ids, env, body),
+            _ => {
+                return Err(EvalError::TypeMismatch(
+                    "Not a closure of the phase and the position".into(),
+                ))
+            }
+        };
+        let nframes = if ids.len() == 2 { CLOSURE_FRAMES } else { 1 };
+        let mut frames = Vec::with_capacity(nframes);
+        for frame in 0..nframes {
+            let position = frame as f64 / (nframes - 1).max(1) as f64;
+            let cycle = (0..TABLE_SIZE)
+                .map(|i| {
+                    let mut newenv = extend_env(env.clone());
+                    newenv.bind(&ids[0], Value::Number(i as f64 / TABLE_SIZE as f64));
+                    if let Some(id) = ids.get(1) {
+                        newenv.bind(id, Value::Number(position));
+                    }
+                    match body.eval(Arc::new(newenv), &mut None)? {
+                        Value::Number(n) => Ok(n as f32),
+                        Value::Parameter(p) => Ok(p.get()),
+                        _ => Err(EvalError::TypeMismatch("Not a number".into())),
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            frames.push(cycle);
+        }
+        Ok(Self::new(frames))
+    }
+    /// Reads the table from an array of the frames, each of which is an array of the numbers.
+    pub fn from_array(frames: &[Value]) -> Result<Self, EvalError> {
+        let to_number = |v: &Value| match v {
+            Value::Number(n) => Ok(*n as f32),
+            _ => Err(EvalError::TypeMismatch("Not a number".into())),
+        };
+        let frames = frames
+            .iter()
+            .map(|frame| match frame {
+                Value::Array(samples, _) => samples.iter().map(to_number).collect(),
+                _ => Err(EvalError::TypeMismatch("Not an array".into())),
+            })
+            .collect::<Result<Vec<Vec<f32>>, _>>()?;
+        Ok(Self::new(frames))
+    }
+    pub fn get_frames(&self) -> usize {
+        self.frames.len()
+    }
+    /// Value at the `phase` with the `increment` per sample. The `position` in 0..1 crossfades between the
+    /// neighbouring frames.
+    pub fn read(&self, position: f32, phase: f32, increment: f32) -> f32 {
+        let level = level_for(increment);
+        let pos = position.clamp(0.0, 1.0) * (self.frames.len() - 1) as f32;
+        let i = (pos as usize).min(self.frames.len() - 1);
+        let frac = pos - i as f32;
+        let a = read_table(&self.frames[i].levels[level], phase);
+        match self.frames.get(i + 1) {
+            Some(next) if frac > 0.0 => a + (read_table(&next.levels[level], phase) - a) * frac,
+            _ => a,
+        }
+    }
+}
+
+/// Oscillator playing the table at the `position` between its frames.
+pub fn wavetable(
+    params: OscillatorParam,
+    position: Arc<FloatParameter>,
+    table: Arc<Wavetable>,
+) -> GenericOscillator {
+    GenericOscillator::new(params, move |phase: f32, dt: f32| {
+        table.read(position.get(), phase, dt)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::script::{Environment, Expr};
+
+    fn saw_cycle() -> Vec<f32> {
+        (0..TABLE_SIZE)
+            .map(|i| i as f32 / TABLE_SIZE as f32 * 2.0 - 1.0)
+            .collect()
+    }
+
+    #[test]
+    fn mipmap_band_limits() {
+        let table = Wavetable::new([saw_cycle()]);
+        // 5000Hz at 48000Hz leaves the harmonics up to the 4th.
+        let dt = 5000.0 / 48000.0;
+        assert_eq!(max_harmonic(level_for(dt)), 4);
+        for i in 0..64 {
+            let phase = i as f32 / 64.0;
+            let expected = (1..=4)
+                .map(|k| {
+                    let k = k as f32;
+                    -2.0 / (std::f32::consts::PI * k) * (std::f32::consts::TAU * k * phase).sin()
+                })
+                .sum::<f32>();
+            assert!((table.read(0.0, phase, dt) - expected).abs() < 1e-2);
+        }
+        // low notes read the whole table.
+        assert_eq!(level_for(20.0 / 48000.0), 0);
+        assert!((table.read(0.0, 0.25, 20.0 / 48000.0) + 0.5).abs() < 1e-2);
+    }
+    #[test]
+    fn morph_between_frames() {
+        let sine = (0..256)
+            .map(|i| (i as f32 / 256.0 * std::f32::consts::TAU).sin())
+            .collect::<Vec<_>>();
+        let inverted = sine.iter().map(|s| -s).collect::<Vec<_>>();
+        let table = Wavetable::new([sine, inverted]);
+        assert_eq!(table.get_frames(), 2);
+        let dt = 440.0 / 48000.0;
+        assert!((table.read(0.0, 0.25, dt) - 1.0).abs() < 1e-3);
+        assert!((table.read(1.0, 0.25, dt) + 1.0).abs() < 1e-3);
+        assert!(table.read(0.5, 0.25, dt).abs() < 1e-3);
+    }
+    #[test]
+    fn frames_from_closure() {
+        let closure = |ids: &[&str], body: Expr| {
+            Value::Closure(
+                ids.iter().map(|id| id.to_string()).collect(),
+                Arc::new(Environment::new()),
+                body.into(),
+            )
+        };
+        let dt = 100.0 / 48000.0;
+        // the frames of the constant values from 0 to 1.
+        let by_position = closure(&["phase", "position"], Expr::Var("position".into()));
+        let table = Wavetable::from_closure(&by_position).ok().unwrap();
+        assert_eq!(table.get_frames(), CLOSURE_FRAMES);
+        assert!(table.read(0.0, 0.3, dt).abs() < 1e-4);
+        assert!((table.read(1.0, 0.3, dt) - 1.0).abs() < 1e-4);
+        let ramp = closure(&["phase"], Expr::Var("phase".into()));
+        let table = Wavetable::from_closure(&ramp).ok().unwrap();
+        assert_eq!(table.get_frames(), 1);
+        assert!(Wavetable::from_closure(&Value::Number(0.0)).is_err());
+    }
+    #[test]
+    fn load_file() {
+        let (file, len) = crate::data::FilePlayerParam::new_test_file();
+        let table = Wavetable::load(file.path).unwrap();
+        assert_eq!(table.get_frames(), len / TABLE_SIZE);
+    }
+}
@@ -0,0 +1,96 @@
+//! Lock-free handoff of a value between the audio thread and other threads.
+
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::time::Duration;
+
+/// A slot holding a boxed value, which is borrowed exclusively by swapping the pointer.
+/// The audio thread never waits for the value: it gets `None` while another thread holds it.
+/// Moving the box in and out does not allocate.
+#[derive(Debug)]
+pub struct Handoff<T> {
+    ptr: AtomicPtr<T>,
+}
+
+// The value is accessed only by the thread which took it from the slot.
+unsafe impl<T: Send> Send for Handoff<T> {}
+unsafe impl<T: Send> Sync for Handoff<T> {}
+
+impl<T> Handoff<T> {
+    pub fn new(v: T) -> Self {
+        Self {
+            ptr: AtomicPtr::new(Box::into_raw(Box::new(v))),
+        }
+    }
+    /// Takes the value without blocking. Returns `None` if another thread holds it.
+    pub fn try_take(&self) -> Option<Box<T>> {
+        let ptr = self.ptr.swap(std::ptr::null_mut(), Ordering::Acquire);
+        // the pointer was created from the box in `new` or `put`.
+        (!ptr.is_null()).then(|| unsafe { Box::from_raw(ptr) })
+    }
+    /// Waits until the holder puts the value back. Do not call from the audio thread.
+    pub fn take(&self) -> Box<T> {
+        loop {
+            if let Some(v) = self.try_take() {
+                return v;
+            }
+            std::thread::sleep(Duration::from_micros(100));
+        }
+    }
+    /// Puts the taken value back into the slot.
+    /// Panics if the slot is not taken, keeping the value in the slot instead of leaking it.
+    pub fn put(&self, v: Box<T>) {
+        let ptr = Box::into_raw(v);
+        let res = self.ptr.compare_exchange(
+            std::ptr::null_mut(),
+            ptr,
+            Ordering::Release,
+            Ordering::Relaxed,
+        );
+        if res.is_err() {
+            // the pointer was not stored, so it is owned here again.
+            drop(unsafe { Box::from_raw(ptr) });
+            panic!("put into the slot which is not taken");
+        }
+    }
+    /// Replaces the value, returning the old one so that the caller can drop it outside the audio thread.
+    pub fn replace(&self, v: Box<T>) -> Box<T> {
+        let old = self.take();
+        self.put(v);
+        old
+    }
+}
+
+impl<T> Drop for Handoff<T> {
+    fn drop(&mut self) {
+        drop(self.try_take());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn exclusive_access() {
+        let slot = Arc::new(Handoff::new(0u64));
+        let v = slot.try_take().unwrap();
+        assert!(slot.try_take().is_none());
+        let slot2 = slot.clone();
+        let waiter = std::thread::spawn(move || *slot2.take());
+        std::thread::sleep(Duration::from_millis(10));
+        slot.put(Box::new(*v + 1));
+        assert_eq!(waiter.join().unwrap(), 1);
+        assert!(slot.try_take().is_none());
+        slot.put(Box::new(2));
+        assert_eq!(*slot.replace(Box::new(3)), 2);
+        assert_eq!(*slot.take(), 3);
+    }
+    #[test]
+    fn put_into_occupied_slot() {
+        let slot = Handoff::new(0u64);
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| slot.put(Box::new(1))));
+        assert!(res.is_err());
+        assert_eq!(*slot.take(), 0);
+    }
+}
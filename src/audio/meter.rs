@@ -0,0 +1,203 @@
+//! Peak, RMS and true-peak measurement in the audio thread.
+//!
+//! The levels are published to [`data::LevelMeter`] after every block, with the ballistics applied here
+//! so that the GUI does not miss the peaks between its frames.
+
+use crate::data::{self, METER_CHANNELS};
+use crate::utils::SimpleAtomic;
+use std::sync::Arc;
+
+/// Falling speed of the peak and true-peak.
+const PEAK_RELEASE_DB_PER_SEC: f32 = 20.0;
+/// Time constant of the RMS averaging.
+const RMS_WINDOW_SEC: f32 = 0.3;
+/// Oversampling ratio for the true-peak, as in ITU-R BS.1770.
+const OVERSAMPLE: usize = 4;
+const TAPS_PER_PHASE: usize = 12;
+
+/// Polyphase interpolator estimating the peaks between the samples.
+#[derive(Debug)]
+struct TruePeakFilter {
+    coefs: [[f32; TAPS_PER_PHASE]; OVERSAMPLE],
+    /// the latest sample first.
+    history: [[f32; TAPS_PER_PHASE]; METER_CHANNELS],
+}
+
+impl TruePeakFilter {
+    fn new() -> Self {
+        // Hann-windowed sinc with the cutoff at the original Nyquist frequency.
+        let len = OVERSAMPLE * TAPS_PER_PHASE;
+        let center = (len - 1) as f64 / 2.0;
+        let mut coefs = [[0.0; TAPS_PER_PHASE]; OVERSAMPLE];
+        for (phase, taps) in coefs.iter_mut().enumerate() {
+            for (t, c) in taps.iter_mut().enumerate() {
+                let n = (t * OVERSAMPLE + phase) as f64;
+                let x = (n - center) / OVERSAMPLE as f64;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+                };
+                let window =
+                    0.5 - 0.5 * (2.0 * std::f64::consts::PI * (n + 0.5) / len as f64).cos();
+                *c = (sinc * window) as f32;
+            }
+            // unity gain at DC for every phase.
+            let sum = taps.iter().sum::<f32>();
+            taps.iter_mut().for_each(|c| *c /= sum);
+        }
+        Self {
+            coefs,
+            history: [[0.0; TAPS_PER_PHASE]; METER_CHANNELS],
+        }
+    }
+    fn reset(&mut self) {
+        self.history = [[0.0; TAPS_PER_PHASE]; METER_CHANNELS];
+    }
+    /// Pushes a sample and returns the largest absolute value of the interpolated ones.
+    fn process(&mut self, ch: usize, sample: f32) -> f32 {
+        let history = &mut self.history[ch];
+        history.copy_within(0..TAPS_PER_PHASE - 1, 1);
+        history[0] = sample;
+        self.coefs.iter().fold(0.0f32, |acc, taps| {
+            let v = taps
+                .iter()
+                .zip(history.iter())
+                .map(|(c, s)| c * s)
+                .sum::<f32>();
+            acc.max(v.abs())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Meter {
+    shared: Arc<data::LevelMeter>,
+    peak: [f32; METER_CHANNELS],
+    mean_square: [f32; METER_CHANNELS],
+    true_peak: [f32; METER_CHANNELS],
+    true_peak_filter: Option<TruePeakFilter>,
+    /// multiplied to the peaks every sample.
+    release: f32,
+    /// coefficient of the one-pole averaging for the RMS.
+    rms_coef: f32,
+}
+
+impl Meter {
+    pub fn new(shared: Arc<data::LevelMeter>) -> Self {
+        let mut res = Self {
+            shared,
+            peak: [0.0; METER_CHANNELS],
+            mean_square: [0.0; METER_CHANNELS],
+            true_peak: [0.0; METER_CHANNELS],
+            true_peak_filter: None,
+            release: 1.0,
+            rms_coef: 1.0,
+        };
+        res.set_sample_rate(48000);
+        res
+    }
+    /// Measures the true-peak too, which costs the oversampling.
+    pub fn with_true_peak(mut self) -> Self {
+        self.true_peak_filter = Some(TruePeakFilter::new());
+        self
+    }
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        let sr = sample_rate.max(1) as f32;
+        self.release = 10f32.powf(-PEAK_RELEASE_DB_PER_SEC / 20.0 / sr);
+        self.rms_coef = 1.0 - (-1.0 / (RMS_WINDOW_SEC * sr)).exp();
+    }
+    pub fn prepare_play(&mut self, sample_rate: u32) {
+        self.set_sample_rate(sample_rate);
+        self.peak = [0.0; METER_CHANNELS];
+        self.mean_square = [0.0; METER_CHANNELS];
+        self.true_peak = [0.0; METER_CHANNELS];
+        if let Some(filter) = self.true_peak_filter.as_mut() {
+            filter.reset();
+        }
+        self.shared.reset_levels();
+    }
+    /// Measures the interleaved `buffer` and publishes the levels.
+    pub fn process(&mut self, buffer: &[f32], channels: usize) {
+        if channels == 0 {
+            return;
+        }
+        let mut clipped = false;
+        for frame in buffer.chunks_exact(channels) {
+            for ch in 0..METER_CHANNELS {
+                let s = frame[ch.min(channels - 1)];
+                let abs = s.abs();
+                clipped |= abs >= 1.0;
+                self.peak[ch] = abs.max(self.peak[ch] * self.release);
+                self.mean_square[ch] += self.rms_coef * (s * s - self.mean_square[ch]);
+                if let Some(filter) = self.true_peak_filter.as_mut() {
+                    let tp = filter.process(ch, s).max(abs);
+                    clipped |= tp > 1.0;
+                    self.true_peak[ch] = tp.max(self.true_peak[ch] * self.release);
+                }
+            }
+        }
+        for ch in 0..METER_CHANNELS {
+            self.shared.peak[ch].store(self.peak[ch]);
+            self.shared.rms[ch].store(self.mean_square[ch].sqrt());
+            self.shared.true_peak[ch].store(self.true_peak[ch]);
+        }
+        if clipped {
+            self.shared.set_clipped();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sine(amp: f32, freq_ratio: f32, phase: f32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .flat_map(|i| {
+                let v = amp * (std::f32::consts::TAU * freq_ratio * i as f32 + phase).sin();
+                [v, v]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn peak_and_rms() {
+        let shared = Arc::new(data::LevelMeter::default());
+        let mut meter = Meter::new(shared.clone());
+        meter.prepare_play(1000);
+        meter.process(&sine(0.5, 0.01, 0.0, 5000), 2);
+        // the peak has fallen a bit since the last crest.
+        let peak = shared.peak[0].load();
+        assert!((0.45..=0.5).contains(&peak));
+        assert!((shared.rms[1].load() - 0.5 / 2f32.sqrt()).abs() < 0.01);
+        assert!(!shared.is_clipped());
+        // one second of the silence falls by 20dB.
+        meter.process(&vec![0.0; 2000], 2);
+        assert!((shared.peak[0].load() - peak * 0.1).abs() < 0.001);
+    }
+    #[test]
+    fn clip_latches() {
+        let shared = Arc::new(data::LevelMeter::default());
+        let mut meter = Meter::new(shared.clone());
+        meter.prepare_play(1000);
+        meter.process(&[0.1, 1.2], 2);
+        meter.process(&vec![0.0; 2000], 2);
+        assert!(shared.is_clipped());
+        shared.reset_clip();
+        meter.process(&[0.0; 20], 2);
+        assert!(!shared.is_clipped());
+    }
+    #[test]
+    fn true_peak_between_samples() {
+        // a quarter of the sample rate sampled at 45 degrees: the samples never reach the actual peak.
+        let signal = sine(0.9, 0.25, std::f32::consts::FRAC_PI_4, 400);
+        let shared = Arc::new(data::LevelMeter::default());
+        let mut meter = Meter::new(shared.clone()).with_true_peak();
+        meter.prepare_play(48000);
+        meter.process(&signal, 2);
+        assert!((shared.peak[0].load() - 0.9 / 2f32.sqrt()).abs() < 0.01);
+        assert!((shared.true_peak[0].load() - 0.9).abs() < 0.03);
+        assert!(!shared.is_clipped());
+    }
+}
@@ -0,0 +1,273 @@
+//! Up/down-mixing between the channel layouts.
+//!
+//! Mono is played on the front pair, or the center of 5.1. The surround and center channels are folded into
+//! the front pair at -3dB when the target has no such speakers, and the LFE is dropped.
+//! Discrete channels have no speaker positions, so they are mapped by their index.
+//! The speaker layouts are encoded into ambisonics from the positions of the speakers and decoded back with
+//! the virtual microphones pointed at them. Mono takes the omnidirectional channel of ambisonics.
+//!
+//! Panning and the stereo width act on the front pair of any layout.
+
+use super::ambisonic;
+use crate::data::{ChannelLayout, PanLaw, PanParam};
+use crate::parameter::Parameter;
+
+const MINUS_3DB: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// Gain of the `input` channel of `from` layout to the `output` channel of `to` layout when downmixed into stereo.
+fn stereo_gain(from: ChannelLayout, output: usize, input: usize) -> f32 {
+    use ChannelLayout::*;
+    match (from, input) {
+        (Mono, _) => 1.0,
+        (Quad, i) if i == output + 2 => MINUS_3DB,
+        (Surround51, 2) => MINUS_3DB,
+        (Surround51, i) if i == output + 4 => MINUS_3DB,
+        (_, i) if i == output => 1.0,
+        _ => 0.0,
+    }
+}
+
+/// Gain of the `input` channel of `from` layout in the `output` channel of `to` layout.
+pub fn gain(from: ChannelLayout, to: ChannelLayout, output: usize, input: usize) -> f32 {
+    use ChannelLayout::*;
+    let identity = (output == input) as u8 as f32;
+    match (from, to) {
+        _ if from == to => identity,
+        (Discrete(_), _) | (_, Discrete(_)) => identity,
+        // the lower orders are the first channels of the higher ones.
+        (Ambisonic(_), Ambisonic(_)) => identity,
+        (_, Ambisonic(_)) => from
+            .speaker_direction(input)
+            .map_or(0.0, |(az, el)| ambisonic::harmonic(output, az, el)),
+        (Ambisonic(_), Mono) => (output == 0 && input == 0) as u8 as f32,
+        (Ambisonic(order), _) => to
+            .speaker_direction(output)
+            .map_or(0.0, |(az, el)| ambisonic::decode_gain(order, input, az, el)),
+        (Mono, Surround51) => (output == 2) as u8 as f32,
+        (Mono, _) => (output < 2) as u8 as f32,
+        (_, Mono) => 0.5 * (stereo_gain(from, 0, input) + stereo_gain(from, 1, input)),
+        (_, Stereo) => stereo_gain(from, output, input),
+        (Stereo, _) => identity,
+        (Quad, Surround51) => match output {
+            0 | 1 => identity,
+            4 | 5 => (input + 2 == output) as u8 as f32,
+            _ => 0.0,
+        },
+        (Surround51, Quad) => match (output, input) {
+            (0 | 1, 2) => MINUS_3DB,
+            (0 | 1, i) => (i == output) as u8 as f32,
+            (o, i) => (i == o + 2) as u8 as f32,
+        },
+        _ => identity,
+    }
+}
+
+/// Scales the mono samples copied into every channel of `buf` by the gains of upmixing into the layout.
+/// The gains are taken once per channel, as encoding into ambisonics is not cheap.
+pub fn upmix_mono(buf: &mut [f32], layout: ChannelLayout) {
+    let chs = layout.count();
+    for ch in 0..chs {
+        let gain = self::gain(ChannelLayout::Mono, layout, ch, 0);
+        if gain != 1.0 {
+            buf.iter_mut()
+                .skip(ch)
+                .step_by(chs)
+                .for_each(|s| *s *= gain);
+        }
+    }
+}
+
+/// Writes the stereo frames returned by `frame` for each frame index into the interleaved `output` of the layout.
+/// The output is filled channel by channel, so `frame` is called once per frame for every channel.
+pub fn upmix_stereo(
+    output: &mut [f32],
+    layout: ChannelLayout,
+    frame: impl Fn(usize) -> (f32, f32),
+) {
+    let chs = layout.count().max(1);
+    for ch in 0..chs {
+        let gain_l = self::gain(ChannelLayout::Stereo, layout, ch, 0);
+        let gain_r = self::gain(ChannelLayout::Stereo, layout, ch, 1);
+        for (i, out) in output.chunks_mut(chs).enumerate() {
+            if let Some(s) = out.get_mut(ch) {
+                let (l, r) = frame(i);
+                *s = l * gain_l + r * gain_r;
+            }
+        }
+    }
+}
+
+/// Converts the interleaved blocks between the layouts. The matrix is calculated on construction
+/// so that the audio thread does not allocate.
+#[derive(Clone, Debug)]
+pub struct Mixer {
+    from: ChannelLayout,
+    to: ChannelLayout,
+    /// gains of the input channels for each output channel.
+    matrix: Vec<f32>,
+}
+
+impl Mixer {
+    pub fn new(from: ChannelLayout, to: ChannelLayout) -> Self {
+        let (ins, outs) = (from.count(), to.count());
+        let matrix = (0..outs)
+            .flat_map(|o| (0..ins).map(move |i| gain(from, to, o, i)))
+            .collect();
+        Self { from, to, matrix }
+    }
+    pub fn get_from(&self) -> ChannelLayout {
+        self.from
+    }
+    pub fn get_to(&self) -> ChannelLayout {
+        self.to
+    }
+    pub fn is_identity(&self) -> bool {
+        self.from == self.to
+    }
+    /// Mixes the `input` into the `output`, overwriting it. The number of the frames is taken from the output.
+    pub fn process(&self, input: &[f32], output: &mut [f32]) {
+        let (ins, outs) = (self.from.count(), self.to.count());
+        if outs == 0 {
+            return;
+        }
+        if ins == 0 {
+            output.fill(0.0);
+            return;
+        }
+        for (frame_in, frame_out) in input.chunks_exact(ins).zip(output.chunks_exact_mut(outs)) {
+            for (out, gains) in frame_out.iter_mut().zip(self.matrix.chunks_exact(ins)) {
+                *out = gains.iter().zip(frame_in).map(|(g, s)| g * s).sum();
+            }
+        }
+    }
+}
+
+/// Gains of the left and right channels at `pan` in -1.0..=1.0.
+/// Both are attenuated by the law at the center, and the side panned to is at unity.
+pub fn pan_gains(law: PanLaw, pan: f32) -> (f32, f32) {
+    let p = (pan.clamp(-1.0, 1.0) + 1.0) * 0.5;
+    let angle = p * std::f32::consts::FRAC_PI_2;
+    match law {
+        PanLaw::ConstantPower => (angle.cos(), angle.sin()),
+        PanLaw::Minus4_5dB => (((1.0 - p) * angle.cos()).sqrt(), (p * angle.sin()).sqrt()),
+        PanLaw::Linear => (1.0 - p, p),
+    }
+}
+
+/// Applies the width and the pan to the front pair of the interleaved `buf`. Mono buffers are left as is.
+/// Each channel of the pair is panned by the law from its own side towards the other, as the linked pans of a
+/// stereo source, so that the pair passes through unchanged at the center and is summed into one side at the end.
+/// The parameters are read once per call, so that they can be changed while playing.
+pub fn apply_pan(buf: &mut [f32], channels: usize, param: &PanParam) {
+    if channels < 2 || param.is_neutral() {
+        return;
+    }
+    let (law, pan) = (param.get_law(), param.pan.get());
+    let (left_l, left_r) = pan_gains(law, pan * 2.0 - 1.0);
+    let (right_l, right_r) = pan_gains(law, pan * 2.0 + 1.0);
+    let width = param.width.get();
+    for frame in buf.chunks_exact_mut(channels) {
+        let mid = (frame[0] + frame[1]) * 0.5;
+        let side = (frame[0] - frame[1]) * 0.5 * width;
+        let (l, r) = (mid + side, mid - side);
+        frame[0] = l * left_l + r * right_l;
+        frame[1] = l * left_r + r * right_r;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ChannelLayout::*;
+
+    fn mix(from: ChannelLayout, to: ChannelLayout, frame: &[f32]) -> Vec<f32> {
+        let mut out = vec![0.0; to.count()];
+        Mixer::new(from, to).process(frame, &mut out);
+        out
+    }
+
+    #[test]
+    fn upmix() {
+        assert_eq!(mix(Mono, Stereo, &[0.5]), [0.5, 0.5]);
+        assert_eq!(
+            mix(Mono, Surround51, &[0.5]),
+            [0.0, 0.0, 0.5, 0.0, 0.0, 0.0]
+        );
+        assert_eq!(mix(Stereo, Quad, &[0.1, 0.2]), [0.1, 0.2, 0.0, 0.0]);
+        assert_eq!(
+            mix(Quad, Surround51, &[0.1, 0.2, 0.3, 0.4]),
+            [0.1, 0.2, 0.0, 0.0, 0.3, 0.4]
+        );
+    }
+    #[test]
+    fn downmix() {
+        assert_eq!(mix(Stereo, Mono, &[0.2, 0.4]), [0.3_f32]);
+        let k = MINUS_3DB;
+        let out = mix(Surround51, Stereo, &[0.1, 0.2, 0.3, 1.0, 0.4, 0.5]);
+        assert!((out[0] - (0.1 + k * 0.3 + k * 0.4)).abs() < 1e-6);
+        assert!((out[1] - (0.2 + k * 0.3 + k * 0.5)).abs() < 1e-6);
+        let out = mix(Surround51, Quad, &[0.1, 0.2, 0.3, 1.0, 0.4, 0.5]);
+        assert!((out[0] - (0.1 + k * 0.3)).abs() < 1e-6);
+        assert_eq!(&out[2..], &[0.4, 0.5]);
+        // the stereo downmix is averaged.
+        let out = mix(Quad, Mono, &[0.2, 0.4, 0.2, 0.4]);
+        assert!((out[0] - 0.3 * (1.0 + k)).abs() < 1e-6);
+    }
+    #[test]
+    fn discrete_by_index() {
+        assert_eq!(mix(Discrete(3), Stereo, &[0.1, 0.2, 0.3]), [0.1, 0.2]);
+        assert_eq!(mix(Stereo, Discrete(3), &[0.1, 0.2]), [0.1, 0.2, 0.0]);
+    }
+    #[test]
+    fn ambisonic_round_trip() {
+        let foa = Ambisonic(1);
+        // the left channel is placed at the side and taken back by the virtual cardioid.
+        let bformat = mix(Stereo, foa, &[1.0, 0.0]);
+        assert_eq!(bformat.len(), 4);
+        let out = mix(foa, Stereo, &bformat);
+        assert!((out[0] - 1.0).abs() < 1e-5 && out[1].abs() < 1e-5);
+        assert_eq!(mix(Ambisonic(3), Mono, &bformat.repeat(4)), [1.0]);
+        // the center of 5.1 is at the front, which has no LFE.
+        let out = mix(foa, Surround51, &mix(Mono, foa, &[1.0]));
+        assert!((out[2] - 1.0).abs() < 1e-5 && out[3] == 0.0);
+        assert_eq!(mix(foa, Ambisonic(2), &bformat)[..4], bformat[..]);
+    }
+    #[test]
+    fn pan_laws() {
+        let db = |g: f32| 20.0 * g.log10();
+        for law in PanLaw::ALL {
+            let (l, r) = pan_gains(law, -1.0);
+            assert!((l - 1.0).abs() < 1e-6 && r.abs() < 1e-6);
+            let (l, r) = pan_gains(law, 1.0);
+            assert!(l.abs() < 1e-6 && (r - 1.0).abs() < 1e-6);
+        }
+        assert!((db(pan_gains(PanLaw::ConstantPower, 0.0).0) + 3.01).abs() < 0.01);
+        assert!((db(pan_gains(PanLaw::Minus4_5dB, 0.0).0) + 4.52).abs() < 0.01);
+        assert!((db(pan_gains(PanLaw::Linear, 0.0).0) + 6.02).abs() < 0.01);
+        // the power stays constant over the positions.
+        let (l, r) = pan_gains(PanLaw::ConstantPower, 0.3);
+        assert!((l * l + r * r - 1.0).abs() < 1e-5);
+    }
+    #[test]
+    fn width_and_pan() {
+        let param = PanParam::new();
+        let mut buf = [0.2, 0.6];
+        apply_pan(&mut buf, 2, &param);
+        assert_eq!(buf, [0.2, 0.6]);
+        param.width.set(0.0);
+        apply_pan(&mut buf, 2, &param);
+        assert!(buf.iter().all(|s| (s - 0.4).abs() < 1e-6));
+        param.width.set(1.0);
+        param.pan.set(1.0);
+        param.set_law(PanLaw::Linear);
+        let mut buf = [0.2, 0.6, 1.0];
+        apply_pan(&mut buf, 3, &param);
+        assert!(buf[0].abs() < 1e-6 && (buf[1] - 0.8).abs() < 1e-6);
+        assert_eq!(buf[2], 1.0);
+        // the left channel is at the center halfway.
+        param.pan.set(0.5);
+        let mut buf = [0.2, 0.6];
+        apply_pan(&mut buf, 2, &param);
+        assert!((buf[0] - 0.1).abs() < 1e-6 && (buf[1] - 0.7).abs() < 1e-6);
+    }
+}
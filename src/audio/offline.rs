@@ -0,0 +1,186 @@
+//! Rendering the whole timeline without audio devices, used for bouncing a project into a file.
+
+use crate::audio::{pool::RenderBatch, resampler, timeline, Component, PlaybackInfo};
+use crate::data::{self, ChannelLayout};
+use crate::utils::SimpleAtomic;
+use std::sync::Arc;
+
+#[derive(Clone, Debug)]
+pub struct RenderOption {
+    /// sample rate of the output. The project is rendered at its own rate and resampled into this.
+    pub sample_rate: u32,
+    /// 16 or 24 for integer PCM, 32 for floating point.
+    pub bit_depth: u16,
+    /// start time in seconds.
+    pub start: f64,
+    /// end time in seconds. The end of the last region is used if `None`.
+    pub end: Option<f64>,
+    /// receives the progress of the regions rendered in the worker pool.
+    pub progress: Option<Arc<RenderBatch>>,
+    /// layout of the file. Ambisonic layouts export the raw B-format.
+    pub layout: ChannelLayout,
+}
+
+impl Default for RenderOption {
+    fn default() -> Self {
+        Self {
+            sample_rate: 48000,
+            bit_depth: 24,
+            start: 0.0,
+            end: None,
+            progress: None,
+            layout: ChannelLayout::Stereo,
+        }
+    }
+}
+
+/// Renders the range of the project into interleaved samples in the layout of the option.
+/// The loop range is ignored so that the timeline is rendered linearly.
+pub fn render_project(project: &data::Project, opt: &RenderOption) -> Vec<f32> {
+    let mut project = project.clone();
+    let (loop_start, loop_end) = project.markers.loop_range.range.get_pair();
+    // the loop range shares its state with the original project, so replace it with disabled one.
+    project.markers.loop_range = data::LoopRange::new(loop_start, loop_end);
+    let end = opt.end.unwrap_or_else(|| project.end());
+    let project_rate = project.sample_rate.load() as u32;
+
+    let to_samples = |sec: f64| data::tempo::sec_to_samples(sec, project_rate) as usize;
+    let (start, end) = (to_samples(opt.start), to_samples(end));
+    let len = end.saturating_sub(start);
+    let mut model =
+        timeline::Model::new(project, Arc::new(data::Transport::new())).without_streaming();
+    if let Some(batch) = &opt.progress {
+        model = model.with_batch(batch.clone());
+    }
+    let mut info = PlaybackInfo {
+        sample_rate: project_rate,
+        current_time: start,
+        frame_per_buffer: super::DEFAULT_BUFFER_LEN as u64,
+        channels: opt.layout,
+    };
+    model.prepare_play(&info);
+
+    let input_dummy = vec![0.0f32; 1];
+    let mut res = vec![0.0f32; len * opt.layout.count()];
+    for block in res.chunks_mut(super::DEFAULT_BUFFER_LEN * opt.layout.count()) {
+        info.frame_per_buffer = (block.len() / opt.layout.count()) as u64;
+        model.render(&input_dummy, block, &info);
+        info.current_time += info.frame_per_buffer as usize;
+    }
+    resampler::resample_buffer(&res, opt.layout.count(), project_rate, opt.sample_rate)
+}
+
+/// Writes interleaved samples in the layout of the option into a wav file. Samples are clipped into -1.0..=1.0 for integer formats.
+pub fn write_wav(
+    path: impl AsRef<std::path::Path>,
+    samples: &[f32],
+    opt: &RenderOption,
+) -> hound::Result<()> {
+    let sample_format = match opt.bit_depth {
+        16 | 24 => hound::SampleFormat::Int,
+        32 => hound::SampleFormat::Float,
+        _ => return Err(hound::Error::Unsupported),
+    };
+    let spec = hound::WavSpec {
+        channels: opt.layout.count() as u16,
+        sample_rate: opt.sample_rate,
+        bits_per_sample: opt.bit_depth,
+        sample_format,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    let max = ((1i32 << (opt.bit_depth - 1)) - 1) as f32;
+    for s in samples {
+        match sample_format {
+            hound::SampleFormat::Float => writer.write_sample(*s)?,
+            hound::SampleFormat::Int => writer.write_sample((s.clamp(-1.0, 1.0) * max) as i32)?,
+        }
+    }
+    writer.finalize()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio::fixture;
+    use crate::{parameter::Parameter, utils::SimpleAtomic};
+
+    fn constant_project() -> data::Project {
+        fixture::project(vec![fixture::constant_region(0.5, 0.5, 1.0)], 1000)
+    }
+
+    #[test]
+    fn render_range() {
+        let project = constant_project();
+        project.markers.loop_range.enabled.store(true);
+        let opt = RenderOption {
+            sample_rate: 1000,
+            start: 0.25,
+            ..Default::default()
+        };
+        let samples = render_project(&project, &opt);
+        // 0.25 ~ 1.0 sec even though the loop is enabled at 0 ~ 4 sec.
+        assert_eq!(samples.len(), 750 * 2);
+        assert_eq!(samples[249 * 2], 0.0);
+        assert_eq!(samples[250 * 2], 0.5);
+        assert!(project.markers.loop_range.is_enabled());
+    }
+    #[test]
+    fn render_at_other_rate() {
+        let opt = RenderOption {
+            sample_rate: 2000,
+            ..Default::default()
+        };
+        let samples = render_project(&constant_project(), &opt);
+        // the project of 1000Hz is rendered for 1 sec and resampled.
+        assert_eq!(samples.len(), 2000 * 2);
+        assert!(samples[..900 * 2].iter().all(|s| s.abs() < 0.02));
+        assert!(samples[1200 * 2..1800 * 2]
+            .iter()
+            .all(|s| (s - 0.5).abs() < 0.02));
+    }
+    #[test]
+    fn write_and_read_wav() {
+        let opt = RenderOption {
+            sample_rate: 1000,
+            bit_depth: 16,
+            ..Default::default()
+        };
+        let samples = render_project(&constant_project(), &opt);
+        let path = std::env::temp_dir().join("otopoiesis_offline_test.wav");
+        write_wav(&path, &samples, &opt).unwrap();
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.spec().sample_rate, 1000);
+        assert_eq!(reader.len() as usize, samples.len());
+        let last = reader.samples::<i16>().last().unwrap().unwrap();
+        assert_eq!(last, (0.5 * i16::MAX as f32) as i16);
+        let _ = std::fs::remove_file(path);
+    }
+    #[test]
+    fn export_bformat() {
+        let mut project = constant_project();
+        let data::Track::Regions(regions, param) = &mut project.tracks[0] else {
+            unreachable!()
+        };
+        let direction = data::DirectionParam::new();
+        direction.azimuth.set(90.0);
+        let origin = regions.remove(0);
+        regions.push(data::Region::new(
+            origin.range.clone(),
+            data::Content::Transformer(data::RegionFilter::Encode(direction), Box::new(origin)),
+            "constant",
+        ));
+        param.layout.set(ChannelLayout::Ambisonic(1));
+        let opt = RenderOption {
+            sample_rate: 1000,
+            layout: ChannelLayout::Ambisonic(1),
+            ..Default::default()
+        };
+        let samples = render_project(&project, &opt);
+        assert_eq!(samples.len(), 1000 * 4);
+        // W, Y, Z, X of the source at the left.
+        let frame = &samples[750 * 4..751 * 4];
+        assert!((frame[0] - 0.5).abs() < 1e-6 && (frame[1] - 0.5).abs() < 1e-6);
+        assert!(frame[2].abs() < 1e-6 && frame[3].abs() < 1e-6);
+    }
+}
@@ -0,0 +1,265 @@
+//! Bounded thread pool for offline rendering.
+//!
+//! Each worker has its own queue, and steals jobs from the back of others' queues when it runs out,
+//! so that the jobs are balanced across workers without a single contended queue.
+
+use std::collections::VecDeque;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::time::Duration;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+struct Shared {
+    queues: Vec<Mutex<VecDeque<Job>>>,
+    /// number of the queued jobs, used for sleeping workers.
+    pending: Mutex<usize>,
+    wakeup: Condvar,
+    next_queue: AtomicUsize,
+    is_alive: AtomicBool,
+}
+
+impl Shared {
+    fn pop(&self, index: usize) -> Option<Job> {
+        let own = self.queues[index].lock().unwrap().pop_front();
+        let job = own.or_else(|| {
+            let n = self.queues.len();
+            (1..n).find_map(|i| self.queues[(index + i) % n].lock().unwrap().pop_back())
+        })?;
+        *self.pending.lock().unwrap() -= 1;
+        Some(job)
+    }
+    fn run_worker(&self, index: usize) {
+        while self.is_alive.load(Ordering::Relaxed) {
+            match self.pop(index) {
+                Some(job) => job(),
+                None => {
+                    let pending = self.pending.lock().unwrap();
+                    if *pending == 0 {
+                        // the timeout is a safety net for the job pushed between `pop` and `wait`.
+                        let _ = self
+                            .wakeup
+                            .wait_timeout(pending, Duration::from_millis(50))
+                            .unwrap();
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub struct ThreadPool {
+    shared: Arc<Shared>,
+    threads: Vec<std::thread::JoinHandle<()>>,
+}
+
+impl ThreadPool {
+    pub fn new(num_threads: usize) -> Self {
+        let num_threads = num_threads.max(1);
+        let shared = Arc::new(Shared {
+            queues: (0..num_threads).map(|_| Mutex::default()).collect(),
+            pending: Mutex::new(0),
+            wakeup: Condvar::new(),
+            next_queue: AtomicUsize::new(0),
+            is_alive: AtomicBool::new(true),
+        });
+        let threads = (0..num_threads)
+            .map(|i| {
+                let shared = shared.clone();
+                std::thread::Builder::new()
+                    .name(format!("render worker {}", i))
+                    .spawn(move || shared.run_worker(i))
+                    .expect("failed to launch thread")
+            })
+            .collect();
+        Self { shared, threads }
+    }
+    /// The pool shared in the application, which has as many workers as the available cores.
+    pub fn global() -> &'static Self {
+        static POOL: OnceLock<ThreadPool> = OnceLock::new();
+        POOL.get_or_init(|| {
+            let n = std::thread::available_parallelism().map_or(4, |n| n.get());
+            Self::new(n)
+        })
+    }
+    pub fn num_threads(&self) -> usize {
+        self.threads.len()
+    }
+    pub fn spawn(&self, job: impl FnOnce() + Send + 'static) {
+        let n = self.shared.queues.len();
+        let index = self.shared.next_queue.fetch_add(1, Ordering::Relaxed) % n;
+        // counted before the job becomes visible, so that `pop` never takes it from the count of 0.
+        *self.shared.pending.lock().unwrap() += 1;
+        self.shared.queues[index]
+            .lock()
+            .unwrap()
+            .push_back(Box::new(job));
+        self.shared.wakeup.notify_one();
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.shared.is_alive.store(false, Ordering::Relaxed);
+        self.shared.wakeup.notify_all();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Progress of the jobs for a project. Shared with the GUI to display the progress.
+#[derive(Debug, Default)]
+pub struct RenderBatch {
+    total: AtomicUsize,
+    finished: AtomicUsize,
+}
+
+impl RenderBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Returns the number of finished and total jobs.
+    pub fn get_progress(&self) -> (usize, usize) {
+        (
+            self.finished.load(Ordering::Relaxed),
+            self.total.load(Ordering::Relaxed),
+        )
+    }
+    pub fn is_finished(&self) -> bool {
+        let (finished, total) = self.get_progress();
+        finished >= total
+    }
+}
+
+/// Handle of the job running in the pool. Dropping the handle cancels the job if it has not started yet.
+pub struct JobHandle<T> {
+    state: Arc<JobState<T>>,
+}
+
+struct JobState<T> {
+    result: Mutex<Option<T>>,
+    finished: AtomicBool,
+    cancelled: AtomicBool,
+    done: Condvar,
+}
+
+impl<T: Send + 'static> JobHandle<T> {
+    /// Runs `job` in the pool, counting it in the `batch`.
+    pub fn spawn(
+        pool: &ThreadPool,
+        batch: &Arc<RenderBatch>,
+        job: impl FnOnce() -> T + Send + 'static,
+    ) -> Self {
+        let state = Arc::new(JobState {
+            result: Mutex::new(None),
+            finished: AtomicBool::new(false),
+            cancelled: AtomicBool::new(false),
+            done: Condvar::new(),
+        });
+        batch.total.fetch_add(1, Ordering::Relaxed);
+        let (state_w, batch) = (state.clone(), batch.clone());
+        pool.spawn(move || {
+            // a panicking job is finished without the result, so that `join` returns and the worker survives.
+            let res = (!state_w.cancelled.load(Ordering::Relaxed))
+                .then(|| std::panic::catch_unwind(AssertUnwindSafe(job)))
+                .and_then(|res| res.map_err(|_| log::error!("render job panicked")).ok());
+            // counted before waking `join`, so that the batch is finished once all the jobs are joined.
+            batch.finished.fetch_add(1, Ordering::Relaxed);
+            {
+                // set under the lock so that `join` does not miss the notification.
+                let mut result = state_w.result.lock().unwrap();
+                *result = res;
+                state_w.finished.store(true, Ordering::Release);
+            }
+            state_w.done.notify_all();
+        });
+        Self { state }
+    }
+    pub fn is_finished(&self) -> bool {
+        self.state.finished.load(Ordering::Acquire)
+    }
+    /// Takes the result without blocking. Safe to call from the audio thread as the lock is free once finished.
+    pub fn try_take(&self) -> Option<T> {
+        if !self.is_finished() {
+            return None;
+        }
+        self.state.result.try_lock().ok()?.take()
+    }
+    /// Blocks until the job finishes, keeping the result in the handle.
+    pub fn wait(&self) {
+        let mut result = self.state.result.lock().unwrap();
+        while !self.is_finished() {
+            result = self.state.done.wait(result).unwrap();
+        }
+    }
+    /// Waits for the job. Returns `None` if the job was cancelled or panicked.
+    pub fn join(self) -> Option<T> {
+        self.wait();
+        self.state.result.lock().unwrap().take()
+    }
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl<T> std::fmt::Debug for JobHandle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JobHandle")
+            .field("finished", &self.state.finished)
+            .field("cancelled", &self.state.cancelled)
+            .finish()
+    }
+}
+
+impl<T> Drop for JobHandle<T> {
+    fn drop(&mut self) {
+        self.state.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn run_and_report_progress() {
+        let pool = ThreadPool::new(2);
+        let batch = Arc::new(RenderBatch::new());
+        let handles = (0..8)
+            .map(|i| JobHandle::spawn(&pool, &batch, move || i * 2))
+            .collect::<Vec<_>>();
+        let results = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(results, (0..8).map(|i| i * 2).collect::<Vec<_>>());
+        assert_eq!(batch.get_progress(), (8, 8));
+        assert!(batch.is_finished());
+    }
+    #[test]
+    fn cancel_before_start() {
+        let pool = ThreadPool::new(1);
+        let batch = Arc::new(RenderBatch::new());
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        // blocks the only worker until the second job is cancelled.
+        let first = JobHandle::spawn(&pool, &batch, move || rx.recv().is_ok());
+        let second = JobHandle::spawn(&pool, &batch, || 1);
+        second.cancel();
+        tx.send(()).unwrap();
+        assert_eq!(first.join(), Some(true));
+        assert_eq!(second.join(), None);
+        assert!(batch.is_finished());
+    }
+    #[test]
+    fn panicking_job() {
+        let pool = ThreadPool::new(1);
+        let batch = Arc::new(RenderBatch::new());
+        let failed = JobHandle::spawn(&pool, &batch, || -> i32 { panic!("failed to render") });
+        assert_eq!(failed.join(), None);
+        // the only worker keeps running the jobs.
+        assert_eq!(JobHandle::spawn(&pool, &batch, || 1).join(), Some(1));
+        assert!(batch.is_finished());
+    }
+}
@@ -0,0 +1,143 @@
+//! Writing the input from the audio device into wav files.
+//!
+//! The audio thread only pushes the input into the ring buffer. A writer thread drains it into the file,
+//! so that the disk access does not block the audio callback.
+
+use ringbuf::HeapConsumer;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Interleaved stereo, same as the input passed to the components.
+const CHANNELS: u16 = 2;
+
+/// The file written by the recorder.
+#[derive(Debug, Clone)]
+pub struct RecordedTake {
+    pub path: PathBuf,
+    /// position where the recording started, in samples.
+    pub start: u64,
+    /// length in frames.
+    pub frames: u64,
+}
+
+type WriterResult = (HeapConsumer<f32>, hound::Result<u64>);
+
+pub struct Recorder {
+    path: PathBuf,
+    start: u64,
+    is_alive: Arc<AtomicBool>,
+    thread: std::thread::JoinHandle<WriterResult>,
+}
+
+impl Recorder {
+    /// Creates the file at `path` and starts draining `consumer` into it.
+    /// The samples left in the consumer from the previous take are discarded.
+    pub fn start(
+        mut consumer: HeapConsumer<f32>,
+        path: PathBuf,
+        sample_rate: u32,
+        start: u64,
+    ) -> Result<Self, (HeapConsumer<f32>, hound::Error)> {
+        let spec = hound::WavSpec {
+            channels: CHANNELS,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let writer = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .map_err(hound::Error::from)
+            .and_then(|_| hound::WavWriter::create(&path, spec));
+        let mut writer = match writer {
+            Ok(w) => w,
+            Err(e) => return Err((consumer, e)),
+        };
+        consumer.clear();
+        let is_alive = Arc::new(AtomicBool::new(true));
+        let alive = is_alive.clone();
+        let thread = std::thread::Builder::new()
+            .name("recorder".to_string())
+            .spawn(move || {
+                let mut buf = vec![0.0f32; 4096];
+                let mut samples = 0u64;
+                let mut write = |consumer: &mut HeapConsumer<f32>| -> hound::Result<usize> {
+                    let n = consumer.pop_slice(&mut buf);
+                    buf[..n].iter().try_for_each(|s| writer.write_sample(*s))?;
+                    samples += n as u64;
+                    Ok(n)
+                };
+                let mut res = Ok(());
+                while alive.load(Ordering::Acquire) && res.is_ok() {
+                    res = write(&mut consumer).map(|n| {
+                        if n == 0 {
+                            std::thread::sleep(Duration::from_millis(5));
+                        }
+                    });
+                }
+                // drains the rest pushed before the recording stopped.
+                while res.is_ok() && !consumer.is_empty() {
+                    res = write(&mut consumer).map(|_| ());
+                }
+                let res = res
+                    .and_then(|_| writer.finalize())
+                    .map(|_| samples / CHANNELS as u64);
+                (consumer, res)
+            })
+            .expect("failed to launch thread");
+        Ok(Self {
+            path,
+            start,
+            is_alive,
+            thread,
+        })
+    }
+    /// Finishes the file. The consumer is returned to be used for the next take.
+    pub fn stop(self) -> (HeapConsumer<f32>, hound::Result<RecordedTake>) {
+        self.is_alive.store(false, Ordering::Release);
+        let (consumer, res) = self.thread.join().expect("recorder thread panicked");
+        let take = res.map(|frames| RecordedTake {
+            path: self.path,
+            start: self.start,
+            frames,
+        });
+        (consumer, take)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ringbuf::HeapRb;
+
+    #[test]
+    fn write_take() {
+        let (mut producer, consumer) = HeapRb::<f32>::new(64).split();
+        // left from the previous take.
+        producer.push_slice(&[1.0; 4]);
+        let path = std::env::temp_dir().join("otopoiesis_recorder_test/take.wav");
+        let recorder = Recorder::start(consumer, path.clone(), 1000, 500)
+            .map_err(|(_, e)| e)
+            .unwrap();
+        let samples = (0..200).map(|i| i as f32 / 200.0).collect::<Vec<_>>();
+        for block in samples.chunks(20) {
+            while producer.free_len() < block.len() {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            producer.push_slice(block);
+        }
+        let (_consumer, take) = recorder.stop();
+        let take = take.unwrap();
+        assert_eq!((take.start, take.frames), (500, 100));
+        let mut reader = hound::WavReader::open(&take.path).unwrap();
+        assert_eq!(reader.spec().sample_rate, 1000);
+        let read = reader
+            .samples::<f32>()
+            .map(|s| s.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(read, samples);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
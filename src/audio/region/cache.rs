@@ -0,0 +1,272 @@
+//! Cache of offline-rendered regions.
+//!
+//! Rendered samples are keyed by a hash of the region content including current parameter values,
+//! so that unchanged regions are not rendered again on every playback.
+//! The cache can be persisted into a directory to be reused across sessions.
+
+use crate::data;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// 64bit FNV-1a. Unlike `std::collections::hash_map::DefaultHasher`, the result is stable across builds,
+/// which is needed for the keys persisted on disk.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl std::hash::Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+/// Hashes the length and the modification time of the files referred by the strings in the content, like the path
+/// of the file player, so that the region is rendered again when the file is recorded again or edited.
+fn hash_files(value: &serde_json::Value, hasher: &mut impl std::hash::Hasher) {
+    match value {
+        serde_json::Value::String(s) => {
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(meta) = std::fs::metadata(s).ok().filter(|m| m.is_file()) {
+                hasher.write_u64(meta.len());
+                let modified = meta
+                    .modified()
+                    .ok()
+                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map_or(0, |d| d.as_nanos());
+                hasher.write_u128(modified);
+            }
+        }
+        serde_json::Value::Array(values) => values.iter().for_each(|v| hash_files(v, hasher)),
+        serde_json::Value::Object(map) => map.values().for_each(|v| hash_files(v, hasher)),
+        _ => {}
+    }
+}
+
+/// Returns the key of the rendered result of the region.
+/// The start time is not included because it does not affect the samples.
+pub fn content_hash(region: &data::Region, sample_rate: u32, channels: data::ChannelLayout) -> u64 {
+    use std::hash::Hasher;
+    let mut hasher = Fnv1a::default();
+    // parameters are serialized with their current values.
+    let content = serde_json::to_value(&region.content).unwrap_or_default();
+    hasher.write(content.to_string().as_bytes());
+    hash_files(&content, &mut hasher);
+    hasher.write_u64(region.range.getrange().to_bits());
+    hasher.write_u32(sample_rate);
+    // the layouts with the same count of channels are upmixed differently.
+    let layout = serde_json::to_string(&channels).unwrap_or_default();
+    hasher.write(layout.as_bytes());
+    hasher.finish()
+}
+
+/// Samples kept in memory by default, 256MB in total.
+pub const MEMORY_CAPACITY: usize = 1 << 26;
+
+/// Rendered regions in memory, dropping the least recently used ones over the capacity.
+#[derive(Debug, Default)]
+struct Lru {
+    entries: HashMap<u64, (Arc<Vec<f32>>, u64)>,
+    /// incremented on every access to order the entries.
+    clock: u64,
+    /// samples of all the entries.
+    size: usize,
+}
+
+impl Lru {
+    fn get(&mut self, key: u64) -> Option<Arc<Vec<f32>>> {
+        self.clock += 1;
+        let (samples, used) = self.entries.get_mut(&key)?;
+        *used = self.clock;
+        Some(samples.clone())
+    }
+    fn insert(&mut self, key: u64, samples: Arc<Vec<f32>>, capacity: usize) {
+        self.clock += 1;
+        if let Some((old, _)) = self.entries.remove(&key) {
+            self.size -= old.len();
+        }
+        if samples.len() > capacity {
+            return;
+        }
+        while self.size + samples.len() > capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(k, _)| *k);
+            match oldest.and_then(|k| self.entries.remove(&k)) {
+                Some((old, _)) => self.size -= old.len(),
+                None => break,
+            }
+        }
+        self.size += samples.len();
+        self.entries.insert(key, (samples, self.clock));
+    }
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.size = 0;
+    }
+}
+
+#[derive(Debug)]
+pub struct RenderCache {
+    memory: Mutex<Lru>,
+    /// samples kept in memory.
+    capacity: usize,
+    /// directory to persist rendered regions. The cache is kept only in memory if `None`.
+    dir: Option<std::path::PathBuf>,
+}
+
+impl Default for RenderCache {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl RenderCache {
+    pub fn new(dir: Option<std::path::PathBuf>) -> Self {
+        Self::with_capacity(dir, MEMORY_CAPACITY)
+    }
+    pub fn with_capacity(dir: Option<std::path::PathBuf>, capacity: usize) -> Self {
+        Self {
+            memory: Mutex::new(Lru::default()),
+            capacity,
+            dir,
+        }
+    }
+    fn path_for(&self, key: u64) -> Option<std::path::PathBuf> {
+        self.dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{:016x}.f32", key)))
+    }
+    /// Returns the interleaved samples rendered before, looking up the disk if not in memory.
+    pub fn get(&self, key: u64) -> Option<Arc<Vec<f32>>> {
+        if let Some(samples) = self.memory.lock().unwrap().get(key) {
+            return Some(samples);
+        }
+        let bytes = std::fs::read(self.path_for(key)?).ok()?;
+        let samples = Arc::new(
+            bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect::<Vec<_>>(),
+        );
+        self.memory
+            .lock()
+            .unwrap()
+            .insert(key, samples.clone(), self.capacity);
+        Some(samples)
+    }
+    /// Stores the rendered samples. Failure on writing into the disk is not fatal as the cache is kept in memory.
+    pub fn insert(&self, key: u64, samples: Vec<f32>) {
+        if let Some(path) = self.path_for(key) {
+            let bytes = samples
+                .iter()
+                .flat_map(|s| s.to_le_bytes())
+                .collect::<Vec<_>>();
+            let res = path
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| std::fs::write(&path, bytes));
+            if let Err(e) = res {
+                log::warn!("failed to write render cache {}: {}", path.display(), e);
+            }
+        }
+        self.memory
+            .lock()
+            .unwrap()
+            .insert(key, Arc::new(samples), self.capacity);
+    }
+    /// Drops the entries in memory. The files on disk are kept.
+    pub fn clear_memory(&self) {
+        self.memory.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio::fixture::constant_region_of;
+    use crate::{
+        param_float,
+        parameter::{FloatParameter, Parameter, RangedNumeric},
+        script::{Expr, Value},
+        utils::AtomicRange,
+    };
+
+    #[test]
+    fn key_follows_parameters() {
+        use data::ChannelLayout::{Discrete, Stereo};
+        let value = Arc::new(param_float!(0.5, "value", 0.0..=1.0));
+        let key_of = |start: f64, sample_rate, channels| {
+            content_hash(
+                &constant_region_of(&value, start, start + 0.5),
+                sample_rate,
+                channels,
+            )
+        };
+        let key = key_of(0.0, 1000, Stereo);
+        // moving the region does not change the samples.
+        assert_eq!(key, key_of(1.0, 1000, Stereo));
+        assert_ne!(key, key_of(0.0, 2000, Stereo));
+        // the mono generators are spread over the stereo channels, but not over the discrete ones.
+        assert_ne!(key, key_of(0.0, 1000, Discrete(2)));
+        value.set(0.25);
+        assert_ne!(key, key_of(0.0, 1000, Stereo));
+    }
+    #[test]
+    fn key_follows_files() {
+        let path = std::env::temp_dir().join("otopoiesis_render_cache_file.wav");
+        std::fs::write(&path, [0u8; 16]).unwrap();
+        let generator = Value::new_lazy(Expr::App(
+            Expr::Literal(Value::ExtFunction("fileplayer".to_string())).into(),
+            vec![Expr::Literal(Value::String(path.display().to_string()))],
+        ));
+        let region = data::Region::new(
+            AtomicRange::<f64>::new(0.0, 0.5),
+            data::Content::Generator(generator),
+            "file",
+        );
+        let key = content_hash(&region, 1000, data::ChannelLayout::Stereo);
+        // recorded again into the same path.
+        std::fs::write(&path, [0u8; 32]).unwrap();
+        assert_ne!(
+            key,
+            content_hash(&region, 1000, data::ChannelLayout::Stereo)
+        );
+        let _ = std::fs::remove_file(path);
+    }
+    #[test]
+    fn evict_least_recently_used() {
+        let cache = RenderCache::with_capacity(None, 4);
+        cache.insert(1, vec![0.0; 2]);
+        cache.insert(2, vec![0.0; 2]);
+        assert!(cache.get(1).is_some());
+        cache.insert(3, vec![0.0; 2]);
+        assert!(cache.get(2).is_none());
+        assert!(cache.get(1).is_some() && cache.get(3).is_some());
+        // larger than the capacity is not kept in memory.
+        cache.insert(4, vec![0.0; 5]);
+        assert!(cache.get(4).is_none());
+        assert!(cache.get(1).is_some());
+    }
+    #[test]
+    fn persist_on_disk() {
+        let dir = std::env::temp_dir().join("otopoiesis_render_cache_test");
+        let cache = RenderCache::new(Some(dir.clone()));
+        cache.insert(42, vec![0.0, 0.5, -1.0]);
+        cache.clear_memory();
+        assert_eq!(cache.get(42).as_deref(), Some(&vec![0.0, 0.5, -1.0]));
+        assert!(cache.get(43).is_none());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,1129 @@
+//! Streaming render of regions.
+//!
+//! A worker renders the region block by block ahead of the playhead into a look-ahead ring buffer,
+//! so that the memory does not grow with the length of the region and the playback starts without rendering the whole region.
+//! The regions share a few workers, which render a block of each region in turn.
+//! Regions with filters that need the whole region are rendered offline into the cache as before.
+//!
+//! When a parameter of the region is changed during the playback, the rendered samples ahead of the playhead are discarded
+//! except for a short margin, and the new samples are crossfaded into the old ones to avoid clicks.
+//! The regions rendered offline are rendered again in the worker pool, and crossfaded when the new samples arrive.
+
+use super::cache::{self, RenderCache};
+use crate::audio::pool::{JobHandle, RenderBatch};
+use crate::audio::{Component, PlaybackInfo};
+use crate::data::{self, ChannelLayout};
+use crate::parameter::{FloatParameter, Parameter};
+use crate::script::{Expr, Value};
+use crate::utils::AtomicRange;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Frames rendered at once by the worker.
+const BLOCK_FRAMES: usize = 1024;
+/// Frames rendered ahead of the playhead.
+const LOOKAHEAD_FRAMES: usize = BLOCK_FRAMES * 16;
+/// How long `prepare_play` waits for the first blocks of the regions.
+pub const PREFILL_TIMEOUT: Duration = Duration::from_millis(200);
+/// How long the workers sleep when none of the tasks had work to do.
+const IDLE_SLEEP: Duration = Duration::from_millis(2);
+/// Frames kept ahead of the playhead when the parameters changed, so that the reader does not run out while re-rendering.
+const SPLICE_MARGIN_FRAMES: usize = BLOCK_FRAMES;
+/// Length of the crossfade from the samples rendered with old parameters.
+const CROSSFADE_FRAMES: usize = 256;
+
+/// Interface for rendering a region block by block from any position.
+pub trait StreamComponent: Send + Sync {
+    /// Prepares to render from `frame` samples after the beginning of the region.
+    fn seek(&mut self, frame: usize, sample_rate: u32, channels: ChannelLayout);
+    /// Renders the next block and advances the position.
+    /// `input` is the input from the device for the block, which is empty when rendered ahead of time.
+    fn render_block(
+        &mut self,
+        input: &[f32],
+        dest: &mut [f32],
+        sample_rate: u32,
+        channels: ChannelLayout,
+    );
+}
+
+struct GeneratorStream {
+    generator: Box<dyn Component + Send + Sync>,
+    pos: usize,
+}
+
+impl StreamComponent for GeneratorStream {
+    fn seek(&mut self, frame: usize, sample_rate: u32, channels: ChannelLayout) {
+        self.pos = frame;
+        let info = PlaybackInfo {
+            sample_rate,
+            current_time: frame,
+            frame_per_buffer: BLOCK_FRAMES as u64,
+            channels,
+        };
+        self.generator.prepare_play(&info);
+    }
+    fn render_block(
+        &mut self,
+        input: &[f32],
+        dest: &mut [f32],
+        sample_rate: u32,
+        channels: ChannelLayout,
+    ) {
+        let frames = dest.len() / channels.count();
+        let info = PlaybackInfo {
+            sample_rate,
+            current_time: self.pos,
+            frame_per_buffer: frames as u64,
+            channels,
+        };
+        self.generator.render(input, dest, &info);
+        self.pos += frames;
+    }
+}
+
+/// Streaming version of [`super::FadeModel`]. The gain is calculated in the same manner so the result matches the offline render.
+struct FadeStream {
+    param: data::FadeParam,
+    origin: Box<dyn StreamComponent>,
+    /// length of the region in samples(not frames).
+    len: usize,
+    pos: usize,
+}
+
+impl FadeStream {
+    fn gain(&self, index: usize, sample_rate: u32, channels: usize) -> Option<f64> {
+        let in_time = (self.param.time_in.get() as f64 * sample_rate as f64) as usize;
+        let out_time = (self.param.time_out.get() as f64 * sample_rate as f64) as usize;
+        if out_time > 0 && index + out_time >= self.len && index < self.len {
+            Some(((self.len - 1 - index) / channels) as f64 / out_time as f64)
+        } else if index < in_time {
+            Some((index / channels) as f64 / in_time as f64)
+        } else {
+            None
+        }
+    }
+}
+
+impl StreamComponent for FadeStream {
+    fn seek(&mut self, frame: usize, sample_rate: u32, channels: ChannelLayout) {
+        self.pos = frame * channels.count();
+        self.origin.seek(frame, sample_rate, channels);
+    }
+    fn render_block(
+        &mut self,
+        input: &[f32],
+        dest: &mut [f32],
+        sample_rate: u32,
+        channels: ChannelLayout,
+    ) {
+        self.origin.render_block(input, dest, sample_rate, channels);
+        for (i, s) in dest.iter_mut().enumerate() {
+            if let Some(gain) = self.gain(self.pos + i, sample_rate, channels.count()) {
+                *s = (*s as f64 * gain) as f32;
+            }
+        }
+        self.pos += dest.len();
+    }
+}
+
+/// Returns the streaming component for the region, or `None` if the content needs to be rendered offline.
+pub fn get_stream_component(
+    region: &data::Region,
+    sample_rate: u32,
+    channels: ChannelLayout,
+) -> Option<Box<dyn StreamComponent>> {
+    match &region.content {
+        data::Content::Generator(g) => Some(Box::new(GeneratorStream {
+            generator: crate::audio::generator::get_component_for_value(g),
+            pos: 0,
+        })),
+        data::Content::Transformer(data::RegionFilter::FadeInOut(param), origin) => {
+            get_stream_component(origin, sample_rate, channels).map(|origin| {
+                let len =
+                    (region.range.getrange() * sample_rate as f64) as usize * channels.count();
+                Box::new(FadeStream {
+                    param: param.clone(),
+                    origin,
+                    len,
+                    pos: 0,
+                }) as Box<dyn StreamComponent>
+            })
+        }
+        data::Content::Transformer(..) => None,
+    }
+}
+
+fn collect_params_expr(expr: &Expr, dest: &mut Vec<Arc<FloatParameter>>) {
+    match expr {
+        Expr::Literal(v) => collect_params_value(v, dest),
+        Expr::Var(_) => {}
+        Expr::Let(_, body, then) => {
+            collect_params_expr(body, dest);
+            collect_params_expr(then, dest);
+        }
+        Expr::Lambda(_, body) => collect_params_expr(body, dest),
+        Expr::App(f, args) => {
+            collect_params_expr(f, dest);
+            args.iter().for_each(|a| collect_params_expr(a, dest));
+        }
+    }
+}
+fn collect_params_value(value: &Value, dest: &mut Vec<Arc<FloatParameter>>) {
+    match value {
+        Value::Parameter(p) => dest.push(p.clone()),
+        Value::Array(vs, _) => vs.iter().for_each(|v| collect_params_value(v, dest)),
+        Value::Function(_, body) | Value::Closure(_, _, body) => collect_params_expr(body, dest),
+        _ => {}
+    }
+}
+fn collect_params(region: &data::Region, dest: &mut Vec<Arc<FloatParameter>>) {
+    match &region.content {
+        data::Content::Generator(g) => collect_params_value(g, dest),
+        data::Content::Transformer(filter, origin) => {
+            if let data::RegionFilter::FadeInOut(param) = filter {
+                dest.push(param.time_in.clone());
+                dest.push(param.time_out.clone());
+            }
+            collect_params(origin, dest);
+        }
+    }
+}
+
+/// Detects the change of parameters used in the region.
+struct ParamWatch {
+    params: Vec<Arc<FloatParameter>>,
+    last: Vec<f32>,
+}
+
+impl ParamWatch {
+    fn new(region: &data::Region) -> Self {
+        let mut params = vec![];
+        collect_params(region, &mut params);
+        let last = params.iter().map(|p| p.get()).collect();
+        Self { params, last }
+    }
+    /// Returns true if any parameter changed since the last call.
+    fn changed(&mut self) -> bool {
+        let mut res = false;
+        for (p, last) in self.params.iter().zip(self.last.iter_mut()) {
+            let v = p.get();
+            if v != *last {
+                *last = v;
+                res = true;
+            }
+        }
+        res
+    }
+}
+
+/// Work of a region done in the streaming workers, stepped in turn with those of other regions.
+trait Task: Send {
+    /// Does a piece of the work. Returns false if there was nothing to do.
+    fn step(&mut self) -> bool;
+}
+
+struct Entry {
+    is_alive: Arc<AtomicBool>,
+    task: Mutex<Box<dyn Task>>,
+}
+
+/// Keeps the task running in the workers. Dropping the handle lets the workers drop the task.
+struct TaskHandle {
+    is_alive: Arc<AtomicBool>,
+}
+
+impl Drop for TaskHandle {
+    fn drop(&mut self) {
+        self.is_alive.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Tasks shared by the workers. Each worker steps the tasks not taken by the others.
+#[derive(Default)]
+struct Workers {
+    entries: Mutex<Vec<Arc<Entry>>>,
+}
+
+impl Workers {
+    /// The workers shared in the application, half as many as the available cores so that the rest are left for the
+    /// offline rendering.
+    fn global() -> &'static Arc<Self> {
+        static WORKERS: OnceLock<Arc<Workers>> = OnceLock::new();
+        WORKERS.get_or_init(|| {
+            let workers = Arc::new(Workers::default());
+            let n = std::thread::available_parallelism().map_or(2, |n| n.get().div_ceil(2));
+            for i in 0..n {
+                let workers = workers.clone();
+                std::thread::Builder::new()
+                    .name(format!("streaming worker {}", i))
+                    .spawn(move || workers.run_worker())
+                    .expect("failed to launch thread");
+            }
+            workers
+        })
+    }
+    fn spawn(&self, task: impl Task + 'static) -> TaskHandle {
+        let is_alive = Arc::new(AtomicBool::new(true));
+        self.entries.lock().unwrap().push(Arc::new(Entry {
+            is_alive: is_alive.clone(),
+            task: Mutex::new(Box::new(task)),
+        }));
+        TaskHandle { is_alive }
+    }
+    fn run_worker(&self) {
+        let mut entries = vec![];
+        loop {
+            entries.clone_from(&self.entries.lock().unwrap());
+            let mut busy = false;
+            for entry in entries.iter() {
+                if !entry.is_alive.load(Ordering::Relaxed) {
+                    continue;
+                }
+                if let Ok(mut task) = entry.task.try_lock() {
+                    busy |= task.step();
+                }
+            }
+            // the tasks of dropped regions are dropped here, out of the audio thread.
+            entries.clear();
+            self.entries
+                .lock()
+                .unwrap()
+                .retain(|e| e.is_alive.load(Ordering::Relaxed));
+            if !busy {
+                std::thread::sleep(IDLE_SLEEP);
+            }
+        }
+    }
+}
+
+/// Header of the block pushed after its samples.
+#[derive(Clone, Copy, Debug)]
+struct BlockHeader {
+    generation: u64,
+    /// position in frames from the beginning of the region.
+    start: usize,
+    frames: usize,
+}
+
+/// States shared between the reader and the worker.
+struct Control {
+    /// incremented by the reader when it discards the rendered blocks.
+    /// Blocks of older generations are skipped by the reader.
+    generation: AtomicU64,
+    /// position where the worker restarts rendering for the new generation.
+    restart_pos: AtomicUsize,
+    /// set by the worker when parameters of the region changed.
+    params_changed: AtomicBool,
+}
+
+/// The loop range of the timeline seen from the region, so that the worker can render across the loop jump ahead.
+#[derive(Clone)]
+struct LoopPoints {
+    markers: data::Markers,
+    range: AtomicRange<f64>,
+    sample_rate: u32,
+    /// length of the region in frames.
+    len: usize,
+}
+
+impl LoopPoints {
+    /// Returns the loop start and end in frames from the beginning of the region, if the loop jumps inside the region.
+    fn get(&self) -> Option<(usize, usize)> {
+        let (start, end) = self.markers.loop_range.get_samples(self.sample_rate)?;
+        let offset = (self.range.start() * self.sample_rate as f64) as usize;
+        let to_local = |t: usize| t.saturating_sub(offset).min(self.len);
+        let (start, end) = (to_local(start), to_local(end));
+        (end > start).then_some((start, end))
+    }
+    /// Position in frames where the playhead goes after `pos`, if it jumps.
+    fn jump_from(&self, pos: usize) -> Option<usize> {
+        self.get()
+            .and_then(|(start, end)| (pos == end).then_some(start))
+    }
+    /// The end of the continuous part from `pos`.
+    fn continuous_end(&self, pos: usize) -> usize {
+        match self.get() {
+            Some((_start, end)) if pos < end => end,
+            _ => self.len,
+        }
+    }
+}
+/// Renders the region ahead of the reader of [`StreamingRegion`].
+struct StreamTask {
+    component: Box<dyn StreamComponent>,
+    control: Arc<Control>,
+    loop_points: LoopPoints,
+    watch: ParamWatch,
+    samples: ringbuf::HeapProducer<f32>,
+    headers: ringbuf::HeapProducer<BlockHeader>,
+    buffer: Vec<f32>,
+    sample_rate: u32,
+    channels: ChannelLayout,
+    generation: Option<u64>,
+    write_pos: usize,
+}
+
+impl Task for StreamTask {
+    fn step(&mut self) -> bool {
+        let (sample_rate, channels) = (self.sample_rate, self.channels);
+        let chs = channels.count();
+        let target = self.control.generation.load(Ordering::Acquire);
+        if self.generation != Some(target) {
+            self.write_pos = self.control.restart_pos.load(Ordering::Relaxed);
+            self.component.seek(self.write_pos, sample_rate, channels);
+            self.generation = Some(target);
+            self.watch.changed();
+        }
+        if self.watch.changed() {
+            // the reader discards the blocks rendered with old parameters.
+            self.control.params_changed.store(true, Ordering::Relaxed);
+        }
+        let segment_end = self.loop_points.continuous_end(self.write_pos);
+        let frames = BLOCK_FRAMES.min(segment_end.saturating_sub(self.write_pos));
+        if frames == 0 {
+            return match self.loop_points.jump_from(self.write_pos) {
+                // continue rendering from the loop start as the playhead will do.
+                Some(next) if next != self.write_pos => {
+                    self.component.seek(next, sample_rate, channels);
+                    self.write_pos = next;
+                    true
+                }
+                _ => false,
+            };
+        }
+        if self.samples.free_len() < frames * chs || self.headers.is_full() {
+            return false;
+        }
+        let block = &mut self.buffer[..frames * chs];
+        self.component
+            .render_block(&[], block, sample_rate, channels);
+        // the header is pushed after the samples so that the reader finds the samples of the header.
+        self.samples.push_slice(block);
+        let _ = self.headers.push(BlockHeader {
+            generation: target,
+            start: self.write_pos,
+            frames,
+        });
+        self.write_pos += frames;
+        true
+    }
+}
+
+/// Samples held by the reader when the parameters changed.
+/// The samples until `splice_at` are played as they are, and the rest are crossfaded into the new samples.
+struct Held {
+    samples: Vec<f32>,
+    /// position of the first held frame.
+    start: usize,
+    frames: usize,
+    splice_at: usize,
+}
+
+impl Held {
+    fn fade_end(&self) -> usize {
+        self.start + self.frames
+    }
+    fn clear(&mut self) {
+        self.frames = 0;
+        self.splice_at = self.start;
+    }
+}
+
+/// Whether the region takes the live input. Such regions are rendered in the audio thread with the input of the block.
+pub fn is_live(region: &data::Region) -> bool {
+    match &region.content {
+        data::Content::Generator(g) => crate::audio::generator::input::is_live(g),
+        data::Content::Transformer(_, origin) => is_live(origin),
+    }
+}
+
+/// A region processing the live input. Rendered in the audio thread as the input cannot be read ahead.
+pub struct LiveRegion {
+    pub params: data::Region,
+    component: Box<dyn StreamComponent>,
+    sample_rate: u32,
+    channels: ChannelLayout,
+    /// the position where the next block continues from without seeking.
+    next_pos: Option<usize>,
+}
+
+impl std::fmt::Debug for LiveRegion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LiveRegion")
+            .field("params", &self.params)
+            .finish()
+    }
+}
+
+impl LiveRegion {
+    pub fn new(
+        params: data::Region,
+        component: Box<dyn StreamComponent>,
+        info: &PlaybackInfo,
+    ) -> Self {
+        Self {
+            params,
+            component,
+            sample_rate: info.sample_rate,
+            channels: info.channels,
+            next_pos: None,
+        }
+    }
+    pub fn get_len(&self) -> usize {
+        (self.params.range.getrange() * self.sample_rate as f64) as usize
+    }
+    /// Processes the `input` of the block into `dest`, from `pos` frames after the beginning of the region.
+    pub fn read(&mut self, pos: usize, input: &[f32], dest: &mut [f32]) {
+        if self.next_pos != Some(pos) {
+            self.component.seek(pos, self.sample_rate, self.channels);
+        }
+        self.component
+            .render_block(input, dest, self.sample_rate, self.channels);
+        self.next_pos = Some(pos + dest.len() / self.channels.count());
+    }
+}
+
+/// A region rendered by the streaming workers ahead of the playhead.
+/// The reader side is owned by the audio thread, and communicates with the worker without locks and allocations.
+pub struct StreamingRegion {
+    pub params: data::Region,
+    channels: ChannelLayout,
+    loop_points: LoopPoints,
+    control: Arc<Control>,
+    samples: ringbuf::HeapConsumer<f32>,
+    headers: ringbuf::HeapConsumer<BlockHeader>,
+    /// the rest of the block being read.
+    current: Option<BlockHeader>,
+    generation: u64,
+    held: Held,
+    /// frames to be rendered before the playback starts.
+    prefill: usize,
+    _task: TaskHandle,
+}
+
+impl std::fmt::Debug for StreamingRegion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamingRegion")
+            .field("params", &self.params)
+            .finish()
+    }
+}
+
+impl StreamingRegion {
+    /// Lets the workers render from the playhead. The first block is waited for by [`Self::wait_prefill`].
+    pub fn new(
+        params: data::Region,
+        component: Box<dyn StreamComponent>,
+        markers: &data::Markers,
+        info: &PlaybackInfo,
+    ) -> Self {
+        let sample_rate = info.sample_rate;
+        let channels = info.channels;
+        let chs = channels.count();
+        let len = (params.range.getrange() * sample_rate as f64) as usize;
+        let start = (params.range.start() * sample_rate as f64) as usize;
+        let pos = info.current_time.saturating_sub(start).min(len);
+        let loop_points = LoopPoints {
+            markers: markers.clone(),
+            range: params.range.clone(),
+            sample_rate,
+            len,
+        };
+        let control = Arc::new(Control {
+            generation: AtomicU64::new(0),
+            restart_pos: AtomicUsize::new(pos),
+            params_changed: AtomicBool::new(false),
+        });
+        let (samples_w, samples) = ringbuf::HeapRb::new(LOOKAHEAD_FRAMES * chs).split();
+        let (headers_w, headers) =
+            ringbuf::HeapRb::new(LOOKAHEAD_FRAMES / BLOCK_FRAMES * 2).split();
+        let task = Workers::global().spawn(StreamTask {
+            component,
+            control: control.clone(),
+            loop_points: loop_points.clone(),
+            watch: ParamWatch::new(&params),
+            samples: samples_w,
+            headers: headers_w,
+            buffer: vec![0.0f32; BLOCK_FRAMES * chs],
+            sample_rate,
+            channels,
+            generation: None,
+            write_pos: 0,
+        });
+        let prefill = BLOCK_FRAMES.min(loop_points.continuous_end(pos) - pos);
+        Self {
+            params,
+            channels,
+            loop_points,
+            control,
+            samples,
+            headers,
+            current: None,
+            generation: 0,
+            held: Held {
+                samples: vec![0.0; (SPLICE_MARGIN_FRAMES + CROSSFADE_FRAMES) * chs],
+                start: 0,
+                frames: 0,
+                splice_at: 0,
+            },
+            prefill,
+            _task: task,
+        }
+    }
+    /// Waits until the first block from the playhead is rendered or the `deadline` passes.
+    /// The regions are rendered in parallel, so they are waited for with the same deadline.
+    pub fn wait_prefill(&self, deadline: Instant) {
+        while Instant::now() < deadline && self.buffered_frames() < self.prefill {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+    /// Frames rendered for the current generation.
+    fn buffered_frames(&self) -> usize {
+        let current = self.current.iter();
+        let queued = self.headers.iter();
+        current
+            .chain(queued)
+            .filter(|h| h.generation == self.generation)
+            .map(|h| h.frames)
+            .sum()
+    }
+    /// length in frames.
+    pub fn get_len(&self) -> usize {
+        self.loop_points.len
+    }
+    /// Discards the rendered blocks and lets the worker restart from `pos`.
+    fn restart(&mut self, pos: usize) {
+        // make room for the worker. The samples of the block being pushed are skipped later with its header.
+        while let Some(h) = self.current.take().or_else(|| self.headers.pop()) {
+            self.samples.skip(h.frames * self.channels.count());
+        }
+        self.generation += 1;
+        self.control.restart_pos.store(pos, Ordering::Relaxed);
+        self.control
+            .generation
+            .store(self.generation, Ordering::Release);
+    }
+    /// Returns the block of the current generation to be read next.
+    fn next_header(&mut self) -> Option<BlockHeader> {
+        loop {
+            let h = self.current.take().or_else(|| self.headers.pop())?;
+            if h.generation == self.generation && h.frames > 0 {
+                self.current = Some(h);
+                return Some(h);
+            }
+            self.samples.skip(h.frames * self.channels.count());
+        }
+    }
+    /// Consumes `frames` frames of the current block, copying them into `dest` if given.
+    fn consume(&mut self, frames: usize, dest: Option<&mut [f32]>) {
+        let chs = self.channels.count();
+        match dest {
+            Some(dest) => {
+                self.samples.pop_slice(&mut dest[..frames * chs]);
+            }
+            None => {
+                self.samples.skip(frames * chs);
+            }
+        }
+        if let Some(h) = self.current.as_mut() {
+            h.start += frames;
+            h.frames -= frames;
+        }
+    }
+    /// Holds the samples from `pos` and lets the worker render again with new parameters after the margin.
+    fn splice(&mut self, pos: usize) {
+        let chs = self.channels.count();
+        let capacity = self.held.samples.len() / chs;
+        let mut frames = 0;
+        while frames < capacity {
+            match self.next_header() {
+                Some(h) if h.start == pos + frames => {
+                    let n = h.frames.min(capacity - frames);
+                    let mut held = std::mem::take(&mut self.held.samples);
+                    self.consume(n, Some(&mut held[frames * chs..]));
+                    self.held.samples = held;
+                    frames += n;
+                }
+                _ => break,
+            }
+        }
+        self.held.start = pos;
+        self.held.frames = frames;
+        self.held.splice_at = pos + frames.min(SPLICE_MARGIN_FRAMES);
+        self.restart(self.held.splice_at);
+    }
+    /// Reads the held samples before the splice point. Returns the number of frames read.
+    fn read_held(&self, pos: usize, dest: &mut [f32]) -> usize {
+        let chs = self.channels.count();
+        if pos < self.held.start || pos >= self.held.splice_at {
+            return 0;
+        }
+        let n = (self.held.splice_at - pos).min(dest.len() / chs);
+        let offset = (pos - self.held.start) * chs;
+        dest[..n * chs].copy_from_slice(&self.held.samples[offset..offset + n * chs]);
+        n
+    }
+    /// Crossfades the held samples after the splice point into the new samples from `pos`.
+    fn crossfade_held(&self, pos: usize, dest: &mut [f32]) {
+        let chs = self.channels.count();
+        let fade_len = self.held.fade_end() - self.held.splice_at;
+        for (i, frame) in dest.chunks_mut(chs).enumerate() {
+            let p = pos + i;
+            if p < self.held.splice_at || p >= self.held.fade_end() {
+                continue;
+            }
+            let gain = (p - self.held.splice_at) as f32 / fade_len as f32;
+            let offset = (p - self.held.start) * chs;
+            let old = &self.held.samples[offset..offset + chs];
+            frame
+                .iter_mut()
+                .zip(old.iter())
+                .for_each(|(n, o)| *n = *o * (1.0 - gain) + *n * gain);
+        }
+    }
+    /// Reads the samples from `pos` frames after the beginning of the region. Fills silence if the worker is late.
+    pub fn read(&mut self, pos: usize, dest: &mut [f32]) {
+        let chs = self.channels.count();
+        let frames = dest.len() / chs;
+        if pos < self.held.start || pos > self.held.fade_end() {
+            self.held.clear();
+        }
+        if self.control.params_changed.swap(false, Ordering::Relaxed) {
+            self.splice(pos);
+        }
+        let mut written = 0;
+        let mut restarted = false;
+        while written < frames {
+            let p = pos + written;
+            let n = self.read_held(p, &mut dest[written * chs..]);
+            if n > 0 {
+                written += n;
+                continue;
+            }
+            let Some(h) = self.next_header() else { break };
+            let end = h.start + h.frames;
+            if h.start != p {
+                if h.start < p && p < end {
+                    self.consume(p - h.start, None);
+                } else if end <= p && self.loop_points.jump_from(end).is_none() {
+                    // the playhead went past the block.
+                    self.consume(h.frames, None);
+                } else {
+                    self.restart(p);
+                    restarted = true;
+                    break;
+                }
+                continue;
+            }
+            let n = h.frames.min(frames - written);
+            let block = &mut dest[written * chs..(written + n) * chs];
+            self.consume(n, Some(block));
+            self.crossfade_held(p, block);
+            written += n;
+        }
+        dest[written * chs..].fill(0.0);
+        if written < frames && !restarted {
+            // the worker is late. let it restart from the next position rather than catching up.
+            self.restart(pos + frames);
+            log::debug!("streaming region {} underrun", self.params.label);
+        }
+    }
+}
+
+/// Renders the region of [`RenderedRegion`] again in the worker pool when the parameters changed.
+struct RenderTask {
+    region: data::Region,
+    info: PlaybackInfo,
+    watch: ParamWatch,
+    cache: Option<Arc<RenderCache>>,
+    batch: Arc<RenderBatch>,
+    job: Option<JobHandle<super::Model>>,
+    rendered: ringbuf::HeapProducer<Box<super::Model>>,
+    retired: ringbuf::HeapConsumer<Box<super::Model>>,
+}
+
+impl RenderTask {
+    fn spawn_job(&self) -> JobHandle<super::Model> {
+        let channels = self.info.channels;
+        let key = cache::content_hash(&self.region, self.info.sample_rate, channels);
+        let cache = self.cache.clone();
+        let model = super::Model::new(self.region.clone(), channels);
+        super::render_region_offline_async(model, &self.info, &self.batch, move |model| {
+            if let Some(cache) = cache {
+                cache.insert(key, model.interleaved_samples_cache.clone());
+            }
+        })
+    }
+}
+
+impl Task for RenderTask {
+    fn step(&mut self) -> bool {
+        let mut busy = false;
+        while let Some(old) = self.retired.pop() {
+            drop(old);
+            busy = true;
+        }
+        if self.watch.changed() {
+            // the job with the old parameters is cancelled unless it has started.
+            self.job = Some(self.spawn_job());
+            busy = true;
+        }
+        if let Some(model) = self.job.as_ref().and_then(|job| job.try_take()) {
+            self.job = None;
+            let _ = self.rendered.push(Box::new(model));
+            busy = true;
+        }
+        busy
+    }
+}
+
+/// A region rendered offline as a whole in the worker pool. Rendered again when its parameters changed while playing,
+/// playing the samples rendered before until the new ones arrive.
+pub struct RenderedRegion {
+    pub params: data::Region,
+    channels: usize,
+    current: Option<Box<super::Model>>,
+    /// crossfaded into `current` from the position it arrived.
+    previous: Option<Box<super::Model>>,
+    /// frames of the crossfade done.
+    fade: usize,
+    rendered: ringbuf::HeapConsumer<Box<super::Model>>,
+    /// the models replaced are dropped by the task, out of the audio thread.
+    retired: ringbuf::HeapProducer<Box<super::Model>>,
+    _task: TaskHandle,
+}
+
+impl std::fmt::Debug for RenderedRegion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RenderedRegion")
+            .field("params", &self.params)
+            .finish()
+    }
+}
+
+impl RenderedRegion {
+    /// Plays the samples `rendered` before, or silence until the region is rendered in the worker pool.
+    pub fn new(
+        params: data::Region,
+        rendered: Option<super::Model>,
+        info: &PlaybackInfo,
+        cache: Option<Arc<RenderCache>>,
+        batch: Arc<RenderBatch>,
+    ) -> Self {
+        let (rendered_w, rendered_r) = ringbuf::HeapRb::new(2).split();
+        let (retired_w, retired_r) = ringbuf::HeapRb::new(4).split();
+        let mut task = RenderTask {
+            region: params.clone(),
+            info: info.clone(),
+            watch: ParamWatch::new(&params),
+            cache,
+            batch,
+            job: None,
+            rendered: rendered_w,
+            retired: retired_r,
+        };
+        if rendered.is_none() {
+            task.job = Some(task.spawn_job());
+        }
+        Self {
+            params,
+            channels: info.channels.count(),
+            current: rendered.map(Box::new),
+            previous: None,
+            fade: 0,
+            rendered: rendered_r,
+            retired: retired_w,
+            _task: Workers::global().spawn(task),
+        }
+    }
+    /// Hands the previous model to the task, or keeps it while the queue is full so that it is never freed on the
+    /// audio thread. Returns true if nothing is left in `previous`.
+    fn retire_previous(&mut self) -> bool {
+        if let Some(previous) = self.previous.take() {
+            if let Err(previous) = self.retired.push(previous) {
+                self.previous = Some(previous);
+                return false;
+            }
+        }
+        true
+    }
+    /// length in frames.
+    pub fn get_len(&self) -> usize {
+        self.current
+            .as_ref()
+            .map_or(0, |m| m.interleaved_samples_cache.len() / self.channels)
+    }
+    /// Reads the samples from `pos` frames after the beginning of the region.
+    pub fn read(&mut self, pos: usize, dest: &mut [f32]) {
+        let chs = self.channels;
+        // the new samples wait in their queue until the previous ones are retired.
+        if !self.rendered.is_empty() && self.retire_previous() {
+            self.previous = self.current.take();
+            self.current = self.rendered.pop();
+            self.fade = 0;
+        }
+        let sample = |model: &Option<Box<super::Model>>, i: usize| {
+            model
+                .as_ref()
+                .and_then(|m| m.interleaved_samples_cache.get(i).copied())
+                .unwrap_or(0.0)
+        };
+        for (i, s) in dest.iter_mut().enumerate() {
+            *s = sample(&self.current, pos * chs + i);
+        }
+        if self.previous.is_none() {
+            return;
+        }
+        for (i, frame) in dest.chunks_mut(chs).enumerate() {
+            if self.fade >= CROSSFADE_FRAMES {
+                break;
+            }
+            let gain = self.fade as f32 / CROSSFADE_FRAMES as f32;
+            for (ch, s) in frame.iter_mut().enumerate() {
+                let old = sample(&self.previous, (pos + i) * chs + ch);
+                *s = old * (1.0 - gain) + *s * gain;
+            }
+            self.fade += 1;
+        }
+        if self.fade >= CROSSFADE_FRAMES {
+            self.retire_previous();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio::fixture::{constant_region, constant_region_of};
+    use crate::{param_float, parameter::RangedNumeric};
+
+    fn wait_until(mut cond: impl FnMut() -> bool) {
+        let started = Instant::now();
+        while !cond() {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "worker is too slow"
+            );
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+    fn wait_filled(stream: &StreamingRegion, frames: usize) {
+        wait_until(|| stream.buffered_frames() >= frames);
+    }
+    #[test]
+    fn same_as_offline() {
+        let (sample_rate, channels) = (48000, ChannelLayout::Stereo);
+        let range = AtomicRange::<f64>::new(0.1, 0.2);
+        let generator = Value::new_lazy(Expr::App(
+            Expr::Literal(Value::ExtFunction("sinewave".to_string())).into(),
+            vec![
+                Expr::Literal(Value::Parameter(Arc::new(param_float!(
+                    440.0,
+                    "freq",
+                    20.0..=20000.0
+                )))),
+                Expr::Literal(Value::Parameter(Arc::new(param_float!(
+                    1.0,
+                    "amp",
+                    0.0..=1.0
+                )))),
+                Expr::Literal(Value::Parameter(Arc::new(param_float!(
+                    0.0,
+                    "phase",
+                    0.0..=1.0
+                )))),
+            ],
+        ));
+        let fade_param = data::FadeParam::new_with(
+            Arc::new(FloatParameter::new(0.01, "time_in").set_range(0.0..=1000.0)),
+            Arc::new(FloatParameter::new(0.05, "time_out").set_range(0.0..=1000.0)),
+        );
+        let region = data::Region::new(
+            range.clone(),
+            data::Content::Transformer(
+                data::RegionFilter::FadeInOut(fade_param),
+                Box::new(data::Region::new(
+                    range,
+                    data::Content::Generator(generator),
+                    "generator",
+                )),
+            ),
+            "fade",
+        );
+        let mut offline = crate::audio::region::Model::new(region.clone(), channels);
+        offline.render_offline(sample_rate, channels);
+
+        let info = PlaybackInfo {
+            sample_rate,
+            current_time: 0,
+            frame_per_buffer: 256,
+            channels,
+        };
+        let component = get_stream_component(&region, sample_rate, channels).unwrap();
+        let mut stream = StreamingRegion::new(region, component, &data::Markers::default(), &info);
+        let len = stream.get_len();
+        wait_filled(&stream, len);
+        let mut streamed = vec![0.0f32; len * channels.count()];
+        for (i, block) in streamed.chunks_mut(256 * channels.count()).enumerate() {
+            stream.read(i * 256, block);
+        }
+        assert_eq!(streamed, offline.interleaved_samples_cache);
+    }
+    #[test]
+    fn regions_share_workers() {
+        let (sample_rate, channels) = (48000, ChannelLayout::Stereo);
+        let info = PlaybackInfo {
+            sample_rate,
+            current_time: 0,
+            frame_per_buffer: 256,
+            channels,
+        };
+        // more regions than the workers.
+        let streams = (0..32)
+            .map(|i| {
+                let value = Arc::new(param_float!(i as f32 / 32.0, "value", 0.0..=1.0));
+                let region = constant_region_of(&value, 0.0, 1.0);
+                let component = get_stream_component(&region, sample_rate, channels).unwrap();
+                StreamingRegion::new(region, component, &data::Markers::default(), &info)
+            })
+            .collect::<Vec<_>>();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        streams.iter().for_each(|s| s.wait_prefill(deadline));
+        for (i, mut stream) in streams.into_iter().enumerate() {
+            wait_filled(&stream, LOOKAHEAD_FRAMES);
+            let mut block = vec![0.0f32; 256 * channels.count()];
+            stream.read(0, &mut block);
+            assert!(block.iter().all(|s| *s == i as f32 / 32.0));
+        }
+    }
+    #[test]
+    fn param_change_is_crossfaded() {
+        let (sample_rate, channels) = (48000, ChannelLayout::Stereo);
+        let value = Arc::new(param_float!(0.5, "value", 0.0..=1.0));
+        let region = constant_region_of(&value, 0.0, 1.0);
+        let info = PlaybackInfo {
+            sample_rate,
+            current_time: 0,
+            frame_per_buffer: 256,
+            channels,
+        };
+        let component = get_stream_component(&region, sample_rate, channels).unwrap();
+        let mut stream = StreamingRegion::new(region, component, &data::Markers::default(), &info);
+        wait_filled(&stream, LOOKAHEAD_FRAMES);
+        let mut block = vec![0.0f32; 256 * channels.count()];
+        stream.read(0, &mut block);
+        assert!(block.iter().all(|s| *s == 0.5));
+
+        value.set(1.0);
+        wait_until(|| stream.control.params_changed.load(Ordering::Relaxed));
+        // the samples are spliced at the next read.
+        stream.read(256, &mut block);
+        assert!(block.iter().all(|s| *s == 0.5));
+        wait_filled(&stream, LOOKAHEAD_FRAMES / 2);
+        let splice_at = 256 + SPLICE_MARGIN_FRAMES;
+        let frames = splice_at - 512 + CROSSFADE_FRAMES * 2;
+        let mut out = vec![0.0f32; frames * channels.count()];
+        stream.read(512, &mut out);
+        let left = out.iter().step_by(channels.count()).collect::<Vec<_>>();
+        // the margin is played with the old value, then crossfaded into the new value without a jump.
+        let fade_start = splice_at - 512;
+        assert!(left[..fade_start].iter().all(|s| **s == 0.5));
+        assert!(left[fade_start + CROSSFADE_FRAMES..]
+            .iter()
+            .all(|s| **s == 1.0));
+        let max_step = 0.5 / CROSSFADE_FRAMES as f32 + f32::EPSILON;
+        assert!(left.windows(2).all(|w| (w[1] - w[0]).abs() <= max_step));
+    }
+    #[test]
+    fn rendered_again_on_param_change() {
+        let channels = ChannelLayout::Stereo;
+        let value = Arc::new(param_float!(0.5, "value", 0.0..=1.0));
+        let region = constant_region_of(&value, 0.0, 1.0);
+        let info = PlaybackInfo {
+            sample_rate: 1000,
+            current_time: 0,
+            frame_per_buffer: 100,
+            channels,
+        };
+        let mut rendered =
+            RenderedRegion::new(region, None, &info, None, Arc::new(RenderBatch::new()));
+        let mut block = vec![0.0f32; 100 * channels.count()];
+        wait_until(|| {
+            rendered.read(0, &mut block);
+            rendered.get_len() == 1000
+        });
+        assert!(block.iter().all(|s| *s == 0.5));
+
+        value.set(1.0);
+        // the old samples are played until the new ones arrive, then crossfaded.
+        wait_until(|| {
+            rendered.read(100, &mut block);
+            rendered.previous.is_some()
+        });
+        assert_eq!(block[0], 0.5);
+        let left = block.iter().step_by(2).collect::<Vec<_>>();
+        assert!(left.windows(2).all(|w| w[0] <= w[1] && w[1] - w[0] < 0.01));
+        let mut rest = vec![0.0f32; CROSSFADE_FRAMES * channels.count()];
+        rendered.read(200, &mut rest);
+        assert!(rendered.previous.is_none());
+        assert_eq!(rest.last(), Some(&1.0));
+    }
+    #[test]
+    fn keep_retired_while_queue_full() {
+        let channels = ChannelLayout::Stereo;
+        let model = |v: f32| {
+            let mut model = super::super::Model::new(constant_region(v, 0.0, 1.0), channels);
+            model.interleaved_samples_cache = vec![v; 2000];
+            Box::new(model)
+        };
+        let info = PlaybackInfo {
+            sample_rate: 1000,
+            current_time: 0,
+            frame_per_buffer: 100,
+            channels,
+        };
+        let region = constant_region(0.0, 0.0, 1.0);
+        let first = Some(*model(0.0));
+        let mut rendered =
+            RenderedRegion::new(region, first, &info, None, Arc::new(RenderBatch::new()));
+        // the task is replaced by the queues not collected.
+        let (mut rendered_w, rendered_r) = ringbuf::HeapRb::new(2).split();
+        let (retired_w, mut retired_r) = ringbuf::HeapRb::new(1).split();
+        (rendered.rendered, rendered.retired) = (rendered_r, retired_w);
+        let mut block = vec![0.0f32; CROSSFADE_FRAMES * channels.count()];
+        for v in [1.0, 2.0] {
+            assert!(rendered_w.push(model(v)).is_ok());
+            rendered.read(0, &mut block);
+        }
+        assert!(retired_r.is_full());
+        assert_eq!(
+            rendered
+                .previous
+                .as_ref()
+                .map(|m| m.interleaved_samples_cache[0]),
+            Some(1.0)
+        );
+        // the next one waits while the previous one can not be retired.
+        assert!(rendered_w.push(model(3.0)).is_ok());
+        rendered.read(0, &mut block);
+        assert_eq!(block.last(), Some(&2.0));
+        assert_eq!(
+            retired_r.pop().map(|m| m.interleaved_samples_cache[0]),
+            Some(0.0)
+        );
+        rendered.read(0, &mut block);
+        assert_eq!(
+            retired_r.pop().map(|m| m.interleaved_samples_cache[0]),
+            Some(1.0)
+        );
+        assert_eq!(
+            rendered
+                .current
+                .as_ref()
+                .map(|m| m.interleaved_samples_cache[0]),
+            Some(3.0)
+        );
+    }
+}
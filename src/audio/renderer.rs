@@ -1,124 +1,34 @@
+use crate::audio::backend::{self, Backend, StreamConfig};
+use crate::audio::handoff::Handoff;
+use crate::audio::mixer::Mixer;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::audio::recorder::{RecordedTake, Recorder};
+use crate::audio::resampler::{self, Resampler};
 use crate::audio::{Component, PlaybackInfo};
-use crate::data;
+use crate::data::{self, ChannelLayout};
 use crate::utils::{atomic, SimpleAtomic};
 
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{self, Stream};
 use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 pub trait RendererBase<E>
 where
     E: Component + Send + Sync + 'static,
 {
-    fn get_host(&self) -> &cpal::Host;
-    fn set_streams(
-        &mut self,
-        i: Option<Stream>,
-        o: Option<Stream>,
-        iconfig: Option<cpal::StreamConfig>,
-        oconfig: Option<cpal::StreamConfig>,
-    );
-    fn get_instream(&self) -> &Option<Stream>;
-    fn get_outstream(&self) -> &Option<Stream>;
-    fn init(
-        &mut self,
-
-        sample_rate: Option<u32>,
-
-        imodel: Arc<Mutex<InputModel>>,
-        omodel: Arc<Mutex<OutputModel<E>>>,
-    ) {
-        let host = cpal::default_host();
-        let idevice = host.default_input_device();
-        let (iconfig, istream) = if let Some(device) = idevice.as_ref() {
-            let iconfig_builder = device
-                .supported_input_configs()
-                .unwrap()
-                .next()
-                .expect("no supported config?!");
-            let iconfig = if let Some(sr) = sample_rate {
-                iconfig_builder
-                    .with_sample_rate(cpal::SampleRate(sr))
-                    .config()
-            } else {
-                iconfig_builder.with_max_sample_rate().config()
-            };
-            let c = iconfig.clone();
-            let in_stream = device.build_input_stream(
-                &iconfig,
-                move |data: &[f32], _s: &cpal::InputCallbackInfo| {
-                    pass_in(imodel.clone(), data, c.clone())
-                },
-                |_e| {},
-                None,
-            );
-            let _ = in_stream.as_ref().map(|i| i.pause());
-            // if let Err(e) = &in_stream {
-            //     web_sys::console::log_1(&e.to_string().into())
-            // }
-            (Some(iconfig), in_stream.ok())
-        } else {
-            (None, None)
-        };
-
-        let odevice = host.default_output_device();
-        log::debug!("device {:?}", odevice.as_ref().map(|d| d.name()));
-        let (oconfig, ostream) = if let Some(device) = odevice.as_ref() {
-            let oconfig_builder = device
-                .supported_output_configs()
-                .unwrap()
-                .find(|c| c.channels() >= 2)
-                .expect("no supported config?!");
-            let mut oconfig = if let Some(sr) = sample_rate {
-                oconfig_builder
-                    .with_sample_rate(cpal::SampleRate(sr))
-                    .config()
-            } else {
-                oconfig_builder.with_max_sample_rate().config()
-            };
-            assert_eq!(oconfig.channels, 2);
-            oconfig.buffer_size = cpal::BufferSize::Fixed(super::DEFAULT_BUFFER_LEN as u32);
-            let oc = oconfig.clone();
-            let out_stream = device.build_output_stream(
-                &oconfig,
-                move |data: &mut [f32], _s: &cpal::OutputCallbackInfo| {
-                    pass_out(omodel.clone(), data, oc.clone())
-                },
-                |_e| {},
-                None,
-            );
-            // if let Err(e) = &out_stream {
-            //     web_sys::console::log_1(&e.to_string().into())
-            // }
-            let _ = out_stream.as_ref().map(|o| o.pause());
-            (Some(oconfig), out_stream.ok())
-        } else {
-            (None, None)
-        };
-        assert!(oconfig.is_some());
-        self.set_streams(istream, ostream, iconfig, oconfig);
-    }
+    fn get_backend(&self) -> &dyn Backend;
+    fn get_backend_mut(&mut self) -> &mut dyn Backend;
     fn prepare_play(&mut self);
     fn is_playing(&self) -> bool;
-    fn get_samplerate(&self) -> u32;
+    fn get_samplerate(&self) -> u32 {
+        self.get_backend().get_config().sample_rate
+    }
     fn play(&mut self);
     fn pause(&mut self);
     fn play_audio(&mut self) {
-        if let Some(is) = self.get_instream() {
-            is.play().unwrap();
-        }
-        if let Some(os) = self.get_outstream() {
-            os.play().unwrap();
-        }
+        self.get_backend_mut().play();
     }
     fn pause_audio(&mut self) {
-        if let Some(is) = self.get_instream() {
-            is.pause().unwrap();
-        }
-        if let Some(os) = self.get_outstream() {
-            os.pause().unwrap();
-        }
+        self.get_backend_mut().pause();
     }
     fn toggle_play(&mut self) {
         if self.is_playing() {
@@ -131,111 +41,297 @@ where
     fn get_current_time_in_sample(&self) -> u64;
     fn get_current_time(&self) -> std::time::Duration {
         let now = self.get_current_time_in_sample();
-        let now_f64 = self.get_outstream().as_ref().map_or(0., |_os| {
-            let sr = self.get_samplerate();
-            now as f64 / sr as f64
-        });
-        std::time::Duration::from_secs_f64(now_f64)
+        let sr = self.get_samplerate();
+        std::time::Duration::from_secs_f64(now as f64 / sr as f64)
     }
 }
 
-pub struct InputModel {
-    pub producer: HeapProducer<f32>,
-}
-
+/// States used by the output callback. Lives in the [`Handoff`] so that the audio thread accesses it without locks.
 pub struct OutputModel<E: Component + Sync + Send> {
     pub consumer: HeapConsumer<f32>,
+    /// number of channels of the input device, which the input callback updates.
+    pub input_channels: Arc<atomic::U16>,
+    /// buffer for the device which does not have 2 channels.
     pub internal_buf: Vec<f32>,
+    /// from the stereo master into the channels of the device.
+    pub device_mixer: Mixer,
+    /// buffer for the input from the device.
+    pub input_buf: Vec<f32>,
+    /// buffer for the output of the old effector while crossfading into the new one.
+    pub fade_buf: Vec<f32>,
     pub effector: E,
+    /// new effectors sent from [`Renderer::replace_effector`], swapped at the block boundary.
+    pub incoming: HeapConsumer<Box<E>>,
+    /// old effectors sent back to be dropped outside the audio thread.
+    pub retired: HeapProducer<Box<E>>,
+    /// the input is copied into this while `recording` is set, and written into the file by the recorder.
+    pub record_tx: HeapProducer<f32>,
+    pub recording: Arc<atomic::Bool>,
     pub current_time: Arc<atomic::U64>,
+    pub latency: Arc<atomic::U64>,
+    /// set while the project rate differs from the device rate.
+    pub conversion: Option<RateConversion>,
 }
 
-fn pass_in(model: Arc<Mutex<InputModel>>, buffer: &[f32], _info: cpal::StreamConfig) {
-    if let Ok(mut m) = model.try_lock() {
-        let _num = m.producer.push_slice(buffer);
+/// Resamplers between the project rate, at which the components render, and the device rate.
+pub struct RateConversion {
+    project_rate: u32,
+    device_rate: u32,
+    /// the output of the components into the device rate.
+    output: Resampler,
+    /// the device input into the project rate.
+    input: Resampler,
+    /// the output of the components at the project rate.
+    stereo_buf: Vec<f32>,
+    /// the input resampled to the project rate.
+    input_buf: Vec<f32>,
+    /// the largest number of the device input frames needed for a block.
+    device_input_block: usize,
+}
+
+impl RateConversion {
+    fn new(project_rate: u32, device_rate: u32, block: usize) -> Self {
+        let project_block = project_block_len(block, project_rate, device_rate);
+        let mut input =
+            Resampler::new(device_rate, project_rate, MAX_INPUT_CHANNELS, project_block);
+        input.set_channels(2);
+        let device_input_block = input.max_frames_needed(project_block);
+        Self {
+            project_rate,
+            device_rate,
+            output: Resampler::new(project_rate, device_rate, 2, block),
+            input,
+            stereo_buf: vec![0.0; project_block * 2],
+            input_buf: vec![0.0; project_block * MAX_INPUT_CHANNELS],
+            device_input_block,
+        }
+    }
+    fn reset(&mut self) {
+        self.output.reset();
+        self.input.reset();
+    }
+}
+
+/// The largest number of the frames at the project rate rendered for a device block of `block` frames.
+fn project_block_len(block: usize, project_rate: u32, device_rate: u32) -> usize {
+    if project_rate == device_rate {
+        block
+    } else {
+        resampler::max_frames_needed(project_rate, device_rate, block)
+    }
+}
+
+/// Layout the timeline is mixed into, before mapped to the channels of the device.
+const MASTER_LAYOUT: ChannelLayout = ChannelLayout::Stereo;
+/// Number of effectors which can wait for swapping or dropping.
+const EFFECTOR_QUEUE_LEN: usize = 4;
+/// Number of the input channels the input queue is sized for.
+const MAX_INPUT_CHANNELS: usize = 8;
+/// Size of the queue for the recorded input in blocks, which absorbs the delay of the writer thread.
+const RECORD_QUEUE_BLOCKS: usize = 64;
+
+/// Linear crossfade from `from` into `to` over the block.
+fn crossfade(from: &[f32], to: &mut [f32], channels: usize) {
+    let frames = to.len() / channels;
+    for (i, (old, new)) in from
+        .chunks(channels)
+        .zip(to.chunks_mut(channels))
+        .enumerate()
+    {
+        let gain = i as f32 / frames as f32;
+        new.iter_mut()
+            .zip(old.iter())
+            .for_each(|(n, o)| *n = *o * (1.0 - gain) + *n * gain);
+    }
+}
+
+/// Pushes the first two channels of the input, as the recorder writes stereo files.
+fn push_stereo(input: &[f32], channels: usize, dest: &mut HeapProducer<f32>) {
+    if channels == 2 {
+        let _num = dest.push_slice(input);
+        return;
+    }
+    for frame in input.chunks(channels) {
+        let (l, r) = match frame {
+            [mono] => (*mono, *mono),
+            [l, r, ..] => (*l, *r),
+            [] => (0.0, 0.0),
+        };
+        let _ = dest.push(l);
+        let _ = dest.push(r);
     }
 }
 
 fn pass_out(
-    model: Arc<Mutex<OutputModel<impl Component + Sync + Send>>>,
+    model: &Handoff<OutputModel<impl Component + Sync + Send>>,
     buffer: &mut [f32],
-    info: cpal::StreamConfig,
+    config: &StreamConfig,
 ) {
+    #[cfg(feature = "alloc-check")]
+    let _guard = crate::utils::alloc_check::AudioThreadGuard::new();
+    // the model is taken by another thread while preparing or swapping it.
+    let Some(mut m) = model.try_take() else {
+        buffer.fill(0.0);
+        return;
+    };
     //assume input channels and output channels are the same
-    if let Ok(mut model) = model.try_lock() {
-        let len = buffer.len();
-        let frame_per_buffer = len as u64 / info.channels as u64;
-        let t = model.current_time.load();
-        // let buf = &mut model.internal_buf.as_mut_slice()[0..len];
-        let mut buf = vec![0.0; len];
-        let _num = model.consumer.pop_slice(&mut buf);
+    let channels = config.channels as usize;
+    let frame_per_buffer = buffer.len() / channels;
+    let t = m.current_time.load();
+    let len = frame_per_buffer * 2;
+    let OutputModel {
+        consumer,
+        input_channels,
+        internal_buf,
+        device_mixer,
+        input_buf,
+        fade_buf,
+        effector,
+        incoming,
+        retired,
+        record_tx,
+        recording,
+        current_time,
+        latency,
+        conversion,
+    } = m.as_mut();
+    // the frames rendered by the components, and the device input frames needed for them.
+    let in_channels = input_channels.load().max(1) as usize;
+    let (project_frames, device_in_frames) = match conversion.as_mut() {
+        Some(conv) => {
+            if conv.input.get_channels() != in_channels {
+                conv.input.set_channels(in_channels.min(MAX_INPUT_CHANNELS));
+            }
+            let project_frames = conv.output.frames_needed(frame_per_buffer);
+            (project_frames, conv.input.frames_needed(project_frames))
+        }
+        None => (frame_per_buffer, frame_per_buffer),
+    };
+    // buffers are allocated before the playback. they grow only if the device requests larger blocks than expected.
+    let in_len = device_in_frames * in_channels;
+    let project_len = project_frames * 2;
+    for (buf, len) in [
+        (&mut *input_buf, in_len),
+        (internal_buf, len),
+        (fade_buf, project_len),
+    ] {
+        if buf.len() < len {
+            buf.resize(len, 0.0);
+        }
+    }
+    let input = &mut input_buf[..in_len];
+    input.fill(0.0);
+    // the input waits in the queue, then the output waits for the block to be played.
+    let queued = consumer.len() / in_channels;
+    let latency_frames = (queued + frame_per_buffer) as u64;
+    let _num = consumer.pop_slice(input);
+    if recording.load() {
+        // the overflowed samples are lost when the writer is too slow.
+        push_stereo(input, in_channels, record_tx);
+    }
 
-        let info = PlaybackInfo {
-            sample_rate: info.sample_rate.0,
-            current_time: t as usize,
-            channels: info.channels as u64,
-            frame_per_buffer,
-        };
-        // todo:if  channels are different?
-        model.effector.render(&buf, buffer, &info);
-        model.current_time.store(t + frame_per_buffer);
+    let info = PlaybackInfo {
+        sample_rate: conversion
+            .as_ref()
+            .map_or(config.sample_rate, |c| c.project_rate),
+        current_time: t as usize,
+        channels: MASTER_LAYOUT,
+        frame_per_buffer: project_frames as u64,
+    };
+    // the old effector is kept until it is sent back, so that it is not dropped in the audio thread.
+    let mut render = |input: &[f32], stereo: &mut [f32]| match (!retired.is_full())
+        .then(|| incoming.pop())
+        .flatten()
+    {
+        Some(mut new) => {
+            let old_out = &mut fade_buf[..project_len];
+            effector.render(input, old_out, &info);
+            std::mem::swap(effector, new.as_mut());
+            effector.render(input, stereo, &info);
+            crossfade(old_out, stereo, 2);
+            let _ = retired.push(new);
+        }
+        None => effector.render(input, stereo, &info),
+    };
+    let stereo = if channels == 2 {
+        &mut *buffer
+    } else {
+        &mut internal_buf[..len]
+    };
+    match conversion.as_mut() {
+        Some(conv) => {
+            if conv.stereo_buf.len() < project_len {
+                conv.stereo_buf.resize(project_len, 0.0);
+            }
+            if conv.input_buf.len() < project_frames * in_channels {
+                conv.input_buf.resize(project_frames * in_channels, 0.0);
+            }
+            let project_input = &mut conv.input_buf[..project_frames * in_channels];
+            conv.input.process(input, project_input);
+            let project_out = &mut conv.stereo_buf[..project_len];
+            render(project_input, project_out);
+            conv.output.process(project_out, stereo);
+            let ratio = conv.project_rate as f64 / conv.device_rate as f64;
+            latency.store((latency_frames as f64 * ratio).round() as u64);
+        }
+        None => {
+            render(input, stereo);
+            latency.store(latency_frames);
+        }
     }
+    if channels != 2 {
+        device_mixer.process(&internal_buf[..len], buffer);
+    }
+    let next = effector.next_time(&info);
+    current_time.store(next as u64);
+    model.put(m);
 }
 
 pub struct Renderer<E>
 where
     E: Component + Send + Sync + 'static,
 {
-    pub host: cpal::Host,
+    backend: Box<dyn Backend>,
     /// Do not mutate transport from the audio renderer side. it just subscribes states changed by GUI.
     transport: Arc<data::Transport>,
-    istream: Option<Stream>,
-    ostream: Option<Stream>,
-    imodel: Arc<Mutex<InputModel>>,
-    omodel: Arc<Mutex<OutputModel<E>>>,
-    iconfig: Option<cpal::StreamConfig>,
-    oconfig: Option<cpal::StreamConfig>,
+    omodel: Arc<Handoff<OutputModel<E>>>,
+    effector_tx: HeapProducer<Box<E>>,
+    retired_rx: HeapConsumer<Box<E>>,
+    /// whether the backend is running the callbacks.
+    is_running: bool,
+    recording: Arc<atomic::Bool>,
+    /// kept while not recording, and moved into the recorder while recording.
+    record_rx: Option<HeapConsumer<f32>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    recorder: Option<Recorder>,
+    /// why the selected devices could not be opened, kept until the GUI shows it.
+    open_error: Option<backend::Error>,
+    /// the rate the components render at. Resampled to the device rate if they differ.
+    project_rate: u32,
 }
 
 impl<E> RendererBase<E> for Renderer<E>
 where
     E: Component + Send + Sync + 'static,
 {
-    fn get_host(&self) -> &cpal::Host {
-        &self.host
-    }
-    fn set_streams(
-        &mut self,
-        i: Option<Stream>,
-        o: Option<Stream>,
-        iconfig: Option<cpal::StreamConfig>,
-        oconfig: Option<cpal::StreamConfig>,
-    ) {
-        self.istream = i;
-        self.ostream = o;
-        self.iconfig = iconfig;
-        self.oconfig = oconfig;
+    fn get_backend(&self) -> &dyn Backend {
+        self.backend.as_ref()
     }
-
-    fn get_instream(&self) -> &Option<Stream> {
-        &self.istream
-    }
-    fn get_outstream(&self) -> &Option<Stream> {
-        &self.ostream
+    fn get_backend_mut(&mut self) -> &mut dyn Backend {
+        self.backend.as_mut()
     }
     fn is_playing(&self) -> bool {
         self.transport.is_playing()
     }
-    fn get_samplerate(&self) -> u32 {
-        self.oconfig.as_ref().unwrap().sample_rate.0
-    }
 
     fn play(&mut self) {
         self.play_audio();
+        self.is_running = true;
     }
 
     fn pause(&mut self) {
         self.pause_audio();
+        self.is_running = false;
     }
 
     fn get_shared_current_time_in_sample(&self) -> Arc<atomic::U64> {
@@ -246,25 +342,58 @@ where
         self.transport.time.load()
     }
 
+    fn get_current_time(&self) -> std::time::Duration {
+        let now = self.get_current_time_in_sample();
+        std::time::Duration::from_secs_f64(now as f64 / self.project_rate as f64)
+    }
+
     fn prepare_play(&mut self) {
-        if let Ok(_model) = self.imodel.try_lock() {
-            //do nothing
+        let config = self.backend.get_config().clone();
+        let info = self.get_playback_info();
+        // the audio thread outputs silence until the model is put back.
+        let mut model = self.omodel.take();
+        // the effector waiting for the swap is used right away as the playback restarts from here.
+        while let Some(mut new) = model.incoming.pop() {
+            std::mem::swap(&mut model.effector, new.as_mut());
         }
-        let config = self.oconfig.as_ref().unwrap();
-        let buffer_size = match config.buffer_size {
-            cpal::BufferSize::Default => todo!(),
-            cpal::BufferSize::Fixed(s) => s as usize,
-        } * config.channels as usize;
-
-        if let Ok(mut model) = self.omodel.try_lock() {
-            let info = PlaybackInfo {
-                sample_rate: config.sample_rate.0,
-                current_time: 0,
-                frame_per_buffer: buffer_size as u64,
-                channels: config.channels as u64,
-            };
-            model.effector.prepare_play(&info);
+        match model.conversion.as_mut() {
+            Some(conv)
+                if (conv.project_rate, conv.device_rate)
+                    == (self.project_rate, config.sample_rate) =>
+            {
+                conv.reset()
+            }
+            _ if self.project_rate == config.sample_rate => model.conversion = None,
+            _ => {
+                model.conversion = Some(RateConversion::new(
+                    self.project_rate,
+                    config.sample_rate,
+                    config.buffer_size,
+                ))
+            }
         }
+        let device_layout = ChannelLayout::from_count(config.channels as usize);
+        if model.device_mixer.get_to() != device_layout {
+            model.device_mixer = Mixer::new(MASTER_LAYOUT, device_layout);
+        }
+        let len = info.frame_per_buffer as usize * 2;
+        let in_frames = model
+            .conversion
+            .as_ref()
+            .map_or(info.frame_per_buffer as usize, |c| c.device_input_block);
+        let in_len = in_frames * model.input_channels.load().max(2) as usize;
+        for (buf, len) in [
+            (&mut model.input_buf, in_len),
+            (&mut model.internal_buf, len),
+            (&mut model.fade_buf, len),
+        ] {
+            if buf.len() < len {
+                buf.resize(len, 0.0);
+            }
+        }
+        model.effector.prepare_play(&info);
+        self.omodel.put(model);
+        self.collect_retired();
     }
 }
 
@@ -272,32 +401,187 @@ impl<E> Renderer<E>
 where
     E: Component + Send + Sync + 'static,
 {
+    /// Opens the backend of `kind` with the selected `devices`. Falls back to the default devices,
+    /// then to the null backend if the backend is not available, so that the application can run on machines without audio devices.
     pub fn new(
         effect: E,
         sample_rate: Option<u32>,
         buffer_size: Option<usize>,
         transport: Arc<data::Transport>,
+        kind: &backend::Kind,
+        devices: &backend::DeviceSelection,
     ) -> Self {
         let latency_samples = buffer_size.unwrap_or(super::DEFAULT_BUFFER_LEN);
-        let ring_buffer = HeapRb::<f32>::new(latency_samples * 4); // Add some latency
+        // room for 2 blocks of the input device with up to 8 channels.
+        let ring_buffer = HeapRb::<f32>::new(latency_samples * 2 * MAX_INPUT_CHANNELS);
         let (producer, consumer) = ring_buffer.split();
-        let mut res = Self {
-            host: cpal::default_host(),
-            transport: transport.clone(),
-            istream: None,
-            ostream: None,
-            imodel: Arc::new(Mutex::new(InputModel { producer })),
-            omodel: Arc::new(Mutex::new(OutputModel::<E> {
-                consumer,
-                internal_buf: vec![0.0; latency_samples * 2],
-                effector: effect,
-                current_time: Arc::clone(&transport.time),
-            })),
-            iconfig: None,
-            oconfig: None,
+        // shared by the input callbacks of the backends tried in turn. Only the opened one uses it.
+        let producer = Arc::new(Handoff::new(producer));
+        let (effector_tx, incoming) = HeapRb::new(EFFECTOR_QUEUE_LEN).split();
+        let (retired, retired_rx) = HeapRb::new(EFFECTOR_QUEUE_LEN).split();
+        let (record_tx, record_rx) = HeapRb::new(latency_samples * 2 * RECORD_QUEUE_BLOCKS).split();
+        let recording = Arc::new(atomic::Bool::from(false));
+        let input_channels = Arc::new(atomic::U16::from(2));
+        let omodel = Arc::new(Handoff::new(OutputModel::<E> {
+            consumer,
+            input_channels: input_channels.clone(),
+            internal_buf: vec![0.0; latency_samples * 2],
+            device_mixer: Mixer::new(MASTER_LAYOUT, MASTER_LAYOUT),
+            input_buf: vec![0.0; latency_samples * 2],
+            fade_buf: vec![0.0; latency_samples * 2],
+            effector: effect,
+            incoming,
+            retired,
+            record_tx,
+            recording: recording.clone(),
+            current_time: Arc::clone(&transport.time),
+            latency: Arc::clone(&transport.latency),
+            conversion: None,
+        }));
+        let open = |kind: &backend::Kind, devices: &backend::DeviceSelection| {
+            let om = omodel.clone();
+            let producer = producer.clone();
+            let input_channels = input_channels.clone();
+            let input = move |data: &[f32], c: &StreamConfig| {
+                input_channels.store(c.channels);
+                if let Some(mut p) = producer.try_take() {
+                    let _num = p.push_slice(data);
+                    producer.put(p);
+                }
+            };
+            backend::open(
+                kind,
+                devices,
+                sample_rate,
+                latency_samples,
+                Box::new(input),
+                Box::new(move |data: &mut [f32], c: &StreamConfig| pass_out(&om, data, c)),
+            )
         };
-        res.init(sample_rate, res.imodel.clone(), res.omodel.clone());
-        res
+        let mut open_error = None;
+        let default_devices = backend::DeviceSelection::default();
+        let backend = open(kind, devices)
+            .or_else(|e| {
+                if devices == &default_devices {
+                    return Err(e);
+                }
+                log::warn!("{}. falls back to the default devices.", e);
+                open_error = Some(e);
+                open(kind, &default_devices)
+            })
+            .unwrap_or_else(|e| {
+                log::warn!("{}. falls back to the null backend.", e);
+                open_error.get_or_insert(e);
+                open(&backend::Kind::Null, &default_devices)
+                    .expect("failed to open the null backend")
+            });
+        // no conversion until the project rate is set.
+        let project_rate = backend.get_config().sample_rate;
+        Self {
+            backend,
+            transport,
+            omodel,
+            effector_tx,
+            retired_rx,
+            is_running: false,
+            recording,
+            record_rx: Some(record_rx),
+            #[cfg(not(target_arch = "wasm32"))]
+            recorder: None,
+            open_error,
+            project_rate,
+        }
+    }
+    /// Whether the backend is running the callbacks.
+    pub fn is_running(&self) -> bool {
+        self.is_running
+    }
+    /// Closes the stream, releasing the devices so that another renderer can open them.
+    pub fn close(&mut self) {
+        self.pause();
+        let config = self.backend.get_config().clone();
+        self.backend = Box::new(backend::Closed(config));
+    }
+    /// Renders the components at `sample_rate` from the next [`RendererBase::prepare_play`],
+    /// resampling to the device rate if they differ.
+    pub fn set_project_sample_rate(&mut self, sample_rate: u32) {
+        self.project_rate = sample_rate;
+    }
+    /// Playback info for the components, in the project rate.
+    fn get_playback_info(&self) -> PlaybackInfo {
+        let config = self.backend.get_config();
+        PlaybackInfo {
+            sample_rate: self.project_rate,
+            current_time: self.transport.time.load() as usize,
+            frame_per_buffer: project_block_len(
+                config.buffer_size,
+                self.project_rate,
+                config.sample_rate,
+            ) as u64,
+            channels: MASTER_LAYOUT,
+        }
+    }
+    /// Returns the error on opening the devices or in the running stream, once.
+    pub fn take_error(&mut self) -> Option<backend::Error> {
+        self.open_error.take().or_else(|| self.backend.take_error())
+    }
+    /// Replaces the effector keeping the audio stream open.
+    /// While the stream is running, the new effector is prepared from the current position and crossfaded at the next block.
+    /// Otherwise it is swapped immediately, and prepared by the next [`RendererBase::prepare_play`].
+    pub fn replace_effector(&mut self, mut effector: E) {
+        self.collect_retired();
+        if self.is_running {
+            effector.prepare_play(&self.get_playback_info());
+            if let Err(effector) = self.effector_tx.push(Box::new(effector)) {
+                // too many effectors are waiting. swap directly with a gap.
+                let mut model = self.omodel.take();
+                let old = std::mem::replace(&mut model.effector, *effector);
+                self.omodel.put(model);
+                drop(old);
+            }
+        } else {
+            let mut model = self.omodel.take();
+            let old = std::mem::replace(&mut model.effector, effector);
+            self.omodel.put(model);
+            drop(old);
+        }
+    }
+    /// Drops the effectors sent back from the audio thread.
+    pub fn collect_retired(&mut self) {
+        while let Some(old) = self.retired_rx.pop() {
+            drop(old);
+        }
+    }
+    /// Starts writing the input into the wav file at `path` from the current position.
+    /// The input is captured while the stream is running.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn start_recording(&mut self, path: std::path::PathBuf) -> hound::Result<()> {
+        if let Some(take) = self.stop_recording() {
+            log::warn!("the previous recording was not stopped: {:?}", take);
+        }
+        let consumer = self.record_rx.take().expect("record queue is lost");
+        let start = self.transport.time.load();
+        let sample_rate = self.get_samplerate();
+        match Recorder::start(consumer, path, sample_rate, start) {
+            Ok(recorder) => {
+                self.recorder = Some(recorder);
+                self.recording.store(true);
+                Ok(())
+            }
+            Err((consumer, e)) => {
+                self.record_rx = Some(consumer);
+                Err(e)
+            }
+        }
+    }
+    /// Finishes the file. Returns `None` if not recording.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn stop_recording(&mut self) -> Option<hound::Result<RecordedTake>> {
+        let recorder = self.recorder.take()?;
+        self.recording.store(false);
+        let (consumer, take) = recorder.stop();
+        self.record_rx = Some(consumer);
+        Some(take)
     }
     pub fn rewind(&mut self) {
         self.get_shared_current_time_in_sample().store(0)
@@ -309,9 +593,137 @@ pub fn create_renderer<E>(
     sample_rate: Option<u32>,
     buffer_size: Option<usize>,
     transport: Arc<data::Transport>,
+    kind: &backend::Kind,
+    devices: &backend::DeviceSelection,
 ) -> Renderer<E>
 where
     E: Component + Send + Sync + 'static,
 {
-    Renderer::<E>::new(effect, sample_rate, buffer_size, transport)
+    Renderer::<E>::new(effect, sample_rate, buffer_size, transport, kind, devices)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio::{fixture, timeline};
+
+    type Queues = (
+        HeapProducer<Box<timeline::Model>>,
+        HeapConsumer<Box<timeline::Model>>,
+    );
+
+    fn constant_timeline(value: f32) -> timeline::Model {
+        let region = fixture::constant_region(value, 0.0, 1.0);
+        let project = fixture::project(vec![region], 48000);
+        let mut effector = timeline::Model::new(project, Arc::new(data::Transport::new()));
+        effector.prepare_play(&PlaybackInfo {
+            sample_rate: 48000,
+            current_time: 0,
+            frame_per_buffer: 256,
+            channels: MASTER_LAYOUT,
+        });
+        effector
+    }
+    fn output_model(effector: timeline::Model) -> (Handoff<OutputModel<timeline::Model>>, Queues) {
+        let (_producer, consumer) = HeapRb::<f32>::new(1024).split();
+        let (tx, incoming) = HeapRb::new(EFFECTOR_QUEUE_LEN).split();
+        let (retired, rx) = HeapRb::new(EFFECTOR_QUEUE_LEN).split();
+        let (record_tx, _record_rx) = HeapRb::new(512).split();
+        let model = Handoff::new(OutputModel {
+            consumer,
+            input_channels: Arc::new(atomic::U16::from(2)),
+            internal_buf: vec![0.0; 512],
+            device_mixer: Mixer::new(MASTER_LAYOUT, MASTER_LAYOUT),
+            input_buf: vec![0.0; 512],
+            fade_buf: vec![0.0; 512],
+            effector,
+            incoming,
+            retired,
+            record_tx,
+            recording: Arc::new(atomic::Bool::from(false)),
+            current_time: Arc::new(atomic::U64::from(0)),
+            latency: Arc::new(atomic::U64::from(0)),
+            conversion: None,
+        });
+        (model, (tx, rx))
+    }
+    const CONFIG: StreamConfig = StreamConfig {
+        sample_rate: 48000,
+        channels: 2,
+        buffer_size: 256,
+    };
+
+    #[test]
+    fn silence_while_taken() {
+        let (model, _queues) = output_model(constant_timeline(0.5));
+        let mut buffer = vec![1.0f32; 512];
+        let taken = model.take();
+        pass_out(&model, &mut buffer, &CONFIG);
+        assert!(buffer.iter().all(|s| *s == 0.0));
+        model.put(taken);
+        pass_out(&model, &mut buffer, &CONFIG);
+        assert!(buffer.iter().all(|s| *s == 0.5));
+        assert_eq!(model.take().current_time.load(), 256);
+    }
+    #[cfg(feature = "alloc-check")]
+    #[test]
+    fn callback_does_not_allocate() {
+        let (model, (mut tx, _rx)) = output_model(constant_timeline(0.5));
+        let mut buffer = vec![0.0f32; 512];
+        let _ = tx.push(Box::new(constant_timeline(1.0)));
+        let before = crate::utils::alloc_check::get_allocations();
+        for _ in 0..32 {
+            pass_out(&model, &mut buffer, &CONFIG);
+        }
+        assert_eq!(crate::utils::alloc_check::get_allocations(), before);
+    }
+    #[cfg(feature = "alloc-check")]
+    #[test]
+    fn conversion_does_not_allocate() {
+        let (model, _queues) = output_model(constant_timeline(0.5));
+        let mut m = model.take();
+        let conv = RateConversion::new(44100, CONFIG.sample_rate, 256);
+        m.input_buf.resize(conv.device_input_block * 2, 0.0);
+        m.conversion = Some(conv);
+        model.put(m);
+        let mut buffer = vec![0.0f32; 512];
+        let before = crate::utils::alloc_check::get_allocations();
+        for _ in 0..32 {
+            pass_out(&model, &mut buffer, &CONFIG);
+        }
+        assert_eq!(crate::utils::alloc_check::get_allocations(), before);
+    }
+    #[test]
+    fn swap_with_crossfade() {
+        let (model, (mut tx, mut rx)) = output_model(constant_timeline(0.5));
+        let mut buffer = vec![0.0f32; 512];
+        pass_out(&model, &mut buffer, &CONFIG);
+        let _ = tx.push(Box::new(constant_timeline(1.0)));
+        pass_out(&model, &mut buffer, &CONFIG);
+        let left = buffer.iter().step_by(2).collect::<Vec<_>>();
+        assert_eq!(*left[0], 0.5);
+        assert!(left.windows(2).all(|w| w[0] < w[1]));
+        // the old effector is sent back to be dropped outside the audio thread.
+        assert!(rx.pop().is_some());
+        pass_out(&model, &mut buffer, &CONFIG);
+        assert!(buffer.iter().all(|s| *s == 1.0));
+    }
+    #[test]
+    fn render_at_project_rate() {
+        let (model, _queues) = output_model(constant_timeline(0.5));
+        let mut m = model.take();
+        m.conversion = Some(RateConversion::new(44100, CONFIG.sample_rate, 256));
+        model.put(m);
+        let mut buffer = vec![0.0f32; 512];
+        let blocks = 16;
+        for _ in 0..blocks {
+            pass_out(&model, &mut buffer, &CONFIG);
+        }
+        // the constant stays constant once the kernel is filled.
+        assert!(buffer.iter().all(|s| (s - 0.5).abs() < 1e-2));
+        // the time advances in the frames of the project rate.
+        let expected = 256 * blocks * 44100 / CONFIG.sample_rate as u64;
+        let time = model.take().current_time.load();
+        assert!(time.abs_diff(expected) < 64, "{} {}", time, expected);
+    }
 }
@@ -0,0 +1,250 @@
+//! Band-limited sample rate conversion with a Kaiser-windowed sinc kernel.
+//!
+//! The converter is pulled by the output side: ask [`Resampler::frames_needed`] how many input frames
+//! the next block requires, then [`Resampler::process`] produces exactly the requested output frames.
+//! The buffers are reserved on construction so that the audio thread does not allocate.
+
+/// Half width of the kernel in zero crossings.
+const ZERO_CROSSINGS: usize = 16;
+/// Number of the kernel values tabulated per zero crossing, linearly interpolated between.
+const TABLE_RESOLUTION: usize = 256;
+const KAISER_BETA: f64 = 8.0;
+/// Cutoff relative to the lower Nyquist frequency, leaving room for the transition band.
+const ROLLOFF: f64 = 0.95;
+
+/// Modified Bessel function of the first kind, order 0.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+    for k in 1..32 {
+        term *= half / k as f64;
+        sum += term * term;
+    }
+    sum
+}
+
+fn make_kernel_table() -> Vec<f32> {
+    let len = ZERO_CROSSINGS * TABLE_RESOLUTION;
+    let norm = bessel_i0(KAISER_BETA);
+    // one more entry at the end for the interpolation.
+    (0..=len + 1)
+        .map(|i| {
+            let x = i as f64 / TABLE_RESOLUTION as f64;
+            if x >= ZERO_CROSSINGS as f64 {
+                return 0.0;
+            }
+            let sinc = if i == 0 {
+                1.0
+            } else {
+                (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+            };
+            let r = x / ZERO_CROSSINGS as f64;
+            let window = bessel_i0(KAISER_BETA * (1.0 - r * r).sqrt()) / norm;
+            (sinc * window) as f32
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    channels: usize,
+    /// input frames advanced per output frame.
+    step: f64,
+    /// cutoff relative to the input Nyquist frequency. Below 1 when downsampling.
+    cutoff: f64,
+    /// half width of the kernel in input frames.
+    half_width: usize,
+    table: Vec<f32>,
+    /// interleaved input frames still referenced by the kernel.
+    history: Vec<f32>,
+    /// position of the next output frame in `history`, in input frames.
+    pos: f64,
+}
+
+fn cutoff_and_half_width(from_rate: u32, to_rate: u32) -> (f64, usize) {
+    let cutoff = (to_rate as f64 / from_rate as f64).min(1.0) * ROLLOFF;
+    (cutoff, (ZERO_CROSSINGS as f64 / cutoff).ceil() as usize)
+}
+
+/// Same as [`Resampler::max_frames_needed`] without constructing the resampler.
+pub fn max_frames_needed(from_rate: u32, to_rate: u32, out_frames: usize) -> usize {
+    let step = from_rate as f64 / to_rate as f64;
+    let (_, half_width) = cutoff_and_half_width(from_rate, to_rate);
+    (out_frames as f64 * step).ceil() as usize + half_width + 1
+}
+
+impl Resampler {
+    /// Converts from `from_rate` to `to_rate`, reserving the buffers for blocks up to `max_block` output frames.
+    pub fn new(from_rate: u32, to_rate: u32, channels: usize, max_block: usize) -> Self {
+        let step = from_rate as f64 / to_rate as f64;
+        let (cutoff, half_width) = cutoff_and_half_width(from_rate, to_rate);
+        let max_input = (max_block as f64 * step).ceil() as usize + 2 * half_width + 2;
+        let mut res = Self {
+            channels,
+            step,
+            cutoff,
+            half_width,
+            table: make_kernel_table(),
+            history: Vec::with_capacity(max_input * channels),
+            pos: 0.0,
+        };
+        res.reset();
+        res
+    }
+    pub fn get_channels(&self) -> usize {
+        self.channels
+    }
+    /// Changes the number of channels and clears the state. Does not allocate while the reserved size suffices.
+    pub fn set_channels(&mut self, channels: usize) {
+        self.channels = channels;
+        self.reset();
+    }
+    /// Clears the state. The first output frame is aligned to the next input frame.
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.history.resize(self.half_width * self.channels, 0.0);
+        self.pos = self.half_width as f64;
+    }
+    fn history_frames(&self) -> usize {
+        self.history.len().checked_div(self.channels).unwrap_or(0)
+    }
+    /// Number of the input frames to pass to the next [`Resampler::process`] to produce `out_frames` frames.
+    pub fn frames_needed(&self, out_frames: usize) -> usize {
+        if out_frames == 0 {
+            return 0;
+        }
+        let last = self.pos + (out_frames - 1) as f64 * self.step;
+        let required = last.floor() as usize + self.half_width + 1;
+        required.saturating_sub(self.history_frames())
+    }
+    /// Upper bound of [`Resampler::frames_needed`] for any state, to size the input buffers in advance.
+    pub fn max_frames_needed(&self, out_frames: usize) -> usize {
+        (out_frames as f64 * self.step).ceil() as usize + self.half_width + 1
+    }
+    fn kernel(&self, x: f64) -> f32 {
+        let idx = x.abs() * TABLE_RESOLUTION as f64;
+        let i = idx as usize;
+        if i + 1 >= self.table.len() {
+            return 0.0;
+        }
+        let frac = (idx - i as f64) as f32;
+        self.table[i] + (self.table[i + 1] - self.table[i]) * frac
+    }
+    /// Appends the interleaved `input` and fills the interleaved `output` as far as the input allows.
+    /// Returns the number of the output frames written.
+    pub fn process(&mut self, input: &[f32], output: &mut [f32]) -> usize {
+        let ch = self.channels;
+        if ch == 0 {
+            return 0;
+        }
+        self.history.extend_from_slice(input);
+        let frames = self.history_frames();
+        let hw = self.half_width as isize;
+        let gain = self.cutoff as f32;
+        let mut written = 0;
+        for out in output.chunks_exact_mut(ch) {
+            let center = self.pos.floor() as isize;
+            if center + hw >= frames as isize {
+                break;
+            }
+            out.fill(0.0);
+            for i in (center - hw + 1).max(0)..=center + hw {
+                let w = gain * self.kernel((self.pos - i as f64) * self.cutoff);
+                let frame = &self.history[i as usize * ch..(i as usize + 1) * ch];
+                out.iter_mut().zip(frame).for_each(|(o, s)| *o += w * s);
+            }
+            self.pos += self.step;
+            written += 1;
+        }
+        // drops the frames which the next output does not reach.
+        let consumed = (self.pos.floor() as isize - hw + 1).clamp(0, frames as isize) as usize;
+        self.history.drain(..consumed * ch);
+        self.pos -= consumed as f64;
+        written
+    }
+}
+
+/// Converts the whole interleaved buffer at once, for the offline rendering.
+pub fn resample_buffer(input: &[f32], channels: usize, from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate || channels == 0 {
+        return input.to_vec();
+    }
+    let in_frames = input.len() / channels;
+    let out_frames = (in_frames as f64 * to_rate as f64 / from_rate as f64).round() as usize;
+    let mut resampler = Resampler::new(from_rate, to_rate, channels, out_frames);
+    let needed = resampler.frames_needed(out_frames);
+    // the tail is padded with silence so that the kernel reaches the last frames.
+    let mut padded = input.to_vec();
+    padded.resize(needed.max(in_frames) * channels, 0.0);
+    let mut output = vec![0.0; out_frames * channels];
+    resampler.process(&padded, &mut output);
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sine(freq: f64, sample_rate: u32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|i| (std::f64::consts::TAU * freq * i as f64 / sample_rate as f64).sin() as f32)
+            .collect()
+    }
+
+    #[test]
+    fn keeps_tone_across_rates() {
+        for (from, to) in [(44100, 48000), (48000, 44100), (48000, 96000)] {
+            let input = sine(1000.0, from, from as usize / 10);
+            let output = resample_buffer(&input, 1, from, to);
+            let expected = sine(1000.0, to, output.len());
+            // the edges are affected by the silence outside the buffer.
+            let margin = 200;
+            let err = output[margin..output.len() - margin]
+                .iter()
+                .zip(&expected[margin..])
+                .fold(0.0f32, |acc, (o, e)| acc.max((o - e).abs()));
+            assert!(err < 2e-3, "{} -> {}: error {}", from, to, err);
+        }
+    }
+    #[test]
+    fn blocks_match_whole_buffer() {
+        let channels = 2;
+        let input = sine(440.0, 44100, 4000)
+            .into_iter()
+            .flat_map(|v| [v, -v])
+            .collect::<Vec<_>>();
+        let whole = resample_buffer(&input, channels, 44100, 48000);
+        let mut resampler = Resampler::new(44100, 48000, channels, 256);
+        let mut output = vec![];
+        let mut read = 0;
+        let mut block = vec![0.0; 256 * channels];
+        for block_len in [256, 100, 37, 256, 1].into_iter().cycle() {
+            let needed = resampler.frames_needed(block_len);
+            if (read + needed) * channels > input.len() {
+                break;
+            }
+            let dest = &mut block[..block_len * channels];
+            let written =
+                resampler.process(&input[read * channels..(read + needed) * channels], dest);
+            assert_eq!(written, block_len);
+            read += needed;
+            output.extend_from_slice(dest);
+        }
+        assert!(output.len() > 3000 * channels);
+        output.iter().zip(whole.iter()).for_each(|(a, b)| {
+            assert!((a - b).abs() < 1e-6);
+        });
+    }
+    #[test]
+    fn removes_aliasing_on_downsampling() {
+        // above the Nyquist frequency of the new rate.
+        let input = sine(15000.0, 48000, 4800);
+        let output = resample_buffer(&input, 1, 48000, 22050);
+        let margin = 200;
+        let peak = output[margin..output.len() - margin]
+            .iter()
+            .fold(0.0f32, |acc, v| acc.max(v.abs()));
+        assert!(peak < 0.01, "peak {}", peak);
+    }
+}
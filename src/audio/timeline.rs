@@ -1,3 +1,6 @@
+use super::meter::Meter;
+use super::pool::RenderBatch;
+use super::region::cache::RenderCache;
 use crate::audio::{Component, PlaybackInfo};
 use crate::data;
 use std::sync::Arc;
@@ -6,31 +9,115 @@ pub struct Model {
     param: data::Project,
     _transport: Arc<data::Transport>,
     tracks: Vec<super::track::Model>, // regions: Vec<audio::region::Region<>>
+    /// levels of each track, in the same order as `tracks`.
+    meters: Vec<Meter>,
+    master_meter: Meter,
     tmp_buffer: Vec<f32>,
+    /// whether regions are streamed ahead of the playhead. Disabled for the faster-than-realtime rendering.
+    streaming: bool,
+    /// shared with the next renderer so that unchanged regions are not rendered again.
+    cache: Option<Arc<RenderCache>>,
+    /// progress of the regions rendered in the worker pool.
+    batch: Arc<RenderBatch>,
 }
 
 impl Model {
     pub fn new(project: data::Project, transport: Arc<data::Transport>) -> Self {
-        let tracks = Self::get_new_tracks(&project);
         let tmp_buffer = vec![0.0; 3];
-        Self {
+        let mut res = Self {
             param: project,
             _transport: Arc::clone(&transport),
-            tracks,
+            tracks: vec![],
+            meters: vec![],
+            master_meter: Meter::new(transport.meter.clone()).with_true_peak(),
             tmp_buffer,
-        }
+            streaming: true,
+            cache: None,
+            batch: Arc::new(RenderBatch::new()),
+        };
+        res.tracks = res.get_new_tracks();
+        res.meters = res.get_new_meters();
+        res
+    }
+    /// Reuses the regions rendered offline with the same content.
+    pub fn with_cache(mut self, cache: Arc<RenderCache>) -> Self {
+        self.cache = Some(cache);
+        self.tracks = self.get_new_tracks();
+        self
+    }
+    /// Reports the progress of the offline rendering into `batch`.
+    pub fn with_batch(mut self, batch: Arc<RenderBatch>) -> Self {
+        self.batch = batch;
+        self.tracks = self.get_new_tracks();
+        self
+    }
+    /// Renders every region as a whole before the playback, so that the output does not depend on the speed of the worker threads.
+    pub fn without_streaming(mut self) -> Self {
+        self.streaming = false;
+        self.tracks = self.get_new_tracks();
+        self
     }
-    fn get_new_tracks(project: &data::Project) -> Vec<super::track::Model> {
+    fn get_new_tracks(&self) -> Vec<super::track::Model> {
+        let project = &self.param;
         project
             .tracks
             .iter()
+            // the transformer track has no audio yet. Skipped also in the meters to keep them aligned.
+            .filter(|t| !matches!(t, data::Track::Transformer()))
             .map(|t| match t {
-                data::Track::Regions(r) => super::track::Model::new(r.clone(), 2),
-                data::Track::Generator(_) => todo!(),
-                data::Track::Transformer() => todo!(),
+                data::Track::Regions(r, param) => super::track::Model::new(
+                    r.clone(),
+                    param.overlap.clone(),
+                    param.layout.get(),
+                    project.markers.clone(),
+                    self.streaming,
+                    self.cache.clone(),
+                    self.batch.clone(),
+                )
+                .with_pan(param.pan.clone()),
+                data::Track::Generator(g, param) => super::track::Model::new(
+                    vec![],
+                    data::SharedOverlapMode::default(),
+                    param.layout.get(),
+                    project.markers.clone(),
+                    self.streaming,
+                    self.cache.clone(),
+                    self.batch.clone(),
+                )
+                .with_generator(g)
+                .with_pan(param.pan.clone()),
+                data::Track::Transformer() => unreachable!(),
             })
             .collect::<Vec<_>>()
     }
+    fn get_new_meters(&self) -> Vec<Meter> {
+        self.param
+            .tracks
+            .iter()
+            .filter_map(|t| match t {
+                data::Track::Regions(_, param) | data::Track::Generator(_, param) => {
+                    Some(Meter::new(param.meter.clone()))
+                }
+                data::Track::Transformer() => None,
+            })
+            .collect()
+    }
+    /// Mixes all tracks into the continuous part of the timeline without a loop jump.
+    fn render_tracks(&mut self, input: &[f32], output: &mut [f32], info: &PlaybackInfo) {
+        if self.tmp_buffer.len() < output.len() {
+            self.tmp_buffer.resize(output.len(), 0.0);
+        }
+        let tmp = &mut self.tmp_buffer[..output.len()];
+        let chs = info.channels.count();
+        for (track, meter) in self.tracks.iter_mut().zip(self.meters.iter_mut()) {
+            track.render(input, tmp, info);
+            meter.process(tmp, chs);
+            output
+                .iter_mut()
+                .zip(tmp.iter())
+                .for_each(|(out, tmp)| *out += *tmp);
+        }
+    }
 }
 impl Component for Model {
     fn get_input_channels(&self) -> u64 {
@@ -40,28 +127,137 @@ impl Component for Model {
         2
     }
     fn prepare_play(&mut self, info: &PlaybackInfo) {
-        self.tracks = Self::get_new_tracks(&self.param);
-        let new_len = (info.frame_per_buffer) as usize;
+        self.tracks = self.get_new_tracks();
+        self.meters = self.get_new_meters();
+        // allocated before the playback so that the audio thread does not allocate.
+        let new_len = info.frame_per_buffer as usize * info.channels.count();
         self.tmp_buffer.resize(new_len, 0.0);
 
         for track in self.tracks.iter_mut() {
             track.prepare_play(info);
         }
+        // the regions of all the tracks are rendered in parallel, so they are waited for at once.
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let deadline = std::time::Instant::now() + super::region::stream::PREFILL_TIMEOUT;
+            for track in self.tracks.iter() {
+                track.wait_prefill(deadline);
+            }
+        }
+        for meter in self.meters.iter_mut() {
+            meter.prepare_play(info.sample_rate);
+        }
+        self.master_meter.prepare_play(info.sample_rate);
     }
     fn render(&mut self, input: &[f32], output: &mut [f32], info: &PlaybackInfo) {
         output.fill(0.0);
         assert_eq!(
             output.len(),
-            (info.channels * info.frame_per_buffer) as usize
+            info.channels.count() * info.frame_per_buffer as usize
         );
         //sometimes buffer size at first block is shorter than the specified size
         // assert_eq!(output.len(), self.tmp_buffer.len());
-        for track in self.tracks.iter_mut() {
-            track.render(input, self.tmp_buffer.as_mut_slice(), info);
-            output
-                .iter_mut()
-                .zip(self.tmp_buffer.iter())
-                .for_each(|(out, tmp)| *out += *tmp);
+
+        // split the block at the loop end so that the loop jumps sample-accurately.
+        let chs = info.channels.count();
+        let frames = output.len() / chs;
+        let mut info_local = info.clone();
+        let mut offset = 0;
+        while offset < frames {
+            let now = info_local.current_time;
+            let remaining = frames - offset;
+            let len = match self.param.markers.loop_range.get_samples(info.sample_rate) {
+                Some((_start, end)) if now < end => (end - now).min(remaining),
+                _ => remaining,
+            };
+            info_local.frame_per_buffer = len as u64;
+            self.render_tracks(
+                super::slice_input(input, frames, offset..offset + len),
+                &mut output[offset * chs..(offset + len) * chs],
+                &info_local,
+            );
+            info_local.current_time = self.next_time(&info_local);
+            offset += len;
         }
+        self.master_meter.process(output, chs);
+    }
+    fn next_time(&self, info: &PlaybackInfo) -> usize {
+        self.param.markers.advance(
+            info.current_time,
+            info.frame_per_buffer as usize,
+            info.sample_rate,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio::fixture;
+    use crate::utils::SimpleAtomic;
+
+    #[test]
+    fn loop_wraps_in_block() {
+        let sample_rate = 1000;
+        let channels = data::ChannelLayout::Stereo;
+        let frames = 256;
+        let project = fixture::project(vec![fixture::constant_region(1.0, 0.0, 0.25)], 1000);
+        project.markers.loop_range.range.set_end(0.5);
+        project.markers.loop_range.enabled.store(true);
+        let mut model = Model::new(project, Arc::new(data::Transport::new()));
+        let mut info = PlaybackInfo {
+            sample_rate,
+            current_time: 0,
+            frame_per_buffer: frames,
+            channels,
+        };
+        model.prepare_play(&info);
+        let input = vec![0.0f32; 1];
+        let mut output = vec![0.0f32; frames as usize * channels.count()];
+        model.render(&input, &mut output, &info);
+        info.current_time = model.next_time(&info);
+        assert_eq!(info.current_time, 256);
+        model.render(&input, &mut output, &info);
+        // 244 samples until the loop end, and the region starts again from the loop start.
+        assert_eq!(output[243 * 2], 0.0);
+        assert_eq!(output[244 * 2], 1.0);
+        assert_eq!(model.next_time(&info), 12);
+    }
+    #[test]
+    fn meters_track_and_master() {
+        let project = fixture::project(vec![fixture::constant_region(1.0, 0.0, 0.25)], 1000);
+        let data::Track::Regions(_, param) = &project.tracks[0] else {
+            unreachable!()
+        };
+        let track_meter = param.meter.clone();
+        let transport = Arc::new(data::Transport::new());
+        let mut model = Model::new(project, transport.clone());
+        let info = PlaybackInfo {
+            sample_rate: 1000,
+            current_time: 0,
+            frame_per_buffer: 64,
+            channels: data::ChannelLayout::Stereo,
+        };
+        model.prepare_play(&info);
+        let mut output = vec![0.0f32; 128];
+        model.render(&[0.0], &mut output, &info);
+        assert_eq!(track_meter.peak[0].load(), 1.0);
+        assert_eq!(transport.meter.peak[1].load(), 1.0);
+        assert!(transport.meter.true_peak[0].load() >= 1.0);
+        assert!(track_meter.is_clipped() && transport.meter.is_clipped());
+    }
+    #[test]
+    fn no_wrap_after_loop_end() {
+        let project = fixture::project(vec![fixture::constant_region(1.0, 0.0, 0.25)], 1000);
+        project.markers.loop_range.range.set_end(0.5);
+        project.markers.loop_range.enabled.store(true);
+        let model = Model::new(project, Arc::new(data::Transport::new()));
+        let info = PlaybackInfo {
+            sample_rate: 1000,
+            current_time: 600,
+            frame_per_buffer: 256,
+            channels: data::ChannelLayout::Stereo,
+        };
+        assert_eq!(model.next_time(&info), 856);
     }
 }
@@ -1,28 +1,185 @@
+use super::ambisonic;
+use super::mixer::{self, Mixer};
+use super::pool::RenderBatch;
+use super::region::cache::{self, RenderCache};
 use crate::audio::{Component, PlaybackInfo};
-use crate::data;
+use crate::data::{self, ChannelLayout};
+use std::sync::Arc;
+
+#[cfg(not(target_arch = "wasm32"))]
+use super::{
+    pool::JobHandle,
+    region::stream::{self, LiveRegion, RenderedRegion, StreamingRegion},
+};
+
+/// A region prepared for the playback.
+#[derive(Debug)]
+enum RegionPlayer {
+    /// Rendered offline into the cache as a whole.
+    Cached(super::region::Model),
+    /// Rendered block by block ahead of the playhead.
+    #[cfg(not(target_arch = "wasm32"))]
+    Streaming(StreamingRegion),
+    /// Processes the live input in the audio thread.
+    #[cfg(not(target_arch = "wasm32"))]
+    Live(LiveRegion),
+    /// Rendered offline in the worker pool while playing, and rendered again when the parameters changed.
+    #[cfg(not(target_arch = "wasm32"))]
+    Rendered(RenderedRegion),
+    /// Being rendered in the worker pool. Silent until the result arrives.
+    /// The handle is kept after that so that it is not dropped on the audio thread.
+    #[cfg(not(target_arch = "wasm32"))]
+    Rendering {
+        params: data::Region,
+        handle: JobHandle<super::region::Model>,
+        model: Option<super::region::Model>,
+    },
+}
+
+impl RegionPlayer {
+    fn get_params(&self) -> &data::Region {
+        match self {
+            RegionPlayer::Cached(model) => &model.params,
+            #[cfg(not(target_arch = "wasm32"))]
+            RegionPlayer::Streaming(region) => &region.params,
+            #[cfg(not(target_arch = "wasm32"))]
+            RegionPlayer::Live(region) => &region.params,
+            #[cfg(not(target_arch = "wasm32"))]
+            RegionPlayer::Rendered(region) => &region.params,
+            #[cfg(not(target_arch = "wasm32"))]
+            RegionPlayer::Rendering { params, .. } => params,
+        }
+    }
+    /// Picks up the result of the rendering job if finished. Does not block.
+    fn poll(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let RegionPlayer::Rendering { handle, model, .. } = self {
+            if model.is_none() {
+                *model = handle.try_take();
+            }
+        }
+    }
+    /// length in frames.
+    fn get_len(&self, channels: usize) -> usize {
+        match self {
+            RegionPlayer::Cached(model) => model.interleaved_samples_cache.len() / channels,
+            #[cfg(not(target_arch = "wasm32"))]
+            RegionPlayer::Streaming(region) => region.get_len(),
+            #[cfg(not(target_arch = "wasm32"))]
+            RegionPlayer::Live(region) => region.get_len(),
+            #[cfg(not(target_arch = "wasm32"))]
+            RegionPlayer::Rendered(region) => region.get_len(),
+            #[cfg(not(target_arch = "wasm32"))]
+            RegionPlayer::Rendering { model, .. } => model
+                .as_ref()
+                .map_or(0, |m| m.interleaved_samples_cache.len() / channels),
+        }
+    }
+    /// Reads the samples from `pos` frames after the beginning of the region.
+    /// `input` is the device input for the same frames as `dest`.
+    fn read(&mut self, pos: usize, input: &[f32], dest: &mut [f32], channels: usize) {
+        match self {
+            RegionPlayer::Cached(model) => read_rendered(model, pos, dest, channels),
+            #[cfg(not(target_arch = "wasm32"))]
+            RegionPlayer::Streaming(region) => region.read(pos, dest),
+            #[cfg(not(target_arch = "wasm32"))]
+            RegionPlayer::Live(region) => region.read(pos, input, dest),
+            #[cfg(not(target_arch = "wasm32"))]
+            RegionPlayer::Rendered(region) => region.read(pos, dest),
+            #[cfg(not(target_arch = "wasm32"))]
+            RegionPlayer::Rendering { model, .. } => match model {
+                Some(model) => read_rendered(model, pos, dest, channels),
+                None => dest.fill(0.0),
+            },
+        }
+    }
+}
+
+fn read_rendered(model: &super::region::Model, pos: usize, dest: &mut [f32], channels: usize) {
+    let cache = &model.interleaved_samples_cache;
+    let start = (pos * channels).min(cache.len());
+    let end = (start + dest.len()).min(cache.len());
+    dest[..end - start].copy_from_slice(&cache[start..end]);
+    dest[end - start..].fill(0.0);
+}
 
 #[derive(Debug)]
 pub struct Model {
+    /// regions without the pan and encode filters, which are applied on reading instead of rendered.
     param: Vec<data::Region>,
-    _channels: u64,
-    regions: Vec<super::region::Model>,
+    /// pan of each region, if panned. Not applied to the ambisonic tracks.
+    pans: Vec<Option<data::PanParam>>,
+    /// direction of each region, if encoded. Applied only to the ambisonic tracks.
+    directions: Vec<Option<data::DirectionParam>>,
+    /// applied after the regions and the generator are mixed.
+    pan: data::PanParam,
+    /// shared with the project so that the mode can be switched while playing.
+    overlap: data::SharedOverlapMode,
+    /// used by streaming regions to render across the loop jump.
+    markers: data::Markers,
+    streaming: bool,
+    cache: Option<Arc<RenderCache>>,
+    /// counts the regions rendered in the worker pool.
+    batch: Arc<RenderBatch>,
+    /// layout the regions and the generator are rendered in.
+    layout: ChannelLayout,
+    /// from the layout of the track into the output.
+    mixer: Mixer,
+    /// the track rendered in its layout before mixed into the output.
+    track_buffer: Vec<f32>,
+    regions: Vec<RegionPlayer>,
+    /// content of the generator track, played over the whole timeline.
+    generator: Option<Box<dyn Component + Send + Sync>>,
+    /// used for mixing regions with gains, allocated before the playback.
+    region_buffer: Vec<f32>,
+    /// start and end frames of the regions for the current block.
+    spans: Vec<(usize, usize)>,
 }
 
 impl Model {
-    pub fn new(param: Vec<data::Region>, channels: u64) -> Self {
-        let regions = Self::get_new_regions(&param, channels);
-
+    pub fn new(
+        param: Vec<data::Region>,
+        overlap: data::SharedOverlapMode,
+        layout: ChannelLayout,
+        markers: data::Markers,
+        streaming: bool,
+        cache: Option<Arc<RenderCache>>,
+        batch: Arc<RenderBatch>,
+    ) -> Self {
+        let pans = param.iter().map(|r| r.get_pan().cloned()).collect();
+        let directions = param.iter().map(|r| r.get_direction().cloned()).collect();
+        let param = param
+            .into_iter()
+            .map(data::Region::without_playback_filters)
+            .collect();
         Self {
             param,
-            _channels: channels,
-            regions,
+            pans,
+            directions,
+            pan: data::PanParam::default(),
+            overlap,
+            markers,
+            streaming,
+            cache,
+            batch,
+            layout,
+            mixer: Mixer::new(layout, layout),
+            track_buffer: vec![],
+            regions: vec![],
+            generator: None,
+            region_buffer: vec![],
+            spans: vec![],
         }
     }
-    fn get_new_regions(param: &[data::Region], channels: u64) -> Vec<super::region::Model> {
-        param
-            .iter()
-            .map(|region| super::region::Model::new(region.clone(), channels))
-            .collect::<Vec<_>>()
+    /// Shares the pan of the track, so that it can be moved while playing.
+    pub fn with_pan(mut self, pan: data::PanParam) -> Self {
+        self.pan = pan;
+        self
+    }
+    /// Makes the track play the generator instead of the regions.
+    pub fn with_generator(mut self, generator: &crate::script::Value) -> Self {
+        self.generator = Some(super::generator::get_component_for_value(generator));
+        self
     }
     fn renew_regions(&mut self, info: &PlaybackInfo) {
         //fetch update.
@@ -30,29 +187,138 @@ impl Model {
         let channels = info.channels;
         #[cfg(not(target_arch = "wasm32"))]
         let res = {
-            self.param
+            // regions which need the whole region are rendered offline in the worker pool, others are streamed.
+            let players = self
+                .param
                 .iter()
                 .map(|region| {
-                    //temporary moves value to
-                    let model = super::region::Model::new(region.clone(), channels);
-                    super::region::render_region_offline_async(model, info)
+                    if stream::is_live(region) {
+                        if let Some(c) =
+                            stream::get_stream_component(region, info.sample_rate, channels)
+                        {
+                            return RegionPlayer::Live(LiveRegion::new(region.clone(), c, info));
+                        }
+                    }
+                    let component = self
+                        .streaming
+                        .then(|| stream::get_stream_component(region, info.sample_rate, channels))
+                        .flatten();
+                    if let Some(component) = component {
+                        let region =
+                            StreamingRegion::new(region.clone(), component, &self.markers, info);
+                        return RegionPlayer::Streaming(region);
+                    }
+                    let key = cache::content_hash(region, info.sample_rate, channels);
+                    let cached = self.get_cached(key, region, channels);
+                    if self.streaming {
+                        return RegionPlayer::Rendered(RenderedRegion::new(
+                            region.clone(),
+                            cached,
+                            info,
+                            self.cache.clone(),
+                            self.batch.clone(),
+                        ));
+                    }
+                    match cached {
+                        Some(model) => RegionPlayer::Cached(model),
+                        None => {
+                            let model = super::region::Model::new(region.clone(), channels);
+                            let cache = self.cache.clone();
+                            let handle = super::region::render_region_offline_async(
+                                model,
+                                info,
+                                &self.batch,
+                                move |model| {
+                                    if let Some(cache) = cache {
+                                        cache.insert(key, model.interleaved_samples_cache.clone());
+                                    }
+                                },
+                            );
+                            RegionPlayer::Rendering {
+                                params: region.clone(),
+                                handle,
+                                model: None,
+                            }
+                        }
+                    }
                 })
-                .map(move |h| h.join().expect("hoge"))
-                .collect::<Vec<super::region::Model>>()
+                .collect::<Vec<_>>();
+            if self.streaming {
+                players
+            } else {
+                // the output must not depend on the speed of the workers.
+                players
+                    .into_iter()
+                    .map(|player| match player {
+                        RegionPlayer::Rendering {
+                            params,
+                            handle,
+                            model,
+                        } => {
+                            handle.wait();
+                            match handle.try_take() {
+                                Some(model) => RegionPlayer::Cached(model),
+                                None => {
+                                    // stays silent, keeping the order of the regions.
+                                    log::error!("failed to render the region {}", params.label);
+                                    RegionPlayer::Rendering {
+                                        params,
+                                        handle,
+                                        model,
+                                    }
+                                }
+                            }
+                        }
+                        player => player,
+                    })
+                    .collect()
+            }
         };
         #[cfg(target_arch = "wasm32")]
         let res = self
             .param
             .iter()
             .map(|region| {
-                let mut model = super::region::Model::new(region.clone(), channels);
-                model.render_offline(info.sample_rate, info.channels);
-                model
+                let key = cache::content_hash(region, info.sample_rate, channels);
+                let model = self.get_cached(key, region, channels).unwrap_or_else(|| {
+                    let mut model = super::region::Model::new(region.clone(), channels);
+                    model.render_offline(info.sample_rate, info.channels);
+                    self.store_cache(key, &model);
+                    model
+                });
+                RegionPlayer::Cached(model)
             })
             .collect::<Vec<_>>();
 
         self.regions = res;
     }
+    /// Waits for the first blocks of the streaming regions until the `deadline`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn wait_prefill(&self, deadline: std::time::Instant) {
+        for region in self.regions.iter() {
+            if let RegionPlayer::Streaming(region) = region {
+                region.wait_prefill(deadline);
+            }
+        }
+    }
+    /// Returns the region filled with the samples rendered before, if any.
+    fn get_cached(
+        &self,
+        key: u64,
+        region: &data::Region,
+        channels: ChannelLayout,
+    ) -> Option<super::region::Model> {
+        let samples = self.cache.as_ref()?.get(key)?;
+        let mut model = super::region::Model::new(region.clone(), channels);
+        model.set_rendered(samples.as_ref().clone());
+        Some(model)
+    }
+    #[cfg(target_arch = "wasm32")]
+    fn store_cache(&self, key: u64, model: &super::region::Model) {
+        if let Some(cache) = &self.cache {
+            cache.insert(key, model.interleaved_samples_cache.clone());
+        }
+    }
 }
 
 impl Component for Model {
@@ -60,34 +326,302 @@ impl Component for Model {
         0
     }
     fn get_output_channels(&self) -> u64 {
-        2
+        self.layout.count() as u64
     }
     fn prepare_play(&mut self, info: &PlaybackInfo) {
-        self.renew_regions(info);
+        let track_info = PlaybackInfo {
+            channels: self.layout,
+            ..info.clone()
+        };
+        self.renew_regions(&track_info);
+        if let Some(generator) = &mut self.generator {
+            generator.prepare_play(&track_info);
+        }
+        if self.mixer.get_to() != info.channels {
+            self.mixer = Mixer::new(self.layout, info.channels);
+        }
+        let len = info.frame_per_buffer as usize * self.layout.count();
+        self.region_buffer.resize(len, 0.0);
+        self.track_buffer.resize(len, 0.0);
+        self.spans.resize(self.regions.len(), (0, 0));
     }
-    fn render(&mut self, _input: &[f32], output: &mut [f32], info: &PlaybackInfo) {
-        //後に入ってるリージョンで基本は上書きする
-        //channel is tekitou
-        let chs = 2;
+    fn render(&mut self, input: &[f32], output: &mut [f32], info: &PlaybackInfo) {
+        if self.mixer.is_identity() {
+            self.render_track(input, output, info);
+            self.apply_track_pan(output);
+            return;
+        }
+        let frames = output.len() / info.channels.count();
+        let track_info = PlaybackInfo {
+            channels: self.layout,
+            ..info.clone()
+        };
+        let len = frames * self.layout.count();
+        if self.track_buffer.len() < len {
+            self.track_buffer.resize(len, 0.0);
+        }
+        // taken out during the rendering, which does not allocate.
+        let mut buffer = std::mem::take(&mut self.track_buffer);
+        self.render_track(input, &mut buffer[..len], &track_info);
+        self.apply_track_pan(&mut buffer[..len]);
+        self.mixer.process(&buffer[..len], output);
+        self.track_buffer = buffer;
+    }
+}
+
+impl Model {
+    /// The front pair is panned only on the speaker layouts, as ambisonics has no pair.
+    fn apply_track_pan(&self, buf: &mut [f32]) {
+        if !self.layout.is_ambisonic() {
+            mixer::apply_pan(buf, self.layout.count(), &self.pan);
+        }
+    }
+    /// Renders the regions and the generator in the layout of the track.
+    fn render_track(&mut self, input: &[f32], output: &mut [f32], info: &PlaybackInfo) {
+        let chs = info.channels.count();
         output.fill(0.0);
-        for (count, out_per_channel) in output.chunks_mut(chs as usize).enumerate() {
-            let now = (info.current_time + count) as i64;
-            let now_in_sec = now as f64 / info.sample_rate as f64;
-            out_per_channel.iter_mut().enumerate().for_each(|(ch, s)| {
-                //順にリージョンを読んでいくので、重なってる場合は後の要素のやつが上書きする形になる
-                for region in self.regions.iter() {
-                    let start_samp = (region.params.range.start() * info.sample_rate as f64) as i64;
-                    if region.params.range.contains(now_in_sec) {
-                        let read_point = ((now - start_samp) * chs) as usize;
-                        // 再生中にRangeを変更すると範囲外アクセスの可能性はあるので対応
-                        let out = region
-                            .interleaved_samples_cache
-                            .get(read_point + ch)
-                            .unwrap_or(&0.0);
-                        *s = *out;
-                    }
-                }
-            });
+        let now = info.current_time;
+        let frames = output.len() / chs;
+        if let Some(generator) = &mut self.generator {
+            generator.render(input, output, info);
+        }
+        let mode = self.overlap.get();
+        for (region, span) in self.regions.iter_mut().zip(self.spans.iter_mut()) {
+            region.poll();
+            let start = (region.get_params().range.start() * info.sample_rate as f64) as usize;
+            *span = (start, start + region.get_len(chs));
+        }
+        if self.region_buffer.len() < output.len() {
+            self.region_buffer.resize(output.len(), 0.0);
+        }
+        for (i, region) in self.regions.iter_mut().enumerate() {
+            let (start, end) = self.spans[i];
+            let (from, to) = (now.max(start), (now + frames).min(end));
+            if from >= to {
+                continue;
+            }
+            let dest = &mut output[(from - now) * chs..(to - now) * chs];
+            let input = super::slice_input(input, frames, from - now..to - now);
+            let (pan, direction) = match self.layout.is_ambisonic() {
+                true => (None, self.directions[i].as_ref()),
+                false => (self.pans[i].as_ref(), None),
+            };
+            if mode == data::OverlapMode::LaterWins && pan.is_none() && direction.is_none() {
+                // regions are read in order, so the later one overwrites where they overlap.
+                region.read(from - start, input, dest, chs);
+                continue;
+            }
+            let buf = &mut self.region_buffer[..dest.len()];
+            region.read(from - start, input, buf, chs);
+            if let Some(pan) = pan {
+                mixer::apply_pan(buf, chs, pan);
+            }
+            if let Some(direction) = direction {
+                ambisonic::encode(buf, chs, direction);
+            }
+            if mode == data::OverlapMode::LaterWins {
+                dest.copy_from_slice(buf);
+                continue;
+            }
+            if mode == data::OverlapMode::Crossfade {
+                apply_crossfade(buf, from, chs, i, &self.spans);
+            }
+            dest.iter_mut().zip(buf.iter()).for_each(|(d, s)| *d += *s);
+        }
+    }
+}
+
+/// Applies the equal-power gains to the samples of the `index`th region starting at `from`,
+/// over the zones where it overlaps with the other regions.
+fn apply_crossfade(
+    buf: &mut [f32],
+    from: usize,
+    channels: usize,
+    index: usize,
+    spans: &[(usize, usize)],
+) {
+    let span = spans[index];
+    let others = spans
+        .iter()
+        .enumerate()
+        .filter_map(|(j, other)| (j != index).then_some(*other));
+    for other in others {
+        let fade_in = data::crossfade_zone(other, span).map(|zone| (zone, true));
+        let fade_out = data::crossfade_zone(span, other).map(|zone| (zone, false));
+        for ((zone_start, zone_end), is_in) in fade_in.into_iter().chain(fade_out) {
+            let len = (zone_end - zone_start) as f32;
+            let frames = buf.len() / channels;
+            let (first, last) = (
+                zone_start.saturating_sub(from).min(frames),
+                zone_end.saturating_sub(from).min(frames),
+            );
+            for k in first..last {
+                let phase = (from + k - zone_start) as f32 / len * std::f32::consts::FRAC_PI_2;
+                let gain = if is_in { phase.sin() } else { phase.cos() };
+                buf[k * channels..(k + 1) * channels]
+                    .iter_mut()
+                    .for_each(|s| *s *= gain);
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio::fixture::constant_region;
+    use crate::{
+        param_float,
+        parameter::{FloatParameter, Parameter, RangedNumeric},
+        script::{Expr, Value},
+        utils::AtomicRange,
+    };
+
+    #[test]
+    fn live_region_processes_input() {
+        let channel = |v: f32| {
+            Expr::Literal(Value::Parameter(Arc::new(param_float!(
+                v,
+                "ch",
+                0.0..=15.0
+            ))))
+        };
+        let generator = Value::new_lazy(Expr::App(
+            Expr::Literal(Value::ExtFunction("input".to_string())).into(),
+            vec![channel(2.0), channel(0.0)],
+        ));
+        let region = data::Region::new(
+            AtomicRange::<f64>::new(0.5, 1.0),
+            data::Content::Generator(generator),
+            "input",
+        );
+        let mut track = Model::new(
+            vec![region],
+            data::SharedOverlapMode::default(),
+            ChannelLayout::Stereo,
+            data::Markers::default(),
+            true,
+            None,
+            Arc::new(RenderBatch::new()),
+        );
+        let info = PlaybackInfo {
+            sample_rate: 1000,
+            current_time: 400,
+            frame_per_buffer: 200,
+            channels: ChannelLayout::Stereo,
+        };
+        track.prepare_play(&info);
+        // 3 channels input, where the channel 0 is the frame index and the channel 2 is negative of it.
+        let input = (0..200)
+            .flat_map(|i| [i as f32, 0.5, -(i as f32)])
+            .collect::<Vec<_>>();
+        let mut output = vec![0.0; 400];
+        track.render(&input, &mut output, &info);
+        assert_eq!(&output[..4], &[0.0; 4]);
+        // the region starts at the frame 100 of the block.
+        assert_eq!(&output[200..204], &[-100.0, 100.0, -101.0, 101.0]);
+    }
+    #[test]
+    fn overlap_modes() {
+        let overlap = data::SharedOverlapMode::default();
+        let regions = vec![
+            constant_region(0.5, 0.0, 0.5),
+            constant_region(0.5, 0.25, 0.75),
+        ];
+        let mut track = Model::new(
+            regions,
+            overlap.clone(),
+            ChannelLayout::Stereo,
+            data::Markers::default(),
+            false,
+            None,
+            Arc::new(RenderBatch::new()),
+        );
+        let mut info = PlaybackInfo {
+            sample_rate: 1000,
+            current_time: 0,
+            frame_per_buffer: 1000,
+            channels: ChannelLayout::Stereo,
+        };
+        track.prepare_play(&info);
+        let mut render = |mode| {
+            overlap.set(mode);
+            let mut output = vec![0.0; 2000];
+            track.render(&[], &mut output, &info);
+            info.current_time = 0;
+            output
+        };
+        let later_wins = render(data::OverlapMode::LaterWins);
+        assert_eq!(later_wins[375 * 2], 0.5);
+        let sum = render(data::OverlapMode::Sum);
+        assert_eq!(sum[375 * 2], 1.0);
+        let crossfade = render(data::OverlapMode::Crossfade);
+        // equal power at the middle of the zone.
+        assert!((crossfade[375 * 2] - 0.5 * std::f32::consts::SQRT_2).abs() < 1e-4);
+        assert_eq!(crossfade[250 * 2], 0.5);
+        assert!(crossfade[499 * 2] < 0.51);
+        assert_eq!(crossfade[600 * 2], 0.5);
+    }
+    #[test]
+    fn region_and_track_pan() {
+        let pan = data::PanParam::new();
+        pan.pan.set(1.0);
+        pan.set_law(data::PanLaw::Linear);
+        let origin = constant_region(0.5, 0.0, 1.0);
+        let region = data::Region::new(
+            origin.range.clone(),
+            data::Content::Transformer(data::RegionFilter::Pan(pan.clone()), Box::new(origin)),
+            "constant",
+        );
+        let track_pan = data::PanParam::new();
+        let mut track = Model::new(
+            vec![region],
+            data::SharedOverlapMode::default(),
+            ChannelLayout::Stereo,
+            data::Markers::default(),
+            false,
+            None,
+            Arc::new(RenderBatch::new()),
+        )
+        .with_pan(track_pan.clone());
+        let info = PlaybackInfo {
+            sample_rate: 1000,
+            current_time: 0,
+            frame_per_buffer: 100,
+            channels: ChannelLayout::Stereo,
+        };
+        track.prepare_play(&info);
+        let mut output = vec![0.0; 200];
+        track.render(&[], &mut output, &info);
+        assert_eq!(&output[..2], &[0.0, 1.0]);
+        // both are read on every block, so they can be moved while playing.
+        pan.pan.set(0.0);
+        track_pan.pan.set(-1.0);
+        track.render(&[], &mut output, &info);
+        assert!((output[0] - 1.0).abs() < 1e-6 && output[1].abs() < 1e-6);
+    }
+    #[test]
+    fn surround_track_into_stereo() {
+        let mut track = Model::new(
+            vec![constant_region(0.5, 0.0, 1.0)],
+            data::SharedOverlapMode::default(),
+            ChannelLayout::Surround51,
+            data::Markers::default(),
+            false,
+            None,
+            Arc::new(RenderBatch::new()),
+        );
+        let info = PlaybackInfo {
+            sample_rate: 1000,
+            current_time: 0,
+            frame_per_buffer: 100,
+            channels: ChannelLayout::Stereo,
+        };
+        track.prepare_play(&info);
+        let mut output = vec![0.0; 200];
+        track.render(&[], &mut output, &info);
+        // the mono generator goes to the center, which is folded into both sides at -3dB.
+        let expected = 0.5 * std::f32::consts::FRAC_1_SQRT_2;
+        assert!(output.iter().all(|s| (s - expected).abs() < 1e-6));
+    }
+}
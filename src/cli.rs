@@ -1,10 +1,18 @@
-use crate::data::LaunchArg;
+use crate::audio::{backend, offline, pool::RenderBatch};
+use crate::data::{self, LaunchArg};
+use crate::utils::SimpleAtomic;
+use clap::builder::{PossibleValuesParser, TypedValueParser};
 pub use clap::Parser;
+use clap::{Args as ClapArgs, Subcommand, ValueEnum};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 /// otopoiesis - constructive sound design environment
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
     /// Path of project file to open
     file: Option<String>,
     /// Path of project directory. Ignored when the file path is absolute
@@ -16,6 +24,60 @@ pub struct Args {
     /// (currently not implemented) log infomation level (1:trace 2:info 3:warn 4:error 5:none)
     #[arg(short, long, default_value_t = 3)]
     log_level: u8,
+    /// Audio backend. Falls back to null when no audio device is available
+    #[arg(long, value_enum, default_value_t = Backend::Cpal)]
+    backend: Backend,
+    /// Path of the wav file written by the file backend
+    #[arg(long, default_value = "otopoiesis_out.wav")]
+    backend_file: String,
+    /// Store rendered regions under the project directory to start the playback quickly next time
+    #[arg(long)]
+    persist_render_cache: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Backend {
+    /// Default audio devices
+    Cpal,
+    /// No audio output
+    Null,
+    /// Write the output into the wav file
+    File,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Render the project into a wav file without opening the window and audio devices
+    Render(RenderArgs),
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct RenderArgs {
+    /// Path of project file to render
+    file: String,
+    /// Path of the output wav file
+    #[arg(short, long)]
+    output: String,
+    /// Sample rate of the output (default: the sample rate of the project)
+    #[arg(short, long)]
+    sample_rate: Option<u32>,
+    /// Bit depth of the output (16, 24: integer, 32: float)
+    #[arg(
+        short,
+        long,
+        default_value_t = 24,
+        value_parser = PossibleValuesParser::new(["16", "24", "32"]).map(|s| s.parse::<u16>().unwrap())
+    )]
+    bit_depth: u16,
+    /// Start time in seconds
+    #[arg(long, default_value_t = 0.0)]
+    start: f64,
+    /// End time in seconds (default: the end of the last region)
+    #[arg(long)]
+    end: Option<f64>,
+    /// Export the raw B-format (ACN/SN3D) of the ambisonic order instead of stereo
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=7))]
+    ambisonic: Option<u8>,
 }
 
 impl From<Args> for LaunchArg {
@@ -26,6 +88,68 @@ impl From<Args> for LaunchArg {
             project_root: val.project_root.or(arg.project_root),
             config_dir: val.config_dir.or(arg.config_dir),
             log_level: val.log_level,
+            backend: match val.backend {
+                Backend::Cpal => backend::Kind::Cpal,
+                Backend::Null => backend::Kind::Null,
+                Backend::File => backend::Kind::File(val.backend_file),
+            },
+            persist_render_cache: val.persist_render_cache,
+        }
+    }
+}
+
+/// Loads and evaluates the project file, then renders it into the wav file.
+pub fn render(args: &RenderArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let launch_arg = LaunchArg {
+        file: Some(args.file.clone()),
+        ..Default::default()
+    };
+    let mut app = data::AppModel::new(
+        data::Transport::new(),
+        data::GlobalSetting::default(),
+        launch_arg,
+    );
+    if app.project_str.is_empty() {
+        return Err(format!("failed to read project file {}", args.file).into());
+    }
+    app.code_to_ui()?;
+    let source = app.source.clone().ok_or("project has no source")?;
+    if !app.compile(source) {
+        return Err(format!("failed to evaluate project {}", args.file).into());
+    }
+    let batch = Arc::new(RenderBatch::new());
+    let opt = offline::RenderOption {
+        sample_rate: args
+            .sample_rate
+            .unwrap_or(app.project.sample_rate.load() as u32),
+        bit_depth: args.bit_depth,
+        start: args.start,
+        end: args.end,
+        progress: Some(batch.clone()),
+        layout: args
+            .ambisonic
+            .map_or(data::ChannelLayout::Stereo, data::ChannelLayout::Ambisonic),
+    };
+    let done = AtomicBool::new(false);
+    let samples = std::thread::scope(|s| {
+        s.spawn(|| report_progress(&batch, &done));
+        let samples = offline::render_project(&app.project, &opt);
+        done.store(true, Ordering::Relaxed);
+        samples
+    });
+    offline::write_wav(&args.output, &samples, &opt)?;
+    Ok(())
+}
+
+/// Prints the number of rendered regions into stderr until `done` is set.
+fn report_progress(batch: &RenderBatch, done: &AtomicBool) {
+    let mut last = (0, 0);
+    while !done.load(Ordering::Relaxed) {
+        let progress = batch.get_progress();
+        if progress != last && progress.1 > 0 {
+            eprintln!("rendering regions {}/{}", progress.0, progress.1);
+            last = progress;
         }
+        std::thread::sleep(std::time::Duration::from_millis(100));
     }
 }
@@ -2,6 +2,7 @@
 
 use crate::action;
 use crate::app::filemanager::{self, FileManager};
+use crate::audio;
 use crate::utils::{atomic, AtomicRange, SimpleAtomic};
 
 use rfd;
@@ -11,12 +12,24 @@ use std::collections::HashMap;
 use std::sync::{mpsc, Arc};
 use undo;
 
+pub mod channel;
 pub mod generator;
+pub mod marker;
+pub mod meter;
+pub mod pan;
 pub mod region;
+pub mod setting;
+pub mod tempo;
 pub mod track;
 
+pub use channel::*;
 pub use generator::*;
+pub use marker::*;
+pub use meter::*;
+pub use pan::*;
 pub use region::*;
+pub use setting::*;
+pub use tempo::*;
 pub use track::*;
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -29,6 +42,9 @@ pub struct LaunchArg {
     pub project_root: Option<String>,
     pub config_dir: Option<String>,
     pub log_level: u8,
+    pub backend: audio::backend::Kind,
+    /// Stores rendered regions under the project directory to reuse them in the next session.
+    pub persist_render_cache: bool,
 }
 impl Default for LaunchArg {
     fn default() -> Self {
@@ -44,6 +60,8 @@ impl Default for LaunchArg {
             project_root: None,
             config_dir,
             log_level: 3,
+            backend: audio::backend::Kind::default(),
+            persist_render_cache: false,
         }
     }
 }
@@ -53,11 +71,13 @@ impl TryFrom<&Value> for Project {
     type Error = ConversionError;
     fn try_from(value: &Value) -> Result<Self, Self::Error> {
         match value {
-            Value::Project(sr, tr) => {
+            Value::Project(sr, tr, tempo, markers) => {
                 let tracks: Vec<Track> = tr.iter().map(Track::try_from).try_collect()?;
                 Ok(Project {
                     sample_rate: (*sr as u64).into(),
                     tracks: tracks,
+                    tempo: tempo.clone(),
+                    markers: markers.clone(),
                 })
             }
             _ => Err(ConversionError {}),
@@ -92,7 +112,12 @@ impl AppModel {
         if let Some(file) = project_file.clone() {
             let _ = filemanager::get_global_file_manager().read_to_string(file, &mut project_str);
         }
-        let source = Some(Expr::Literal(Value::Project(44100., vec![])));
+        let source = Some(Expr::Literal(Value::Project(
+            44100.,
+            vec![],
+            TempoMap::default(),
+            Markers::default(),
+        )));
         let (action_tx, action_rx) = mpsc::channel();
         Self {
             transport,
@@ -108,6 +133,28 @@ impl AppModel {
             builtin_fns: script::builtin_fn::gen_default_functions(),
         }
     }
+    /// Directory to persist the render cache: `.otopoiesis_cache` next to the project file, or in the project root.
+    pub fn render_cache_dir(&self) -> Option<std::path::PathBuf> {
+        if !self.launch_arg.persist_render_cache {
+            return None;
+        }
+        Some(self.project_dir()?.join(".otopoiesis_cache"))
+    }
+    /// Directory to write recorded takes: `recordings` next to the project file, or in the project root.
+    /// Falls back to the current directory for the unsaved project.
+    pub fn recording_dir(&self) -> Option<std::path::PathBuf> {
+        let dir = self
+            .project_dir()
+            .or_else(|| std::env::current_dir().ok())?;
+        Some(dir.join("recordings"))
+    }
+    fn project_dir(&self) -> Option<std::path::PathBuf> {
+        match (&self.project_file, &self.launch_arg.project_root) {
+            (Some(file), _) => Some(std::path::Path::new(file).parent()?.to_path_buf()),
+            (None, Some(root)) => Some(std::path::PathBuf::from(root)),
+            (None, None) => None,
+        }
+    }
     pub fn get_builtin_fn(&self, name: &str) -> Option<&script::ExtFun> {
         self.builtin_fns.get(name)
     }
@@ -230,6 +277,8 @@ pub enum PlayOp {
     Play = 0,
     Pause = 1,
     Halt = 2,
+    /// Plays while recording the input into the armed tracks.
+    Record = 3,
 }
 
 impl From<u8> for PlayOp {
@@ -238,6 +287,7 @@ impl From<u8> for PlayOp {
             0 => Self::Play,
             1 => Self::Pause,
             2 => Self::Halt,
+            3 => Self::Record,
             _ => panic!("invalid operation"),
         }
     }
@@ -249,6 +299,13 @@ pub struct Transport {
     is_playing: atomic::U8,
     pub time: Arc<atomic::U64>, //in sample
     playing_history: atomic::U8,
+    seek_requested: atomic::Bool,
+    /// round-trip latency from the input to the output in samples, measured by the audio thread.
+    #[serde(default)]
+    pub latency: Arc<atomic::U64>,
+    /// levels of the master output.
+    #[serde(skip)]
+    pub meter: Arc<LevelMeter>,
 }
 
 impl Transport {
@@ -261,10 +318,26 @@ impl Transport {
     }
     pub fn is_playing(&self) -> bool {
         match PlayOp::from(self.is_playing.load()) {
-            PlayOp::Play => true,
+            PlayOp::Play | PlayOp::Record => true,
             PlayOp::Pause | PlayOp::Halt => false,
         }
     }
+    pub fn is_recording(&self) -> bool {
+        matches!(PlayOp::from(self.is_playing.load()), PlayOp::Record)
+    }
+    /// Moves the playhead to `sample`. The audio side prepares the components again from there at the next update.
+    pub fn seek(&self, sample: u64) {
+        self.time.store(sample);
+        self.seek_requested.store(true);
+    }
+    /// Returns true only once after [`Transport::seek`] is called.
+    pub fn take_seek_request(&self) -> bool {
+        let res = self.seek_requested.load();
+        if res {
+            self.seek_requested.store(false);
+        }
+        res
+    }
     pub fn ready_to_trigger(&self) -> Option<PlayOp> {
         if self.is_playing.load() != self.playing_history.load() {
             let res = Some(PlayOp::from(self.is_playing.load()));
@@ -282,24 +355,42 @@ impl Default for Transport {
             is_playing: atomic::U8::from(2),
             time: Arc::new(atomic::U64::from(0)),
             playing_history: atomic::U8::from(2),
+            seek_requested: atomic::Bool::from(false),
+            latency: Arc::new(atomic::U64::from(0)),
+            meter: Arc::new(LevelMeter::default()),
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy)]
-pub struct GlobalSetting;
-
 /// A main project data. It should be imported/exported via serde.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Project {
     pub sample_rate: atomic::U64,
     pub tracks: Vec<Track>,
+    #[serde(default)]
+    pub tempo: TempoMap,
+    #[serde(default)]
+    pub markers: Markers,
 }
 impl Project {
     fn new(sample_rate: u64) -> Self {
         Self {
             sample_rate: atomic::U64::from(sample_rate),
             tracks: vec![],
+            tempo: TempoMap::default(),
+            markers: Markers::default(),
         }
     }
+    /// End time in seconds of the last region in the project.
+    pub fn end(&self) -> f64 {
+        self.tracks
+            .iter()
+            .filter_map(|t| match t {
+                Track::Regions(regions, _) => {
+                    regions.iter().map(|r| r.range.end()).reduce(f64::max)
+                }
+                _ => None,
+            })
+            .fold(0.0, f64::max)
+    }
 }
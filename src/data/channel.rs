@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+
+/// Arrangement of the interleaved channels of a track or a device.
+/// The order of the channels follows the WAV/SMPTE convention.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum ChannelLayout {
+    Mono,
+    /// left, right.
+    #[default]
+    Stereo,
+    /// front left, front right, rear left, rear right.
+    Quad,
+    /// front left, front right, center, LFE, surround left, surround right.
+    Surround51,
+    /// channels without the speaker positions, mapped by their index.
+    Discrete(u16),
+    /// full-sphere ambisonics (B-format) of the order, in ACN channel order with SN3D normalization.
+    Ambisonic(u8),
+}
+
+impl ChannelLayout {
+    /// Layouts offered in the track settings, except for the discrete ones.
+    pub const PRESETS: [Self; 4] = [Self::Mono, Self::Stereo, Self::Quad, Self::Surround51];
+    /// Orders of the ambisonic buses offered in the track settings.
+    pub const AMBISONIC_ORDERS: std::ops::RangeInclusive<u8> = 1..=3;
+
+    pub fn count(&self) -> usize {
+        match self {
+            Self::Mono => 1,
+            Self::Stereo => 2,
+            Self::Quad => 4,
+            Self::Surround51 => 6,
+            Self::Discrete(n) => *n as usize,
+            Self::Ambisonic(order) => (*order as usize + 1).pow(2),
+        }
+    }
+    pub fn is_ambisonic(&self) -> bool {
+        matches!(self, Self::Ambisonic(_))
+    }
+    /// Azimuth and elevation in degrees of the speaker of the channel, used for encoding and decoding ambisonics.
+    /// The azimuth is counter-clockwise from the front. The stereo pair is placed at the sides.
+    /// `None` for the LFE and the channels without positions.
+    pub fn speaker_direction(&self, channel: usize) -> Option<(f32, f32)> {
+        let azimuth = match (self, channel) {
+            (Self::Mono, 0) => 0.0,
+            (Self::Stereo, 0) => 90.0,
+            (Self::Stereo, 1) => -90.0,
+            (Self::Quad, 0) => 45.0,
+            (Self::Quad, 1) => -45.0,
+            (Self::Quad, 2) => 135.0,
+            (Self::Quad, 3) => -135.0,
+            (Self::Surround51, 0) => 30.0,
+            (Self::Surround51, 1) => -30.0,
+            (Self::Surround51, 2) => 0.0,
+            (Self::Surround51, 4) => 110.0,
+            (Self::Surround51, 5) => -110.0,
+            _ => return None,
+        };
+        Some((azimuth, 0.0))
+    }
+    /// The preset layout with `count` channels, or discrete channels if there is none.
+    pub fn from_count(count: usize) -> Self {
+        Self::PRESETS
+            .into_iter()
+            .find(|l| l.count() == count)
+            .unwrap_or(Self::Discrete(count as u16))
+    }
+}
+
+impl std::fmt::Display for ChannelLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mono => write!(f, "mono"),
+            Self::Stereo => write!(f, "stereo"),
+            Self::Quad => write!(f, "quad"),
+            Self::Surround51 => write!(f, "5.1"),
+            Self::Discrete(n) => write!(f, "{}ch", n),
+            Self::Ambisonic(order) => write!(f, "ambisonic {}", order),
+        }
+    }
+}
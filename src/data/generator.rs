@@ -36,6 +36,51 @@ pub enum OscillatorFun {
     Rectanglular(Arc<atomic::F32>),
     Triangular,
 }
+/// Spectrum of the noise generator. Each color is a function of the script named by `get_name`.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum NoiseColor {
+    #[default]
+    White,
+    /// -3dB per octave.
+    Pink,
+    /// -6dB per octave.
+    Brown,
+    /// Sparse impulses of random signs, one in each period of the density.
+    Velvet,
+}
+impl NoiseColor {
+    pub const ALL: [Self; 4] = [Self::White, Self::Pink, Self::Brown, Self::Velvet];
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Self::White => "whitenoise",
+            Self::Pink => "pinknoise",
+            Self::Brown => "brownnoise",
+            Self::Velvet => "velvetnoise",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.get_name() == name)
+    }
+}
+/// Utility Parameter for noise. The same seed always renders the same samples.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NoiseParam {
+    pub amp: Arc<FloatParameter>,
+    /// rounded to the integer.
+    pub seed: Arc<FloatParameter>,
+    /// impulses per second, only used by the velvet noise.
+    pub density: Arc<FloatParameter>,
+}
+impl Default for NoiseParam {
+    fn default() -> Self {
+        Self {
+            amp: Arc::new(FloatParameter::new(1.0, "amp").set_range(0.0..=1.0)),
+            seed: Arc::new(FloatParameter::new(0.0, "seed").set_range(0.0..=9999.0)),
+            density: Arc::new(FloatParameter::new(2000.0, "density").set_range(10.0..=20000.0)),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct FilePlayerParam {
     pub path: String,
@@ -67,7 +112,7 @@ impl FilePlayerParam {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum Generator {
     Oscillator(OscillatorFun, Arc<OscillatorParam>),
-    Noise(),
+    Noise(NoiseColor, Arc<NoiseParam>),
     ///mostly for debugging filter.
     Constant(Arc<FloatParameter>),
     #[cfg(not(target_arch = "wasm32"))]
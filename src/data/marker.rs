@@ -0,0 +1,73 @@
+use crate::utils::{atomic, AtomicRange, SimpleAtomic};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Named position on the timeline. The position is shared with GUI so that it can be dragged while playing.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Marker {
+    pub label: String,
+    /// position in seconds.
+    pub time: Arc<atomic::F64>,
+}
+
+impl Marker {
+    pub fn new(label: impl Into<String>, time: f64) -> Self {
+        Self {
+            label: label.into(),
+            time: Arc::new(atomic::F64::from(time)),
+        }
+    }
+}
+
+/// The range of the timeline repeated while playing when enabled.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LoopRange {
+    /// range stores a real time, not in sample.
+    pub range: AtomicRange<f64>,
+    pub enabled: Arc<atomic::Bool>,
+}
+
+impl LoopRange {
+    pub fn new(start: f64, end: f64) -> Self {
+        Self {
+            range: AtomicRange::new(start, end),
+            enabled: Arc::new(atomic::Bool::from(false)),
+        }
+    }
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load()
+    }
+    /// Returns the start and end of the loop in samples only when the loop is enabled and not empty.
+    pub fn get_samples(&self, sample_rate: u32) -> Option<(usize, usize)> {
+        let (start, end) = self.range.get_pair();
+        let to_samples = |sec: f64| (sec.max(0.0) * sample_rate as f64) as usize;
+        let (start, end) = (to_samples(start), to_samples(end));
+        (self.is_enabled() && end > start).then_some((start, end))
+    }
+}
+
+impl Default for LoopRange {
+    fn default() -> Self {
+        Self::new(0.0, 4.0)
+    }
+}
+
+/// Markers and the loop range of the project.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Markers {
+    pub markers: Vec<Marker>,
+    pub loop_range: LoopRange,
+}
+
+impl Markers {
+    /// Calculates where the playhead should be after `frames` samples from `now`, wrapping at the loop end.
+    /// The loop is applied only when the playhead is before the loop end, like the other DAWs.
+    pub fn advance(&self, now: usize, frames: usize, sample_rate: u32) -> usize {
+        match self.loop_range.get_samples(sample_rate) {
+            Some((start, end)) if now < end && now + frames >= end => {
+                start + (now + frames - end) % (end - start)
+            }
+            _ => now + frames,
+        }
+    }
+}
@@ -0,0 +1,44 @@
+//! Levels measured in the audio thread, shared with the GUI through atomics.
+
+use crate::utils::{atomic, SimpleAtomic};
+
+/// Number of the channels metered. Mono signals are shown on both.
+pub const METER_CHANNELS: usize = 2;
+
+/// Levels of a track or the master output in linear amplitude.
+/// Written only by the audio thread, and not saved in the project file.
+#[derive(Debug, Default)]
+pub struct LevelMeter {
+    pub peak: [atomic::F32; METER_CHANNELS],
+    pub rms: [atomic::F32; METER_CHANNELS],
+    /// Inter-sample peak estimated by oversampling. Measured only on the master output.
+    pub true_peak: [atomic::F32; METER_CHANNELS],
+    clipped: atomic::Bool,
+}
+
+impl LevelMeter {
+    /// Whether the signal has exceeded the full scale since the indicator was reset.
+    pub fn is_clipped(&self) -> bool {
+        self.clipped.load()
+    }
+    pub fn set_clipped(&self) {
+        self.clipped.store(true);
+    }
+    pub fn reset_clip(&self) {
+        self.clipped.store(false);
+    }
+    pub fn reset_levels(&self) {
+        for level in self.peak.iter().chain(&self.rms).chain(&self.true_peak) {
+            level.store(0.0);
+        }
+    }
+}
+
+/// Converts the linear amplitude into decibels, clamped at `floor`.
+pub fn amp_to_db(amp: f32, floor: f32) -> f32 {
+    if amp > 0.0 {
+        (20.0 * amp.log10()).max(floor)
+    } else {
+        floor
+    }
+}
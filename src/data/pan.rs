@@ -0,0 +1,103 @@
+use crate::parameter::{FloatParameter, Parameter, RangedNumeric};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// How the level of the left and right channels changes with the pan position.
+/// The laws are named after the attenuation at the center relative to the hard panned side.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum PanLaw {
+    #[default]
+    ConstantPower,
+    Minus4_5dB,
+    Linear,
+}
+
+impl PanLaw {
+    pub const ALL: [Self; 3] = [Self::ConstantPower, Self::Minus4_5dB, Self::Linear];
+}
+
+impl std::fmt::Display for PanLaw {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::ConstantPower => "-3dB (constant power)",
+            Self::Minus4_5dB => "-4.5dB",
+            Self::Linear => "-6dB (linear)",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Pan and stereo width of a region or a track, changed while playing.
+/// The law is kept as a parameter of its index so that it can be passed as an argument in the script.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PanParam {
+    /// -1.0 for the hard left, 1.0 for the hard right.
+    pub pan: Arc<FloatParameter>,
+    /// 0.0 makes stereo sources mono, 1.0 keeps them as is, and 2.0 doubles the side.
+    pub width: Arc<FloatParameter>,
+    pub law: Arc<FloatParameter>,
+}
+
+impl Default for PanParam {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PanParam {
+    pub fn new() -> Self {
+        Self {
+            pan: Arc::new(FloatParameter::new(0.0, "pan").set_range(-1.0..=1.0)),
+            width: Arc::new(FloatParameter::new(1.0, "width").set_range(0.0..=2.0)),
+            law: Arc::new(FloatParameter::new(0.0, "law").set_range(0.0..=2.0)),
+        }
+    }
+    pub fn new_with(
+        pan: Arc<FloatParameter>,
+        width: Arc<FloatParameter>,
+        law: Arc<FloatParameter>,
+    ) -> Self {
+        Self { pan, width, law }
+    }
+    pub fn get_law(&self) -> PanLaw {
+        let index = self.law.get().max(0.0).round() as usize;
+        PanLaw::ALL.get(index).copied().unwrap_or_default()
+    }
+    pub fn set_law(&self, law: PanLaw) {
+        self.law.set(law as usize as f32);
+    }
+    /// Whether the signal passes through unchanged.
+    pub fn is_neutral(&self) -> bool {
+        self.pan.get() == 0.0 && self.width.get() == 1.0
+    }
+    pub(crate) fn is_default(&self) -> bool {
+        self.is_neutral() && self.get_law() == PanLaw::default()
+    }
+}
+
+/// Direction of a region encoded into an ambisonic track, in degrees.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DirectionParam {
+    /// counter-clockwise from the front.
+    pub azimuth: Arc<FloatParameter>,
+    /// upward from the horizontal plane.
+    pub elevation: Arc<FloatParameter>,
+}
+
+impl Default for DirectionParam {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DirectionParam {
+    pub fn new() -> Self {
+        Self {
+            azimuth: Arc::new(FloatParameter::new(0.0, "azimuth").set_range(-180.0..=180.0)),
+            elevation: Arc::new(FloatParameter::new(0.0, "elevation").set_range(-90.0..=90.0)),
+        }
+    }
+    pub fn new_with(azimuth: Arc<FloatParameter>, elevation: Arc<FloatParameter>) -> Self {
+        Self { azimuth, elevation }
+    }
+}
@@ -42,6 +42,10 @@ pub enum RegionFilter {
     FadeInOut(FadeParam),
     Reverse,
     Replicate(ReplicateParam),
+    /// Pans the origin while playing. The origin is rendered as is.
+    Pan(super::PanParam),
+    /// Encodes the origin into the ambisonic track at the direction while playing.
+    Encode(super::DirectionParam),
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -81,6 +85,31 @@ impl Region {
             origin.label,
         )
     }
+    /// The pan applied over the region while playing, if it is wrapped by the pan filter.
+    pub fn get_pan(&self) -> Option<&super::PanParam> {
+        match &self.content {
+            Content::Transformer(RegionFilter::Pan(param), _) => Some(param),
+            Content::Transformer(RegionFilter::Encode(_), origin) => origin.get_pan(),
+            _ => None,
+        }
+    }
+    /// The direction of the region in the ambisonic track, if it is wrapped by the encode filter.
+    pub fn get_direction(&self) -> Option<&super::DirectionParam> {
+        match &self.content {
+            Content::Transformer(RegionFilter::Encode(param), _) => Some(param),
+            Content::Transformer(RegionFilter::Pan(_), origin) => origin.get_direction(),
+            _ => None,
+        }
+    }
+    /// The region inside the pan and encode filters, which are applied while playing instead of rendered.
+    pub fn without_playback_filters(self) -> Self {
+        match self.content {
+            Content::Transformer(RegionFilter::Pan(_) | RegionFilter::Encode(_), origin) => {
+                origin.without_playback_filters()
+            }
+            _ => self,
+        }
+    }
 }
 
 impl std::default::Default for Region {
@@ -118,6 +147,38 @@ impl TryFrom<&Value> for Region {
             Value::Region(start, dur, content, label, _) => {
                 make_region_from_param(*start, *dur, content, label)
             }
+            Value::Closure(
+                _ids,
+                _env,
+                box Expr::App(box Expr::Literal(Value::ExtFunction(regionfilter)), args),
+            ) if regionfilter == "pan" => match args.as_slice() {
+                [Expr::Literal(region), Expr::Literal(Value::Parameter(pan)), Expr::Literal(Value::Parameter(width)), Expr::Literal(Value::Parameter(law))] =>
+                {
+                    let rg = Region::try_from(region)?;
+                    let range = rg.range.clone();
+                    let label = rg.label.clone();
+                    let param = super::PanParam::new_with(pan.clone(), width.clone(), law.clone());
+                    let content = Content::Transformer(RegionFilter::Pan(param), Box::new(rg));
+                    Ok(Region::new(range, content, label))
+                }
+                _ => Err(ConversionError {}),
+            },
+            Value::Closure(
+                _ids,
+                _env,
+                box Expr::App(box Expr::Literal(Value::ExtFunction(regionfilter)), args),
+            ) if regionfilter == "encode" => match args.as_slice() {
+                [Expr::Literal(region), Expr::Literal(Value::Parameter(azimuth)), Expr::Literal(Value::Parameter(elevation))] =>
+                {
+                    let rg = Region::try_from(region)?;
+                    let range = rg.range.clone();
+                    let label = rg.label.clone();
+                    let param = super::DirectionParam::new_with(azimuth.clone(), elevation.clone());
+                    let content = Content::Transformer(RegionFilter::Encode(param), Box::new(rg));
+                    Ok(Region::new(range, content, label))
+                }
+                _ => Err(ConversionError {}),
+            },
             Value::Closure(
                 _ids,
                 _env,
@@ -0,0 +1,93 @@
+//! Settings of the application independent from the projects, persisted in the config directory.
+
+use crate::audio::{self, backend::DeviceSelection};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Buffer sizes offered in the settings, in frames.
+pub const BUFFER_SIZES: [usize; 7] = [64, 128, 256, 512, 1024, 2048, 4096];
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct AudioSetting {
+    pub devices: DeviceSelection,
+    /// `None` uses the highest rate the device supports.
+    pub sample_rate: Option<u32>,
+    /// block size in frames.
+    pub buffer_size: usize,
+}
+
+impl Default for AudioSetting {
+    fn default() -> Self {
+        Self {
+            devices: DeviceSelection::default(),
+            sample_rate: Some(44100),
+            buffer_size: audio::DEFAULT_BUFFER_LEN,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct GlobalSetting {
+    pub audio: AudioSetting,
+}
+
+impl GlobalSetting {
+    const FILE_NAME: &'static str = "setting.json";
+
+    fn path(config_dir: &str) -> PathBuf {
+        Path::new(config_dir).join(Self::FILE_NAME)
+    }
+    /// Reads the setting saved in `config_dir`. Falls back to the default if it is missing or broken.
+    pub fn load(config_dir: Option<&str>) -> Self {
+        let Some(dir) = config_dir else {
+            return Self::default();
+        };
+        let path = Self::path(dir);
+        match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                log::warn!("failed to parse {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(e) => {
+                log::debug!("no setting at {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+    /// Writes the setting into `config_dir`, creating the directory if needed.
+    pub fn save(&self, config_dir: Option<&str>) -> std::io::Result<()> {
+        let dir = config_dir.ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "no config directory")
+        })?;
+        std::fs::create_dir_all(dir)?;
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(Self::path(dir), json)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let dir = std::env::temp_dir().join("otopoiesis_setting_test");
+        let dir_str = dir.to_str().unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(GlobalSetting::load(Some(dir_str)), GlobalSetting::default());
+        let mut setting = GlobalSetting::default();
+        setting.audio.devices.output = Some("speaker".to_string());
+        setting.audio.sample_rate = None;
+        setting.audio.buffer_size = 256;
+        setting.save(Some(dir_str)).unwrap();
+        assert_eq!(GlobalSetting::load(Some(dir_str)), setting);
+        // the fields missing in the file are filled with the default.
+        std::fs::write(dir.join("setting.json"), r#"{"audio":{"buffer_size":128}}"#).unwrap();
+        let partial = GlobalSetting::load(Some(dir_str));
+        assert_eq!(partial.audio.buffer_size, 128);
+        assert_eq!(partial.audio.sample_rate, Some(44100));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
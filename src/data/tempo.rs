@@ -0,0 +1,331 @@
+//! Musical time of the project: tempo, time signature and the conversion between seconds, samples and bars/beats/ticks.
+//!
+//! A tempo map is a list of [`TempoPoint`]s. Each point changes the tempo and the time signature at the beginning of a bar.
+//! "Beat" always means a note value of the denominator of the time signature (a quarter note in 4/4, an eighth note in 6/8) and the tempo is counted in those beats.
+
+use serde::{Deserialize, Serialize};
+
+/// Resolution of a beat used for [`BarBeatTick`].
+pub const TICKS_PER_BEAT: u64 = 960;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeSignature {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+impl TimeSignature {
+    pub fn new(numerator: u32, denominator: u32) -> Self {
+        assert!(numerator > 0 && denominator > 0, "invalid time signature");
+        Self {
+            numerator,
+            denominator,
+        }
+    }
+}
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self::new(4, 4)
+    }
+}
+impl std::fmt::Display for TimeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+/// Tempo and time signature which are valid from the beginning of `bar`(0-origin) until the next point.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TempoPoint {
+    pub bar: u64,
+    pub bpm: f64,
+    pub signature: TimeSignature,
+}
+
+impl TempoPoint {
+    pub fn beat_duration(&self) -> f64 {
+        60.0 / self.bpm
+    }
+}
+
+/// Musical position in 0-origin bars, beats and ticks. [`std::fmt::Display`] prints it in 1-origin like the other DAWs.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BarBeatTick {
+    pub bar: u64,
+    pub beat: u64,
+    pub tick: u64,
+}
+
+impl BarBeatTick {
+    pub fn new(bar: u64, beat: u64, tick: u64) -> Self {
+        Self { bar, beat, tick }
+    }
+}
+
+impl std::fmt::Display for BarBeatTick {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:03}:{:02}:{:03}",
+            self.bar + 1,
+            self.beat + 1,
+            self.tick
+        )
+    }
+}
+
+/// The section of tempo map between two tempo points, with accumulated positions at its beginning.
+struct Section<'a> {
+    point: &'a TempoPoint,
+    start_beat: f64,
+    start_sec: f64,
+    end_bar: Option<u64>,
+}
+
+impl<'a> Section<'a> {
+    fn beats_per_bar(&self) -> f64 {
+        self.point.signature.numerator as f64
+    }
+    fn end_beat(&self) -> Option<f64> {
+        self.end_bar
+            .map(|end| self.start_beat + (end - self.point.bar) as f64 * self.beats_per_bar())
+    }
+    fn end_sec(&self) -> Option<f64> {
+        self.end_beat()
+            .map(|end| self.start_sec + (end - self.start_beat) * self.point.beat_duration())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(try_from = "TempoPoints")]
+pub struct TempoMap {
+    /// sorted by the bar and the first point always starts at bar 0.
+    points: Vec<TempoPoint>,
+}
+
+/// The points read from the project file, validated before they become a [`TempoMap`].
+#[derive(Deserialize)]
+struct TempoPoints {
+    points: Vec<TempoPoint>,
+}
+
+impl TryFrom<TempoPoints> for TempoMap {
+    type Error = String;
+    fn try_from(value: TempoPoints) -> Result<Self, Self::Error> {
+        let points = value.points;
+        match points.first() {
+            None => return Err("tempo map has no points".into()),
+            Some(first) if first.bar != 0 => {
+                return Err("the first tempo point is not at bar 0".into())
+            }
+            _ => {}
+        }
+        if points.windows(2).any(|w| w[0].bar >= w[1].bar) {
+            return Err("tempo points are not sorted by the bar".into());
+        }
+        let valid = |p: &TempoPoint| {
+            p.bpm > 0.0 && p.signature.numerator > 0 && p.signature.denominator > 0
+        };
+        if let Some(p) = points.iter().find(|p| !valid(p)) {
+            return Err(format!(
+                "invalid tempo {} BPM {} at bar {}",
+                p.bpm, p.signature, p.bar
+            ));
+        }
+        Ok(Self { points })
+    }
+}
+
+impl Default for TempoMap {
+    fn default() -> Self {
+        Self::new(120.0, TimeSignature::default())
+    }
+}
+
+impl TempoMap {
+    pub fn new(bpm: f64, signature: TimeSignature) -> Self {
+        assert!(bpm > 0.0, "tempo should be positive");
+        Self {
+            points: vec![TempoPoint {
+                bar: 0,
+                bpm,
+                signature,
+            }],
+        }
+    }
+    /// Inserts tempo/time signature change at the beginning of the `bar`. Replaces the existing point at the same bar.
+    pub fn with_change(mut self, bar: u64, bpm: f64, signature: TimeSignature) -> Self {
+        assert!(bpm > 0.0, "tempo should be positive");
+        let point = TempoPoint {
+            bar,
+            bpm,
+            signature,
+        };
+        match self.points.binary_search_by_key(&bar, |p| p.bar) {
+            Ok(i) => self.points[i] = point,
+            Err(i) => self.points.insert(i, point),
+        }
+        self
+    }
+    pub fn points(&self) -> &[TempoPoint] {
+        &self.points
+    }
+    /// Tempo point that is valid at the beginning of the project.
+    pub fn initial(&self) -> &TempoPoint {
+        &self.points[0]
+    }
+
+    fn sections(&self) -> impl Iterator<Item = Section<'_>> + '_ {
+        let mut start_beat = 0.0;
+        let mut start_sec = 0.0;
+        self.points.iter().enumerate().map(move |(i, point)| {
+            let section = Section {
+                point,
+                start_beat,
+                start_sec,
+                end_bar: self.points.get(i + 1).map(|next| next.bar),
+            };
+            if let (Some(b), Some(s)) = (section.end_beat(), section.end_sec()) {
+                start_beat = b;
+                start_sec = s;
+            }
+            section
+        })
+    }
+    fn section_for_sec(&self, sec: f64) -> Section<'_> {
+        self.sections()
+            .find(|s| s.end_sec().is_none_or(|end| sec < end))
+            .expect("tempo map has no points")
+    }
+    fn section_for_beat(&self, beat: f64) -> Section<'_> {
+        self.sections()
+            .find(|s| s.end_beat().is_none_or(|end| beat < end))
+            .expect("tempo map has no points")
+    }
+    fn section_for_bar(&self, bar: u64) -> Section<'_> {
+        self.sections()
+            .find(|s| s.end_bar.is_none_or(|end| bar < end))
+            .expect("tempo map has no points")
+    }
+
+    /// Converts the number of beats counted from the beginning of the project into seconds.
+    pub fn beats_to_sec(&self, beats: f64) -> f64 {
+        let beats = beats.max(0.0);
+        let s = self.section_for_beat(beats);
+        s.start_sec + (beats - s.start_beat) * s.point.beat_duration()
+    }
+    /// Converts the time in seconds into the number of beats counted from the beginning of the project.
+    pub fn sec_to_beats(&self, sec: f64) -> f64 {
+        let sec = sec.max(0.0);
+        let s = self.section_for_sec(sec);
+        s.start_beat + (sec - s.start_sec) / s.point.beat_duration()
+    }
+    /// Duration in seconds of `beats` starting from the position `from_beat`.
+    pub fn beats_duration_to_sec(&self, from_beat: f64, beats: f64) -> f64 {
+        self.beats_to_sec(from_beat + beats) - self.beats_to_sec(from_beat)
+    }
+    pub fn bbt_to_sec(&self, pos: &BarBeatTick) -> f64 {
+        let s = self.section_for_bar(pos.bar);
+        let beat = s.start_beat
+            + (pos.bar - s.point.bar) as f64 * s.beats_per_bar()
+            + pos.beat as f64
+            + pos.tick as f64 / TICKS_PER_BEAT as f64;
+        self.beats_to_sec(beat)
+    }
+    pub fn sec_to_bbt(&self, sec: f64) -> BarBeatTick {
+        let sec = sec.max(0.0);
+        let s = self.section_for_sec(sec);
+        let beats_in_section = (sec - s.start_sec) / s.point.beat_duration();
+        let bar_in_section = (beats_in_section / s.beats_per_bar()).floor();
+        let beat_in_bar = beats_in_section - bar_in_section * s.beats_per_bar();
+        let ticks = (beat_in_bar * TICKS_PER_BEAT as f64).round() as u64;
+        let ticks_per_bar = s.point.signature.numerator as u64 * TICKS_PER_BEAT;
+        // rounding may carry the position over to the next bar.
+        let bar = s.point.bar + bar_in_section as u64 + ticks / ticks_per_bar;
+        let ticks = ticks % ticks_per_bar;
+        BarBeatTick {
+            bar,
+            beat: ticks / TICKS_PER_BEAT,
+            tick: ticks % TICKS_PER_BEAT,
+        }
+    }
+    pub fn samples_to_bbt(&self, samples: u64, sample_rate: u32) -> BarBeatTick {
+        self.sec_to_bbt(samples_to_sec(samples, sample_rate))
+    }
+    pub fn bbt_to_samples(&self, pos: &BarBeatTick, sample_rate: u32) -> u64 {
+        sec_to_samples(self.bbt_to_sec(pos), sample_rate)
+    }
+}
+
+impl std::fmt::Display for TempoMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let p = self.initial();
+        write!(f, "{} BPM {}", p.bpm, p.signature)
+    }
+}
+
+pub fn sec_to_samples(sec: f64, sample_rate: u32) -> u64 {
+    (sec.max(0.0) * sample_rate as f64) as u64
+}
+pub fn samples_to_sec(samples: u64, sample_rate: u32) -> f64 {
+    samples as f64 / sample_rate as f64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    fn assert_near(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+    #[test]
+    fn constant_tempo() {
+        let map = TempoMap::default();
+        assert_near(map.beats_to_sec(4.0), 2.0);
+        assert_near(map.sec_to_beats(1.5), 3.0);
+        assert_eq!(map.sec_to_bbt(2.25), BarBeatTick::new(1, 0, 480));
+        assert_near(map.bbt_to_sec(&BarBeatTick::new(1, 0, 480)), 2.25);
+        assert_eq!(map.sec_to_bbt(2.25).to_string(), "002:01:480");
+    }
+    #[test]
+    fn tempo_and_meter_change() {
+        // 2 bars of 4/4 at 120bpm (4 sec), then 3/4 at 60bpm.
+        let map = TempoMap::default().with_change(2, 60.0, TimeSignature::new(3, 4));
+        assert_near(map.bbt_to_sec(&BarBeatTick::new(2, 0, 0)), 4.0);
+        assert_near(map.bbt_to_sec(&BarBeatTick::new(3, 1, 0)), 8.0);
+        assert_eq!(map.sec_to_bbt(8.0), BarBeatTick::new(3, 1, 0));
+        assert_near(map.beats_to_sec(9.0), 5.0);
+        assert_near(map.beats_duration_to_sec(7.0, 2.0), 1.5);
+        for sec in [0.0, 1.3, 3.99, 4.0, 10.7] {
+            assert_near(map.beats_to_sec(map.sec_to_beats(sec)), sec);
+        }
+    }
+    #[test]
+    fn validate_on_load() {
+        let map = TempoMap::default().with_change(2, 60.0, TimeSignature::new(3, 4));
+        let json = serde_json::to_string(&map).unwrap();
+        assert_eq!(serde_json::from_str::<TempoMap>(&json).ok(), Some(map));
+        let point = |bar, bpm| {
+            format!(
+                r#"{{"bar":{},"bpm":{},"signature":{{"numerator":4,"denominator":4}}}}"#,
+                bar, bpm
+            )
+        };
+        for points in [
+            vec![],
+            vec![point(1, 120.0)],
+            vec![point(0, 120.0), point(3, 60.0), point(2, 90.0)],
+            vec![point(0, 0.0)],
+        ] {
+            let json = format!(r#"{{"points":[{}]}}"#, points.join(","));
+            assert!(serde_json::from_str::<TempoMap>(&json).is_err(), "{}", json);
+        }
+    }
+    #[test]
+    fn samples() {
+        let map = TempoMap::default();
+        let pos = BarBeatTick::new(1, 2, 0);
+        let samples = map.bbt_to_samples(&pos, 48000);
+        assert_eq!(samples, 144000);
+        assert_eq!(map.samples_to_bbt(samples, 48000), pos);
+    }
+}
@@ -1,15 +1,160 @@
-use super::{ConversionError, Generator, Region};
+use super::{ChannelLayout, ConversionError, PanParam, Region};
 use crate::script::Value;
+use crate::utils::{atomic, SimpleAtomic};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// How the regions on the same track are mixed where they overlap.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OverlapMode {
+    /// The region later in the track overwrites the earlier ones.
+    #[default]
+    LaterWins,
+    /// Overlapping regions are simply added.
+    Sum,
+    /// The earlier region fades out while the later one fades in over the overlap, with equal power.
+    Crossfade,
+}
+
+impl OverlapMode {
+    pub const ALL: [Self; 3] = [Self::LaterWins, Self::Sum, Self::Crossfade];
+}
+
+impl std::fmt::Display for OverlapMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::LaterWins => "later wins",
+            Self::Sum => "sum",
+            Self::Crossfade => "crossfade",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Overlap mode shared with the audio thread so that it can be switched while playing.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(from = "OverlapMode", into = "OverlapMode")]
+pub struct SharedOverlapMode(Arc<atomic::U8>);
+
+impl SharedOverlapMode {
+    pub fn get(&self) -> OverlapMode {
+        OverlapMode::ALL
+            .get(self.0.load() as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+    pub fn set(&self, mode: OverlapMode) {
+        self.0.store(mode as u8);
+    }
+}
+
+impl From<OverlapMode> for SharedOverlapMode {
+    fn from(mode: OverlapMode) -> Self {
+        Self(Arc::new(atomic::U8::from(mode as u8)))
+    }
+}
+impl From<SharedOverlapMode> for OverlapMode {
+    fn from(mode: SharedOverlapMode) -> Self {
+        mode.get()
+    }
+}
+
+/// Channel layout shared with the source, so that the change from GUI is saved.
+/// The track is rendered with the new layout from the next playback.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(from = "ChannelLayout", into = "ChannelLayout")]
+pub struct SharedChannelLayout(Arc<atomic::U32>);
+
+impl Default for SharedChannelLayout {
+    fn default() -> Self {
+        Self::from(ChannelLayout::default())
+    }
+}
+
+impl SharedChannelLayout {
+    /// Discrete layouts are stored with this flag and the number of channels in the lower bits.
+    const DISCRETE: u32 = 1 << 16;
+    /// Ambisonic layouts are stored with this flag and the order in the lower bits.
+    const AMBISONIC: u32 = 1 << 17;
+
+    pub fn get(&self) -> ChannelLayout {
+        match self.0.load() {
+            bits if bits & Self::DISCRETE != 0 => ChannelLayout::Discrete(bits as u16),
+            bits if bits & Self::AMBISONIC != 0 => ChannelLayout::Ambisonic(bits as u8),
+            bits => ChannelLayout::PRESETS
+                .get(bits as usize)
+                .copied()
+                .unwrap_or_default(),
+        }
+    }
+    pub fn set(&self, layout: ChannelLayout) {
+        let bits = match layout {
+            ChannelLayout::Discrete(n) => Self::DISCRETE | n as u32,
+            ChannelLayout::Ambisonic(order) => Self::AMBISONIC | order as u32,
+            preset => ChannelLayout::PRESETS
+                .iter()
+                .position(|l| *l == preset)
+                .unwrap_or_default() as u32,
+        };
+        self.0.store(bits);
+    }
+    fn is_default(&self) -> bool {
+        self.get() == ChannelLayout::default()
+    }
+}
+
+impl From<ChannelLayout> for SharedChannelLayout {
+    fn from(layout: ChannelLayout) -> Self {
+        let res = Self(Arc::new(atomic::U32::from(0)));
+        res.set(layout);
+        res
+    }
+}
+impl From<SharedChannelLayout> for ChannelLayout {
+    fn from(layout: SharedChannelLayout) -> Self {
+        layout.get()
+    }
+}
+
+/// Settings of the track, shared with the audio thread.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TrackParam {
+    #[serde(default)]
+    pub overlap: SharedOverlapMode,
+    /// whether the input is recorded into this track.
+    #[serde(default)]
+    pub armed: Arc<atomic::Bool>,
+    #[serde(skip)]
+    pub meter: Arc<super::LevelMeter>,
+    /// layout the regions and the generator of the track are rendered in.
+    #[serde(default, skip_serializing_if = "SharedChannelLayout::is_default")]
+    pub layout: SharedChannelLayout,
+    /// applied to the front pair of the track after the regions are mixed.
+    #[serde(default, skip_serializing_if = "PanParam::is_default")]
+    pub pan: PanParam,
+}
+
+impl TrackParam {
+    pub fn is_armed(&self) -> bool {
+        self.armed.load()
+    }
+}
+
+/// Returns the zone where the region `a` fades out into the region `b` in the crossfade mode.
+/// The zone is made only when `b` starts inside `a` and ends after it, so a region contained in another one is summed.
+pub fn crossfade_zone<T: PartialOrd + Copy>(a: (T, T), b: (T, T)) -> Option<(T, T)> {
+    let ((a_start, a_end), (b_start, b_end)) = (a, b);
+    (a_start < b_start && b_start < a_end && a_end <= b_end).then_some((b_start, a_end))
+}
 /// Data structure for track.
 /// The track has some input/output stream.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum Track {
     ///Contains Multiple Regions.
     /// TODO:Change container for this to be HashedSet for the more efficient implmentation of Undo Action.
-    Regions(Vec<Region>),
-    ///Contains one audio generator(0 input).
-    Generator(Generator),
+    Regions(Vec<Region>, #[serde(default)] TrackParam),
+    ///Contains one audio generator played over the whole timeline, like the live input.
+    Generator(Value, #[serde(default)] TrackParam),
     ///Take another track and transform it (like filter).
     Transformer(),
 }
@@ -22,7 +167,7 @@ impl Track {
 
 impl Default for Track {
     fn default() -> Self {
-        Track::Regions(vec![])
+        Track::Regions(vec![], TrackParam::default())
     }
 }
 impl std::fmt::Display for Track {
@@ -37,7 +182,7 @@ impl TryFrom<&Value> for Track {
 
     fn try_from(value: &Value) -> Result<Self, Self::Error> {
         match value {
-            Value::Track(box Value::Array(regions, _), _t) => {
+            Value::Track(box Value::Array(regions, _), _t, param) => {
                 let regions: Vec<Region> = regions
                     .iter()
                     .map(|rg| {
@@ -45,9 +190,64 @@ impl TryFrom<&Value> for Track {
                         region
                     })
                     .try_collect()?;
-                Ok(Self::Regions(regions))
+                // the parameter is shared with the source so that the change from GUI is saved.
+                Ok(Self::Regions(regions, param.clone()))
+            }
+            Value::Track(box generator @ Value::Closure(..), _t, param) => {
+                Ok(Self::Generator(generator.clone(), param.clone()))
             }
             _ => Err(ConversionError {}),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn zone_only_for_partial_overlap() {
+        assert_eq!(crossfade_zone((0.0, 2.0), (1.0, 3.0)), Some((1.0, 2.0)));
+        assert_eq!(crossfade_zone((1.0, 3.0), (0.0, 2.0)), None);
+        assert_eq!(crossfade_zone((0.0, 1.0), (1.0, 2.0)), None);
+        // contained region is summed.
+        assert_eq!(crossfade_zone((0.0, 4.0), (1.0, 2.0)), None);
+    }
+    #[test]
+    fn overlap_mode_is_shared() {
+        let mode = SharedOverlapMode::default();
+        assert_eq!(mode.get(), OverlapMode::LaterWins);
+        let json = serde_json::to_string(&TrackParam {
+            overlap: mode.clone(),
+            ..Default::default()
+        })
+        .unwrap();
+        let copy = mode.clone();
+        copy.set(OverlapMode::Crossfade);
+        assert_eq!(mode.get(), OverlapMode::Crossfade);
+        assert_eq!(json, r#"{"overlap":"LaterWins","armed":false}"#);
+        let param: TrackParam = serde_json::from_str(r#"{"overlap":"Sum"}"#).unwrap();
+        assert_eq!(param.overlap.get(), OverlapMode::Sum);
+    }
+    #[test]
+    fn layout_is_shared() {
+        let param = TrackParam::default();
+        assert_eq!(param.layout.get(), ChannelLayout::Stereo);
+        let copy = param.clone();
+        for layout in [
+            ChannelLayout::Surround51,
+            ChannelLayout::Ambisonic(3),
+            ChannelLayout::Discrete(12),
+        ] {
+            copy.layout.set(layout);
+            assert_eq!(param.layout.get(), layout);
+        }
+        let json = serde_json::to_string(&param).unwrap();
+        assert_eq!(
+            json,
+            r#"{"overlap":"LaterWins","armed":false,"layout":{"Discrete":12}}"#
+        );
+        let param: TrackParam = serde_json::from_str(&json).unwrap();
+        assert_eq!(param.layout.get(), ChannelLayout::Discrete(12));
+    }
+}
@@ -4,8 +4,10 @@
 pub mod app;
 pub mod generator;
 pub mod menu;
+pub mod meter;
 pub mod parameter;
 pub mod region;
+pub mod setting;
 pub mod timeline;
 pub mod track;
 pub mod transport;
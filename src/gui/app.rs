@@ -6,18 +6,21 @@ use std::sync::{Arc, Mutex};
 pub struct State {
     timeline: gui::timeline::State,
     transport: gui::transport::Model,
+    pub setting: gui::setting::State,
 }
 
 impl State {
     pub fn new(param: &data::AppModel) -> Self {
         let sr = param.project.sample_rate.load();
         let transport = &param.transport;
-        let transport = gui::transport::Model::new(Arc::clone(transport), sr);
+        let transport =
+            gui::transport::Model::new(Arc::clone(transport), sr, param.project.tempo.clone());
         let timeline =
             gui::timeline::State::new(&param.project.tracks, transport.param.time.clone(), sr);
         Self {
             timeline,
             transport,
+            setting: gui::setting::State::new(),
         }
     }
     pub fn sync_state(&mut self, track_p: &[data::Track]) {
@@ -54,6 +57,9 @@ impl<'a> Model<'a> {
                             if ui.button("Save as").clicked() {
                                 app.save_as_file();
                             }
+                            if ui.button("Settings").clicked() {
+                                self.state.setting.open(&app.global_setting);
+                            }
                         }
                         if ui.button("Force Sync Ui State(Debug)").clicked() {
                             #[cfg(debug_assertions)]
@@ -110,6 +116,9 @@ impl<'a> Model<'a> {
                         &mut self.state.timeline,
                     ));
                 }
+                if let Ok(app) = self.app.try_lock() {
+                    self.state.transport.set_tempo_map(&app.project.tempo);
+                }
                 egui::panel::TopBottomPanel::bottom("footer")
                     .show(ctx, |ui| ui.add(&mut self.state.transport));
             });
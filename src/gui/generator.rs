@@ -3,6 +3,7 @@ use crate::{
         self,
         region::{RangedComponent, RangedComponentDyn},
     },
+    data::ChannelLayout,
     gui::parameter::slider_from_parameter,
     script::{self, Expr, Value},
     utils::AtomicRange,
@@ -14,19 +15,22 @@ use std::ops::RangeInclusive;
 pub struct State {
     samples: Vec<f32>,
     shape: Shape,
+    /// the preview is rendered at the rate of the project.
+    sample_rate: u32,
 }
 
 impl State {
-    pub fn new() -> Self {
+    pub fn new(sample_rate: u32) -> Self {
         Self {
             samples: vec![0.0; 0],
             shape: Shape::Noop,
+            sample_rate,
         }
     }
 }
 impl Default for State {
     fn default() -> Self {
-        Self::new()
+        Self::new(44100)
     }
 }
 
@@ -39,13 +43,56 @@ fn reduce_samples(input: &[f32], output: &mut [f32]) {
         .for_each(|(o, is)| {
             //take left channel
             let chs = 2;
-            *o = is.chunks(chs).map(|i| i[0]).last().unwrap();
+            // the sparse impulses like the velvet noise are kept visible.
+            *o = is
+                .chunks(chs)
+                .map(|i| i[0])
+                .fold(0.0, |acc, s| if s.abs() > acc.abs() { s } else { acc });
         });
 }
+/// Rows of the parameters shown without scrolling.
+const MAX_ROWS: usize = 8;
+/// Sliders of the parameter, or of the parameters in the array like the operators of the FM generator.
+fn show_argument(v: &Value, ui: &mut egui::Ui) -> egui::Response {
+    match v {
+        Value::Parameter(param) => slider_from_parameter(param, false, ui),
+        Value::Array(values, _) if values.iter().any(has_parameter) => {
+            let show = |ui: &mut egui::Ui| {
+                values
+                    .iter()
+                    .filter(|v| has_parameter(v))
+                    .map(|v| show_argument(v, ui))
+                    .reduce(|acc, b| acc.union(b))
+                    .unwrap()
+            };
+            if values.len() > MAX_ROWS {
+                // the long lists like the partials of the additive generator are scrolled.
+                egui::ScrollArea::vertical()
+                    .max_height(super::TRACK_HEIGHT * 2.0)
+                    .show(ui, |ui| ui.vertical(show).inner)
+                    .inner
+            } else if values.iter().any(|v| matches!(v, Value::Array(..))) {
+                ui.vertical(show).inner
+            } else {
+                ui.horizontal(show).inner
+            }
+        }
+        Value::Array(values, _) => ui.label(format!("[{} items]", values.len())),
+        _ => ui.label("Invalid Parameter"),
+    }
+}
+fn has_parameter(v: &Value) -> bool {
+    match v {
+        Value::Parameter(_) => true,
+        Value::Array(values, _) => values.iter().any(has_parameter),
+        _ => false,
+    }
+}
 pub trait GeneratorUI<'a> {
     fn get_generator(&self) -> &script::Value;
     fn get_samples(&mut self) -> &mut Vec<f32>;
     fn get_displayed_range(&self) -> RangeInclusive<f64>;
+    fn get_sample_rate(&self) -> u32;
 
     fn get_displayed_duration(&self) -> f64 {
         let range = self.get_displayed_range();
@@ -54,16 +101,16 @@ pub trait GeneratorUI<'a> {
     fn update_samples(&mut self) {
         let width = self.get_displayed_duration() * super::PIXELS_PER_SEC_DEFAULT as f64;
         let pix_len = width.ceil() as usize;
-        let sample_rate = 44100u32;
-        let channels = 2;
+        let sample_rate = self.get_sample_rate();
+        let channels = ChannelLayout::Stereo;
         let numsamples = (sample_rate as f64 * self.get_displayed_duration()).ceil() as usize;
-        let mut buf = vec![0.0f32; numsamples * channels];
+        let mut buf = vec![0.0f32; numsamples * channels.count()];
         let audio_component = audio::generator::get_component_for_value(self.get_generator());
         let mut ranged_component = RangedComponentDyn::new(
             audio_component,
             AtomicRange::from(self.get_displayed_range()),
         );
-        ranged_component.render_offline(&mut buf, sample_rate, channels as u64);
+        ranged_component.render_offline(&mut buf, sample_rate, channels);
         self.get_samples().resize(pix_len, 0.0f32);
         reduce_samples(&buf, self.get_samples());
     }
@@ -87,6 +134,10 @@ impl<'a> GeneratorUI<'a> for Generator<'a> {
     fn get_displayed_range(&self) -> RangeInclusive<f64> {
         self.displayed_range.start()..=self.displayed_range.end()
     }
+
+    fn get_sample_rate(&self) -> u32 {
+        self.state.sample_rate
+    }
 }
 
 impl<'a> Generator<'a> {
@@ -154,12 +205,9 @@ impl<'a> egui::Widget for Generator<'a> {
                                 .vertical(|ui| {
                                     ui.label(fname.as_str());
                                     args.iter()
-                                        .map(|a| {
-                                            if let Expr::Literal(Value::Parameter(param)) = a {
-                                                slider_from_parameter(param, false, ui)
-                                            } else {
-                                                ui.label("Invalid Parameter")
-                                            }
+                                        .map(|a| match a {
+                                            Expr::Literal(v) => show_argument(v, ui),
+                                            _ => ui.label("Invalid Parameter"),
                                         })
                                         .reduce(|acc, b| acc.union(b))
                                         .unwrap()
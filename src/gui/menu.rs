@@ -23,21 +23,136 @@ fn with_fade(region: Value) -> Value {
         .into(),
     )
 }
-fn make_region(trackid: usize, pos: f64, c: String) -> Value {
-    let generator = Value::new_lazy(Expr::App(
-        Expr::Literal(Value::ExtFunction(c)).into(),
+/// Wraps the region with the pan filter, which is applied while playing.
+fn with_pan(region: Value) -> Value {
+    let param = data::PanParam::new();
+    let literal = |p: Arc<FloatParameter>| Expr::Literal(Value::Parameter(p));
+    Value::Closure(
+        vec![],
+        Arc::new(Environment::new()),
+        Expr::App(
+            Expr::Literal(Value::ExtFunction("pan".to_string())).into(),
+            vec![
+                Expr::Literal(region),
+                literal(param.pan),
+                literal(param.width),
+                literal(param.law),
+            ],
+        )
+        .into(),
+    )
+}
+/// Wraps the region with the encode filter placing it at the front, for the ambisonic tracks.
+fn with_direction(region: Value) -> Value {
+    let param = data::DirectionParam::new();
+    let literal = |p: Arc<FloatParameter>| Expr::Literal(Value::Parameter(p));
+    Value::Closure(
+        vec![],
+        Arc::new(Environment::new()),
+        Expr::App(
+            Expr::Literal(Value::ExtFunction("encode".to_string())).into(),
+            vec![
+                Expr::Literal(region),
+                literal(param.azimuth),
+                literal(param.elevation),
+            ],
+        )
+        .into(),
+    )
+}
+/// Pans the region, or places it in the direction on the ambisonic tracks.
+fn with_placement(region: Value, layout: data::ChannelLayout) -> Value {
+    if layout.is_ambisonic() {
+        with_direction(region)
+    } else {
+        with_pan(region)
+    }
+}
+/// Frames of a sine and a sawtooth to morph between, kept short as they are saved in the project.
+fn default_wavetable() -> Value {
+    let len = 256;
+    let frame = |f: &dyn Fn(f64) -> f64| {
+        let samples = (0..len)
+            .map(|i| Value::Number(f(i as f64 / len as f64)))
+            .collect();
+        Value::Array(samples, Type::Number)
+    };
+    Value::Array(
         vec![
-            Expr::Literal(Value::Parameter(Arc::new(
-                FloatParameter::new(440., "freq").set_range(10.0..=20000.),
-            ))),
-            Expr::Literal(Value::Parameter(Arc::new(
-                FloatParameter::new(1.0, "amp").set_range(0.0..=1.0),
-            ))),
-            Expr::Literal(Value::Parameter(Arc::new(
-                FloatParameter::new(0.0, "phase").set_range(0.0..=1.0),
-            ))),
+            frame(&|phase| (phase * std::f64::consts::TAU).sin()),
+            frame(&|phase| phase * 2.0 - 1.0),
         ],
-    ));
+        Type::Array(Type::Number.into(), len as u64),
+    )
+}
+
+fn make_region(trackid: usize, pos: f64, c: String) -> Value {
+    let mut args = vec![
+        Expr::Literal(Value::Parameter(Arc::new(
+            FloatParameter::new(440., "freq").set_range(10.0..=20000.),
+        ))),
+        Expr::Literal(Value::Parameter(Arc::new(
+            FloatParameter::new(1.0, "amp").set_range(0.0..=1.0),
+        ))),
+        Expr::Literal(Value::Parameter(Arc::new(
+            FloatParameter::new(0.0, "phase").set_range(0.0..=1.0),
+        ))),
+    ];
+    let param = |v: f32, label: &str, range: std::ops::RangeInclusive<f32>| {
+        Value::Parameter(Arc::new(FloatParameter::new(v, label).set_range(range)))
+    };
+    if c == "pm" {
+        // the carrier modulated by the operator at the same frequency.
+        let operator = |ratio: f32, index: f32| {
+            Value::Array(
+                vec![
+                    param(ratio, "ratio", 0.0..=16.0),
+                    param(index, "index", 0.0..=10.0),
+                ],
+                Type::Number,
+            )
+        };
+        let route = |from: f64, to: f64| {
+            Value::Array(vec![Value::Number(from), Value::Number(to)], Type::Number)
+        };
+        let array = |values| Value::Array(values, Type::Unknown);
+        args.push(Expr::Literal(array(vec![
+            operator(1.0, 0.0),
+            operator(1.0, 1.0),
+        ])));
+        args.push(Expr::Literal(array(vec![
+            route(1.0, 0.0),
+            route(0.0, -1.0),
+        ])));
+    }
+    if c == "am" {
+        args.push(Expr::Literal(param(0.5, "ratio", 0.0..=16.0)));
+        args.push(Expr::Literal(param(0.5, "depth", 0.0..=1.0)));
+    }
+    if c == "additive" {
+        // the harmonics of the sawtooth.
+        let partials = (1..=16)
+            .map(|k| {
+                let k = k as f32;
+                Value::Array(
+                    vec![
+                        param(k, "ratio", 0.0..=32.0),
+                        param(1.0 / k, "amp", 0.0..=1.0),
+                        param(0.0, "phase", 0.0..=1.0),
+                    ],
+                    Type::Number,
+                )
+            })
+            .collect();
+        args.push(Expr::Literal(Value::Array(partials, Type::Unknown)));
+    }
+    if c == "wavetable" {
+        args.push(Expr::Literal(Value::Parameter(Arc::new(
+            FloatParameter::new(0.0, "position").set_range(0.0..=1.0),
+        ))));
+        args.push(Expr::Literal(default_wavetable()));
+    }
+    let generator = Value::new_lazy(Expr::App(Expr::Literal(Value::ExtFunction(c)).into(), args));
     let region = Value::Region(
         pos,
         pos + 1.0,
@@ -49,24 +164,90 @@ fn make_region(trackid: usize, pos: f64, c: String) -> Value {
     with_fade(region)
 }
 
-fn make_region_file(trackid: usize, pos: f64, path: String) -> Value {
-    let generator = Value::new_lazy(Expr::App(
+/// Noise generator of the color. The velvet noise takes the density as the third argument.
+fn noise(color: data::NoiseColor) -> Value {
+    let param = data::NoiseParam::default();
+    let mut args = vec![
+        Expr::Literal(Value::Parameter(param.amp)),
+        Expr::Literal(Value::Parameter(param.seed)),
+    ];
+    if color == data::NoiseColor::Velvet {
+        args.push(Expr::Literal(Value::Parameter(param.density)));
+    }
+    Value::new_lazy(Expr::App(
+        Expr::Literal(Value::ExtFunction(color.get_name().to_string())).into(),
+        args,
+    ))
+}
+
+fn make_region_noise(trackid: usize, pos: f64, color: data::NoiseColor) -> Value {
+    let region = Value::Region(
+        pos,
+        pos + 1.0,
+        noise(color).into(),
+        format!("noise{}", trackid + 1),
+        Type::Unknown,
+    );
+    with_fade(region)
+}
+
+/// Generator of the live input, taking the first two channels of the device by default.
+pub fn live_input() -> Value {
+    let channel = |v: f32, label: &str| {
+        Expr::Literal(Value::Parameter(Arc::new(
+            FloatParameter::new(v, label).set_range(0.0..=15.0),
+        )))
+    };
+    Value::new_lazy(Expr::App(
+        Expr::Literal(Value::ExtFunction("input".to_string())).into(),
+        vec![channel(0.0, "left ch"), channel(1.0, "right ch")],
+    ))
+}
+
+fn make_region_input(trackid: usize, pos: f64) -> Value {
+    let region = Value::Region(
+        pos,
+        pos + 1.0,
+        live_input().into(),
+        format!("input{}", trackid + 1),
+        Type::Unknown,
+    );
+    with_fade(region)
+}
+
+fn fileplayer(path: String) -> Value {
+    Value::new_lazy(Expr::App(
         Expr::Literal(Value::ExtFunction("fileplayer".to_string())).into(),
         vec![Expr::Literal(Value::String(path))],
-    ));
+    ))
+}
+
+fn make_region_file(trackid: usize, pos: f64, path: String) -> Value {
     let region = Value::Region(
         pos,
         pos + 1.0,
-        generator.into(),
+        fileplayer(path).into(),
         format!("region{}", trackid + 1),
         Type::Unknown,
     );
     with_fade(region)
 }
 
+/// Region playing the recorded file from `pos` for `duration` seconds. The fades are not applied to keep the take as is.
+pub fn make_region_recorded(trackid: usize, pos: f64, duration: f64, path: String) -> Value {
+    with_pan(Value::Region(
+        pos,
+        pos + duration,
+        fileplayer(path).into(),
+        format!("rec{}", trackid + 1),
+        Type::Unknown,
+    ))
+}
+
 pub fn add_region_button(
     trackid: usize,
     pos: f64,
+    layout: data::ChannelLayout,
     sender: &mpsc::Sender<Action>,
     ui: &mut egui::Ui,
 ) -> egui::Response {
@@ -83,13 +264,38 @@ pub fn add_region_button(
                 let _ = ui.radio_value(&mut osckind, "sawtooth".to_string(), "SawTooth");
                 let _ = ui.radio_value(&mut osckind, "rectangular".to_string(), "Rectangular");
                 let _ = ui.radio_value(&mut osckind, "triangular".to_string(), "Triangular");
+                let _ = ui.radio_value(&mut osckind, "wavetable".to_string(), "Wavetable");
+                let _ = ui.radio_value(&mut osckind, "pm".to_string(), "FM");
+                let _ = ui.radio_value(&mut osckind, "am".to_string(), "AM");
+                let _ = ui.radio_value(&mut osckind, "additive".to_string(), "Additive");
                 ui.ctx().data_mut(|d| {
                     d.insert_persisted(id, osckind.clone());
                 });
                 (addosc, osckind)
             })
             .inner;
+        let id = ui.auto_id_with("noisecolor");
+        let mut color = ui
+            .ctx()
+            .data_mut(|d| d.get_persisted(id))
+            .unwrap_or_default();
+        let addnoise = ui
+            .horizontal(|ui| {
+                let addnoise = ui.button("≈ Add noise");
+                let _ = ui.radio_value(&mut color, data::NoiseColor::White, "White");
+                let _ = ui.radio_value(&mut color, data::NoiseColor::Pink, "Pink");
+                let _ = ui.radio_value(&mut color, data::NoiseColor::Brown, "Brown");
+                let _ = ui.radio_value(&mut color, data::NoiseColor::Velvet, "Velvet");
+                ui.ctx().data_mut(|d| {
+                    d.insert_persisted(id, color);
+                });
+                addnoise
+            })
+            .inner;
         let addfile = ui.button("💾 Load File");
+        let addinput = ui
+            .button("🎤 Add input")
+            .on_hover_text("Region processing the input from the audio device");
         let mut array_num = 5;
         let addarray = ui
             .horizontal(|ui| {
@@ -99,14 +305,22 @@ pub fn add_region_button(
             })
             .inner;
         if addosc.clicked() {
-            let region = make_region(trackid, pos, osckind);
+            let region = with_placement(make_region(trackid, pos, osckind), layout);
+            let _ = sender.send(action::AddRegion::new(region, trackid).into());
+        }
+        if addnoise.clicked() {
+            let region = with_placement(make_region_noise(trackid, pos, color), layout);
             let _ = sender.send(action::AddRegion::new(region, trackid).into());
         }
         if addfile.clicked() {
             let (file, _len) = data::generator::FilePlayerParam::new_test_file();
             //todo!
 
-            let region = make_region_file(trackid, pos, file.path);
+            let region = with_placement(make_region_file(trackid, pos, file.path), layout);
+            let _ = sender.send(action::AddRegion::new(region, trackid).into());
+        }
+        if addinput.clicked() {
+            let region = with_placement(make_region_input(trackid, pos), layout);
             let _ = sender.send(action::AddRegion::new(region, trackid).into());
         }
         if addarray.clicked() {
@@ -0,0 +1,93 @@
+//! Level meter drawn from the levels published by the audio thread.
+
+use crate::data::{self, METER_CHANNELS};
+use crate::utils::atomic::SimpleAtomic;
+
+/// The lowest level shown on the meter.
+const FLOOR_DB: f32 = -60.0;
+const BAR_SIZE: egui::Vec2 = egui::vec2(120.0, 16.0);
+
+pub struct Meter<'a> {
+    level: &'a data::LevelMeter,
+    show_true_peak: bool,
+}
+
+impl<'a> Meter<'a> {
+    pub fn new(level: &'a data::LevelMeter) -> Self {
+        Self {
+            level,
+            show_true_peak: false,
+        }
+    }
+    /// Shows the true-peak next to the bars. Used for the master output.
+    pub fn with_true_peak(mut self) -> Self {
+        self.show_true_peak = true;
+        self
+    }
+}
+
+fn db_to_ratio(amp: f32) -> f32 {
+    (data::amp_to_db(amp, FLOOR_DB) - FLOOR_DB) / -FLOOR_DB
+}
+
+fn level_color(db: f32) -> egui::Color32 {
+    if db > -6.0 {
+        egui::Color32::from_rgb(230, 80, 60)
+    } else if db > -18.0 {
+        egui::Color32::from_rgb(220, 200, 60)
+    } else {
+        egui::Color32::from_rgb(80, 200, 90)
+    }
+}
+
+impl<'a> egui::Widget for Meter<'a> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let level = self.level;
+        ui.horizontal(|ui| {
+            let (rect, bars) = ui.allocate_exact_size(BAR_SIZE, egui::Sense::hover());
+            let painter = ui.painter_at(rect);
+            painter.rect_filled(rect, 2.0, egui::Color32::from_gray(30));
+            let bar_h = rect.height() / METER_CHANNELS as f32;
+            for ch in 0..METER_CHANNELS {
+                let top = rect.top() + bar_h * ch as f32;
+                let x = |amp: f32| rect.left() + rect.width() * db_to_ratio(amp);
+                let (rms, peak) = (level.rms[ch].load(), level.peak[ch].load());
+                let bar = egui::Rect::from_min_max(
+                    egui::pos2(rect.left(), top + 1.0),
+                    egui::pos2(x(rms), top + bar_h - 1.0),
+                );
+                painter.rect_filled(bar, 0.0, level_color(data::amp_to_db(rms, FLOOR_DB)));
+                painter.vline(
+                    x(peak),
+                    top..=top + bar_h,
+                    egui::Stroke::new(1.5, level_color(data::amp_to_db(peak, FLOOR_DB))),
+                );
+            }
+            let max_db = |levels: &[crate::utils::atomic::F32]| {
+                let max = levels.iter().map(|l| l.load()).fold(0.0, f32::max);
+                data::amp_to_db(max, FLOOR_DB)
+            };
+            bars.on_hover_text(format!(
+                "peak {:.1} dB / rms {:.1} dB",
+                max_db(&level.peak),
+                max_db(&level.rms)
+            ));
+            if self.show_true_peak {
+                ui.label(format!("TP {:.1} dB", max_db(&level.true_peak)))
+                    .on_hover_text("True-peak, including the peaks between the samples");
+            }
+            let (clip_rect, clip) =
+                ui.allocate_exact_size(egui::vec2(10.0, BAR_SIZE.y), egui::Sense::click());
+            let clip_color = if level.is_clipped() {
+                egui::Color32::RED
+            } else {
+                egui::Color32::from_gray(60)
+            };
+            ui.painter().rect_filled(clip_rect, 2.0, clip_color);
+            if clip.on_hover_text("Clipped. Click to reset").clicked() {
+                level.reset_clip();
+            }
+        })
+        .response
+    }
+}
@@ -1,3 +1,4 @@
+use crate::data;
 use crate::parameter::{FloatParameter, Parameter};
 
 pub(crate) fn slider_from_parameter(
@@ -28,3 +29,27 @@ pub(crate) fn slider_from_parameter(
     })
     .inner
 }
+
+/// Sliders of the pan and the width, with the choice of the pan law.
+pub(crate) fn pan_controls(
+    id: impl std::hash::Hash,
+    param: &data::PanParam,
+    ui: &mut egui::Ui,
+) -> egui::Response {
+    ui.vertical(|ui| {
+        slider_from_parameter(&param.pan, false, ui);
+        slider_from_parameter(&param.width, false, ui);
+        let mut law = param.get_law();
+        egui::ComboBox::from_id_source(("pan law", id))
+            .selected_text(law.to_string())
+            .show_ui(ui, |ui| {
+                for l in data::PanLaw::ALL {
+                    ui.selectable_value(&mut law, l, l.to_string());
+                }
+            });
+        if law != param.get_law() {
+            param.set_law(law);
+        }
+    })
+    .response
+}
@@ -6,10 +6,12 @@ mod region_handle;
 pub mod regionfilter;
 use region_handle::{HandleMode, UiBar, UiBarState};
 
+use self::regionfilter::encode::Encode;
 use self::regionfilter::fadeinout::FadeInOut;
+use self::regionfilter::pan::Pan;
 use self::regionfilter::replicate::Replicate;
 use self::regionfilter::RegionFilterState;
-use self::regionfilter::{fadeinout, replicate};
+use self::regionfilter::{encode, fadeinout, pan, replicate};
 
 pub enum ContentModel {
     RegionFilter(regionfilter::RegionFilterState),
@@ -26,12 +28,17 @@ pub struct State {
 }
 
 impl State {
-    pub fn new(params: &data::Region, labeltext: impl ToString, is_interactive: bool) -> Self {
+    pub fn new(
+        params: &data::Region,
+        labeltext: impl ToString,
+        is_interactive: bool,
+        sample_rate: u32,
+    ) -> Self {
         let handle_left = UiBarState::new(0.0..=params.range.0.load());
         let handle_right = UiBarState::new(params.range.1.load()..=f64::MAX);
         let content = match &params.content {
             data::Content::Generator(param) => {
-                ContentModel::Generator(param.clone(), super::generator::State::new())
+                ContentModel::Generator(param.clone(), super::generator::State::new(sample_rate))
             }
             data::Content::Transformer(filter, origin) => {
                 ContentModel::RegionFilter(match filter {
@@ -41,10 +48,17 @@ impl State {
                         regionfilter::RegionFilterState::FadeInOut(fadeinout::State::new(
                             origin,
                             origin.range.clone(),
+                            sample_rate,
                         ))
                     }
                     data::RegionFilter::Replicate(p) => regionfilter::RegionFilterState::Replicate(
-                        replicate::State::new(origin.as_ref(), p.count.load() as u64),
+                        replicate::State::new(origin.as_ref(), p.count.load() as u64, sample_rate),
+                    ),
+                    data::RegionFilter::Pan(_p) => {
+                        regionfilter::RegionFilterState::Pan(pan::State::new(origin, sample_rate))
+                    }
+                    data::RegionFilter::Encode(_p) => regionfilter::RegionFilterState::Encode(
+                        encode::State::new(origin, sample_rate),
                     ),
                 })
             }
@@ -142,6 +156,30 @@ impl<'a> egui::Widget for Model<'a> {
                                 false,
                             )
                         }
+                        (data::RegionFilter::Pan(param), RegionFilterState::Pan(s)) => {
+                            self.params.range.set_start(origin.range.start());
+                            self.params.range.set_end(origin.range.end());
+                            (
+                                ui.add(regionfilter::RegionFilter::Pan(Pan::new(
+                                    param,
+                                    origin.as_ref(),
+                                    s,
+                                ))),
+                                false,
+                            )
+                        }
+                        (data::RegionFilter::Encode(param), RegionFilterState::Encode(s)) => {
+                            self.params.range.set_start(origin.range.start());
+                            self.params.range.set_end(origin.range.end());
+                            (
+                                ui.add(regionfilter::RegionFilter::Encode(Encode::new(
+                                    param,
+                                    origin.as_ref(),
+                                    s,
+                                ))),
+                                false,
+                            )
+                        }
                         (_, _) => panic!(
                             "invalid combination of parameter and gui state in pattern matching "
                         ),
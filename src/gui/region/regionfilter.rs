@@ -1,4 +1,6 @@
+pub(crate) mod encode;
 pub(crate) mod fadeinout;
+pub(crate) mod pan;
 pub(crate) mod replicate;
 
 use crate::gui::region;
@@ -6,10 +8,14 @@ use crate::gui::region;
 pub enum RegionFilter<'a> {
     FadeInOut(fadeinout::FadeInOut<'a>),
     Replicate(replicate::Replicate<'a>),
+    Pan(pan::Pan<'a>),
+    Encode(encode::Encode<'a>),
 }
 pub enum RegionFilterState {
     FadeInOut(fadeinout::State),
     Replicate(replicate::State),
+    Pan(pan::State),
+    Encode(encode::State),
 }
 
 impl<'a> egui::Widget for RegionFilter<'a> {
@@ -17,6 +23,8 @@ impl<'a> egui::Widget for RegionFilter<'a> {
         match self {
             RegionFilter::FadeInOut(p) => ui.add(p),
             RegionFilter::Replicate(p) => ui.add(p),
+            RegionFilter::Pan(p) => ui.add(p),
+            RegionFilter::Encode(p) => ui.add(p),
         }
     }
 }
@@ -0,0 +1,57 @@
+use crate::data;
+use crate::gui::parameter::slider_from_parameter;
+
+pub struct State {
+    pub origin: Box<super::region::State>,
+}
+impl State {
+    pub fn new(origin: &data::Region, sample_rate: u32) -> Self {
+        Self {
+            origin: Box::new(super::region::State::new(
+                origin,
+                origin.label.clone(),
+                true,
+                sample_rate,
+            )),
+        }
+    }
+}
+
+/// Shows the origin as is, with the menu of the direction below.
+pub struct Encode<'a> {
+    param: &'a data::DirectionParam,
+    origin: &'a data::Region,
+    state: &'a mut State,
+}
+impl<'a> Encode<'a> {
+    pub fn new(
+        param: &'a data::DirectionParam,
+        origin: &'a data::Region,
+        state: &'a mut State,
+    ) -> Self {
+        Self {
+            param,
+            origin,
+            state,
+        }
+    }
+}
+
+impl<'a> egui::Widget for Encode<'a> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        ui.vertical(|ui| {
+            let response = ui.add(super::region::Model::new(
+                self.origin,
+                self.state.origin.as_mut(),
+            ));
+            ui.push_id(("region direction", &self.state.origin.label), |ui| {
+                egui::menu::menu_button(ui, "direction", |ui| {
+                    slider_from_parameter(&self.param.azimuth, false, ui);
+                    slider_from_parameter(&self.param.elevation, false, ui);
+                });
+            });
+            response
+        })
+        .inner
+    }
+}
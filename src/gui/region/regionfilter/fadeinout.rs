@@ -13,13 +13,14 @@ pub struct State {
     end_tmp: f32,
 }
 impl State {
-    pub fn new(origin: &data::Region, range: AtomicRange<f64>) -> Self {
+    pub fn new(origin: &data::Region, range: AtomicRange<f64>, sample_rate: u32) -> Self {
         let label = &origin.label.clone();
         Self {
             origin: Box::new(super::region::State::new(
                 origin,
                 format!("{}_fade", label),
                 false,
+                sample_rate,
             )),
             range,
             start_tmp: 0.0,
@@ -0,0 +1,53 @@
+use crate::data;
+use crate::gui;
+
+pub struct State {
+    pub origin: Box<super::region::State>,
+}
+impl State {
+    pub fn new(origin: &data::Region, sample_rate: u32) -> Self {
+        Self {
+            origin: Box::new(super::region::State::new(
+                origin,
+                origin.label.clone(),
+                true,
+                sample_rate,
+            )),
+        }
+    }
+}
+
+/// Shows the origin as is, with the menu of the pan below.
+pub struct Pan<'a> {
+    param: &'a data::PanParam,
+    origin: &'a data::Region,
+    state: &'a mut State,
+}
+impl<'a> Pan<'a> {
+    pub fn new(param: &'a data::PanParam, origin: &'a data::Region, state: &'a mut State) -> Self {
+        Self {
+            param,
+            origin,
+            state,
+        }
+    }
+}
+
+impl<'a> egui::Widget for Pan<'a> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        ui.vertical(|ui| {
+            let response = ui.add(super::region::Model::new(
+                self.origin,
+                self.state.origin.as_mut(),
+            ));
+            let label = &self.state.origin.label;
+            ui.push_id(("region pan", label), |ui| {
+                egui::menu::menu_button(ui, "pan", |ui| {
+                    gui::parameter::pan_controls(("region pan", label), self.param, ui);
+                });
+            });
+            response
+        })
+        .inner
+    }
+}
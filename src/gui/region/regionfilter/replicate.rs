@@ -13,11 +13,11 @@ pub struct State {
     pub regions: Vec<super::region::State>,
 }
 impl State {
-    pub fn new(origin: &data::Region, count: u64) -> Self {
+    pub fn new(origin: &data::Region, count: u64, sample_rate: u32) -> Self {
         let regions = (0..count)
             .map(|i| {
                 let is_editable = i == 0;
-                super::region::State::new(origin, origin.label.clone(), is_editable)
+                super::region::State::new(origin, origin.label.clone(), is_editable, sample_rate)
             })
             .collect::<Vec<super::region::State>>();
         Self { regions }
@@ -0,0 +1,152 @@
+//! Window to choose the audio host, devices, sample rate and buffer size.
+
+use crate::audio::backend::cpal_device::{self, DeviceList};
+use crate::data;
+
+pub struct State {
+    is_open: bool,
+    /// edited in the window, and applied only when the apply button is clicked.
+    draft: data::GlobalSetting,
+    hosts: Vec<String>,
+    devices: DeviceList,
+    list_error: Option<String>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl State {
+    pub fn new() -> Self {
+        Self {
+            is_open: false,
+            draft: data::GlobalSetting::default(),
+            hosts: vec![],
+            devices: DeviceList::default(),
+            list_error: None,
+        }
+    }
+    /// Opens the window starting from the `current` setting.
+    pub fn open(&mut self, current: &data::GlobalSetting) {
+        if !self.is_open {
+            self.draft = current.clone();
+            self.rescan();
+        }
+        self.is_open = true;
+    }
+    /// Lists the devices again, as they may be plugged or unplugged.
+    fn rescan(&mut self) {
+        self.hosts = cpal_device::host_names();
+        match cpal_device::list_devices(&self.draft.audio.devices) {
+            Ok(devices) => {
+                self.devices = devices;
+                self.list_error = None;
+            }
+            Err(e) => {
+                self.devices = DeviceList::default();
+                self.list_error = Some(e.to_string());
+            }
+        }
+    }
+    /// Shows the window with the `error` from the audio devices. Returns the setting to apply.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        error: Option<&str>,
+    ) -> Option<data::GlobalSetting> {
+        let mut is_open = self.is_open;
+        let mut apply = false;
+        egui::Window::new("Settings")
+            .open(&mut is_open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                if let Some(e) = error.or(self.list_error.as_deref()) {
+                    ui.colored_label(egui::Color32::RED, e);
+                }
+                let audio = &mut self.draft.audio;
+                let mut changed = false;
+                egui::Grid::new("audio settings")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("Host");
+                        changed |= choose_name(ui, "host", &mut audio.devices.host, &self.hosts);
+                        ui.end_row();
+                        ui.label("Output");
+                        changed |= choose_name(
+                            ui,
+                            "output",
+                            &mut audio.devices.output,
+                            &self.devices.outputs,
+                        );
+                        ui.end_row();
+                        ui.label("Input");
+                        choose_name(ui, "input", &mut audio.devices.input, &self.devices.inputs);
+                        ui.end_row();
+                        ui.label("Sample rate");
+                        choose_sample_rate(ui, &mut audio.sample_rate, &self.devices.sample_rates);
+                        ui.end_row();
+                        ui.label("Buffer size");
+                        egui::ComboBox::from_id_source("buffer size")
+                            .selected_text(audio.buffer_size.to_string())
+                            .show_ui(ui, |ui| {
+                                for size in data::BUFFER_SIZES {
+                                    ui.selectable_value(
+                                        &mut audio.buffer_size,
+                                        size,
+                                        size.to_string(),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+                    });
+                ui.horizontal(|ui| {
+                    if ui.button("Rescan").clicked() {
+                        changed = true;
+                    }
+                    apply = ui.button("Apply").clicked();
+                });
+                if changed {
+                    // the devices and the sample rates depend on the host and the output.
+                    self.rescan();
+                }
+            });
+        self.is_open = is_open && !apply;
+        apply.then(|| self.draft.clone())
+    }
+}
+
+/// Combo box for the device or the host. `None` is the default one.
+/// The name saved in the setting but not found is still shown so that it is not cleared silently.
+fn choose_name(ui: &mut egui::Ui, id: &str, value: &mut Option<String>, names: &[String]) -> bool {
+    let before = value.clone();
+    let text = match value {
+        Some(name) if names.contains(name) => egui::RichText::new(name.as_str()),
+        Some(name) => {
+            egui::RichText::new(format!("{} (not available)", name)).color(egui::Color32::RED)
+        }
+        None => egui::RichText::new("default"),
+    };
+    egui::ComboBox::from_id_source(id)
+        .selected_text(text)
+        .show_ui(ui, |ui| {
+            ui.selectable_value(value, None, "default");
+            for name in names {
+                ui.selectable_value(value, Some(name.clone()), name);
+            }
+        });
+    *value != before
+}
+
+fn choose_sample_rate(ui: &mut egui::Ui, value: &mut Option<u32>, rates: &[u32]) {
+    let text = value.map_or("device maximum".to_string(), |sr| sr.to_string());
+    egui::ComboBox::from_id_source("sample rate")
+        .selected_text(text)
+        .show_ui(ui, |ui| {
+            ui.selectable_value(value, None, "device maximum");
+            for sr in rates {
+                ui.selectable_value(value, Some(*sr), sr.to_string());
+            }
+        });
+}
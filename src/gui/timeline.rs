@@ -1,25 +1,29 @@
-use crate::action::{Action, AddTrack};
+use crate::action::{Action, AddMarker, AddTrack};
 use crate::data;
 use crate::gui;
 use crate::script::{Type, Value};
 use crate::utils::atomic::{self, SimpleAtomic};
 use std::sync::Arc;
 
+mod ruler;
+
 pub struct State {
     track: Vec<gui::track::State>,
+    ruler: ruler::State,
     now: Arc<atomic::U64>,
     sample_rate: u64,
 }
 impl State {
     pub fn new(track_p: &[data::Track], now: Arc<atomic::U64>, sample_rate: u64) -> Self {
         Self {
-            track: param_to_track(track_p),
+            track: param_to_track(track_p, sample_rate),
+            ruler: ruler::State::default(),
             now,
             sample_rate,
         }
     }
     pub fn sync_state(&mut self, track_p: &[data::Track]) {
-        self.track = param_to_track(track_p);
+        self.track = param_to_track(track_p, self.sample_rate);
     }
 }
 
@@ -29,11 +33,11 @@ pub struct Model<'a> {
     state: &'a mut State,
 }
 
-fn param_to_track(track_p: &[data::Track]) -> Vec<gui::track::State> {
+fn param_to_track(track_p: &[data::Track], sample_rate: u64) -> Vec<gui::track::State> {
     track_p
         .iter()
         .enumerate()
-        .map(|(_i, t)| gui::track::State::new(t, 5))
+        .map(|(_i, t)| gui::track::State::new(t, 5, sample_rate as u32))
         .collect::<Vec<_>>()
 }
 
@@ -66,6 +70,24 @@ impl<'a> Model<'a> {
             + rect.left();
         painter.line_segment([[x, rect.top()].into(), [x, rect.bottom()].into()], stroke);
     }
+    fn add_marker(&self) {
+        let now = self.get_current_time_in_sample() as f64 / self.state.sample_rate as f64;
+        let label = format!("Marker {}", self.app.project.markers.markers.len() + 1);
+        let _ = self
+            .app
+            .action_tx
+            .send(Action::from(AddMarker::new(data::Marker::new(label, now))));
+    }
+    fn add_input_track(&self) {
+        let _ = self
+            .app
+            .action_tx
+            .send(Action::from(AddTrack::new(Value::Track(
+                gui::menu::live_input().into(),
+                Type::Unknown,
+                data::TrackParam::default(),
+            ))));
+    }
     fn add_track(&self) {
         let _ = self
             .app
@@ -73,6 +95,7 @@ impl<'a> Model<'a> {
             .send(Action::from(AddTrack::new(Value::Track(
                 Value::Array(vec![], Type::Unknown).into(),
                 Type::Unknown,
+                data::TrackParam::default(),
             ))));
     }
 }
@@ -82,6 +105,32 @@ impl<'a> egui::Widget for Model<'a> {
         let main = egui::ScrollArea::horizontal().show(ui, |ui| {
             let res = ui
                 .vertical(|ui| {
+                    ui.horizontal(|ui| {
+                        let loop_range = &self.app.project.markers.loop_range;
+                        let mut enabled = loop_range.is_enabled();
+                        if ui
+                            .toggle_value(&mut enabled, "🔁")
+                            .on_hover_text("Loop playback")
+                            .changed()
+                        {
+                            loop_range.enabled.store(enabled);
+                        }
+                        if ui
+                            .button("🚩+")
+                            .on_hover_text("Add marker at the current position")
+                            .clicked()
+                        {
+                            self.add_marker();
+                        }
+                    });
+                    ui.add(ruler::Ruler::new(
+                        &self.app.project.markers,
+                        &self.app.project.tempo,
+                        &self.app.transport,
+                        self.state.sample_rate,
+                        &self.app.action_tx,
+                        &mut self.state.ruler,
+                    ));
                     for (i, state) in self.state.track.iter_mut().enumerate() {
                         ui.add(gui::track::Model::new(
                             i,
@@ -97,6 +146,12 @@ impl<'a> egui::Widget for Model<'a> {
             if add_track_button.clicked() {
                 self.add_track();
             }
+            let add_input_button = ui
+                .button("🎤+")
+                .on_hover_text("Add new Track playing the live input");
+            if add_input_button.clicked() {
+                self.add_input_track();
+            }
 
             let painter = ui.painter_at(ui.clip_rect());
             self.draw_current_time(&painter, ui.style());
@@ -0,0 +1,232 @@
+use crate::action::{Action, RemoveMarker};
+use crate::data;
+use crate::gui;
+use crate::utils::atomic::SimpleAtomic;
+use std::sync::mpsc;
+
+pub(super) const RULER_HEIGHT: f32 = 40.0;
+const HANDLE_WIDTH: f32 = 6.0;
+
+#[derive(Default)]
+pub struct State {
+    /// accumulates dragged position in seconds like region handles do.
+    saved_state: f64,
+}
+
+/// Bar lines, the loop bar, markers and the playhead drawn above the tracks.
+/// Clicking or dragging on the empty area of the ruler seeks the playhead.
+pub(super) struct Ruler<'a> {
+    markers: &'a data::Markers,
+    tempo: &'a data::TempoMap,
+    transport: &'a data::Transport,
+    sample_rate: u64,
+    action_tx: &'a mpsc::Sender<Action>,
+    state: &'a mut State,
+}
+
+impl<'a> Ruler<'a> {
+    pub fn new(
+        markers: &'a data::Markers,
+        tempo: &'a data::TempoMap,
+        transport: &'a data::Transport,
+        sample_rate: u64,
+        action_tx: &'a mpsc::Sender<Action>,
+        state: &'a mut State,
+    ) -> Self {
+        Self {
+            markers,
+            tempo,
+            transport,
+            sample_rate,
+            action_tx,
+            state,
+        }
+    }
+    /// Returns the new position if the response was dragged.
+    fn drag_position(&mut self, response: &egui::Response, current: f64) -> Option<f64> {
+        if response.drag_started() {
+            self.state.saved_state = current;
+        }
+        if response.dragged() {
+            self.state.saved_state +=
+                (response.drag_delta().x / gui::PIXELS_PER_SEC_DEFAULT) as f64;
+            Some(self.state.saved_state.max(0.0))
+        } else {
+            None
+        }
+    }
+    fn sec_to_sample(&self, sec: f64) -> u64 {
+        (sec.max(0.0) * self.sample_rate as f64) as u64
+    }
+    /// Moves the playhead while dragging and requests the seek when the drag is released.
+    fn scrub(&self, response: &egui::Response, sec: f64) {
+        if response.clicked() || response.drag_released() {
+            self.transport.seek(self.sec_to_sample(sec));
+        } else if response.dragged() {
+            self.transport.time.store(self.sec_to_sample(sec));
+        }
+    }
+    fn draw_bars(&self, painter: &egui::Painter, rect: egui::Rect, style: &egui::Style) {
+        let stroke = style.visuals.widgets.noninteractive.bg_stroke;
+        let font = egui::FontId::monospace(10.0);
+        let max_sec = (rect.width() / gui::PIXELS_PER_SEC_DEFAULT) as f64;
+        let mut bar = 0;
+        loop {
+            let sec = self.tempo.bbt_to_sec(&data::BarBeatTick::new(bar, 0, 0));
+            if sec > max_sec {
+                break;
+            }
+            let x = rect.left() + sec as f32 * gui::PIXELS_PER_SEC_DEFAULT;
+            painter.line_segment(
+                [[x, rect.center().y].into(), [x, rect.bottom()].into()],
+                stroke,
+            );
+            painter.text(
+                [x + 2.0, rect.center().y].into(),
+                egui::Align2::LEFT_TOP,
+                bar + 1,
+                font.clone(),
+                style.visuals.weak_text_color(),
+            );
+            bar += 1;
+        }
+    }
+    fn loop_bar(&mut self, ui: &mut egui::Ui, rect: egui::Rect) {
+        let range = &self.markers.loop_range.range;
+        let sec_to_x = |sec: f64| rect.left() + sec as f32 * gui::PIXELS_PER_SEC_DEFAULT;
+        let top = rect.top();
+        let bottom = rect.top() + RULER_HEIGHT / 2.0 - 2.0;
+        let body_rect = egui::Rect::from_x_y_ranges(
+            sec_to_x(range.start())..=sec_to_x(range.end()),
+            top..=bottom,
+        );
+        let visuals = &ui.style().visuals;
+        let color = if self.markers.loop_range.is_enabled() {
+            visuals.selection.bg_fill
+        } else {
+            visuals.widgets.inactive.bg_fill
+        };
+        ui.painter().rect_filled(body_rect, 2.0, color);
+
+        let body = ui
+            .interact(body_rect, ui.id().with("loop_body"), egui::Sense::drag())
+            .on_hover_cursor(egui::CursorIcon::Grab);
+        if let Some(start) = self.drag_position(&body, range.start()) {
+            let len = range.getrange();
+            range.set_start(start);
+            range.set_end(start + len);
+        }
+        let handle = |x: f32| {
+            egui::Rect::from_x_y_ranges(
+                x - HANDLE_WIDTH / 2.0..=x + HANDLE_WIDTH / 2.0,
+                top..=bottom,
+            )
+        };
+        let start_handle = ui
+            .interact(
+                handle(body_rect.left()),
+                ui.id().with("loop_start"),
+                egui::Sense::drag(),
+            )
+            .on_hover_cursor(egui::CursorIcon::ResizeWest);
+        if let Some(start) = self.drag_position(&start_handle, range.start()) {
+            range.set_start(start.min(range.end()));
+        }
+        let end_handle = ui
+            .interact(
+                handle(body_rect.right()),
+                ui.id().with("loop_end"),
+                egui::Sense::drag(),
+            )
+            .on_hover_cursor(egui::CursorIcon::ResizeEast);
+        if let Some(end) = self.drag_position(&end_handle, range.end()) {
+            range.set_end(end.max(range.start()));
+        }
+    }
+    fn markers(&mut self, ui: &mut egui::Ui, rect: egui::Rect) {
+        let y = rect.center().y;
+        let font = egui::FontId::proportional(12.0);
+        for (i, marker) in self.markers.markers.iter().enumerate() {
+            let x = rect.left() + marker.time.load() as f32 * gui::PIXELS_PER_SEC_DEFAULT;
+            let flag = egui::Rect::from_min_size([x, y].into(), [10.0, RULER_HEIGHT / 2.0].into());
+            let color = egui::Color32::GOLD;
+            ui.painter()
+                .line_segment([flag.left_top(), flag.left_bottom()], (1.5, color));
+            ui.painter().add(egui::Shape::convex_polygon(
+                vec![
+                    flag.left_top(),
+                    flag.right_top() + egui::vec2(0.0, 4.0),
+                    [x, y + 8.0].into(),
+                ],
+                color,
+                egui::Stroke::NONE,
+            ));
+            ui.painter().text(
+                flag.right_top(),
+                egui::Align2::LEFT_TOP,
+                &marker.label,
+                font.clone(),
+                ui.style().visuals.text_color(),
+            );
+            let response = ui
+                .interact(
+                    flag,
+                    ui.id().with(("marker", i)),
+                    egui::Sense::click_and_drag(),
+                )
+                .on_hover_cursor(egui::CursorIcon::ResizeHorizontal)
+                .on_hover_text(&marker.label);
+            if let Some(time) = self.drag_position(&response, marker.time.load()) {
+                marker.time.store(time);
+            }
+            response.context_menu(|ui| {
+                if ui.button("Remove").clicked() {
+                    let _ = self.action_tx.send(RemoveMarker::new(i).into());
+                    ui.close_menu();
+                }
+            });
+        }
+    }
+    fn playhead(&mut self, ui: &mut egui::Ui, rect: egui::Rect) {
+        let now = self.transport.time.load() as f64 / self.sample_rate as f64;
+        let x = rect.left() + now as f32 * gui::PIXELS_PER_SEC_DEFAULT;
+        let y = rect.center().y;
+        let head =
+            egui::Rect::from_x_y_ranges(x - HANDLE_WIDTH..=x + HANDLE_WIDTH, y..=rect.bottom());
+        ui.painter().add(egui::Shape::convex_polygon(
+            vec![head.left_top(), head.right_top(), head.center_bottom()],
+            ui.style().visuals.strong_text_color(),
+            egui::Stroke::NONE,
+        ));
+        let response = ui
+            .interact(head, ui.id().with("playhead"), egui::Sense::drag())
+            .on_hover_cursor(egui::CursorIcon::ResizeHorizontal);
+        if let Some(time) = self.drag_position(&response, now) {
+            self.scrub(&response, time);
+        } else if response.drag_released() {
+            self.transport.seek(self.transport.time.load());
+        }
+    }
+}
+
+impl<'a> egui::Widget for Ruler<'a> {
+    fn ui(mut self, ui: &mut egui::Ui) -> egui::Response {
+        let (rect, response) = ui.allocate_exact_size(
+            egui::vec2(ui.available_width(), RULER_HEIGHT),
+            egui::Sense::click_and_drag(),
+        );
+        if let Some(pos) = response.interact_pointer_pos() {
+            self.scrub(
+                &response,
+                ((pos.x - rect.left()) / gui::PIXELS_PER_SEC_DEFAULT) as f64,
+            );
+        }
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, ui.style().visuals.faint_bg_color);
+        self.draw_bars(&painter, rect, ui.style());
+        self.loop_bar(ui, rect);
+        self.markers(ui, rect);
+        self.playhead(ui, rect);
+        response
+    }
+}
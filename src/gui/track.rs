@@ -2,18 +2,23 @@ use crate::action::Action;
 use crate::data;
 use crate::gui;
 use crate::gui::menu;
+use crate::gui::parameter::slider_from_parameter;
+use crate::script::{Expr, Value};
+use crate::utils::atomic::{self, SimpleAtomic};
 use std::sync::mpsc;
 pub struct State {
     regions: Vec<gui::region::State>,
     // new_array_count: u32,
+    sample_rate: u32,
 }
 impl State {
-    pub fn new(param: &data::Track, _new_array_count: u32) -> Self {
-        let regions = get_region_from_param(param);
+    pub fn new(param: &data::Track, _new_array_count: u32, sample_rate: u32) -> Self {
+        let regions = get_region_from_param(param, sample_rate);
 
         Self {
             regions,
             // new_array_count,
+            sample_rate,
         }
     }
 }
@@ -25,17 +30,129 @@ pub struct Model<'a> {
     state: &'a mut State,
 }
 
-fn get_region_from_param(track: &data::Track) -> Vec<gui::region::State> {
+fn get_region_from_param(track: &data::Track, sample_rate: u32) -> Vec<gui::region::State> {
     match track {
-        data::Track::Regions(regions) => regions
+        data::Track::Regions(regions, _) => regions
             .iter()
-            .map(|region| gui::region::State::new(region, region.label.clone(), true))
+            .map(|region| gui::region::State::new(region, region.label.clone(), true, sample_rate))
             .collect::<Vec<_>>(),
-        data::Track::Generator(_) => todo!(),
+        data::Track::Generator(..) => vec![],
         data::Track::Transformer() => todo!(),
     }
 }
 
+/// Generator tracks have no regions. Shows the parameters of the generator instead.
+fn show_generator_track(generator: &Value, ui: &mut egui::Ui) -> egui::Response {
+    ui.horizontal(|ui| {
+        ui.set_min_height(gui::TRACK_HEIGHT);
+        match generator {
+            Value::Closure(
+                _,
+                _,
+                box Expr::App(box Expr::Literal(Value::ExtFunction(fname)), args),
+            ) => {
+                let icon = if fname == "input" { "🎤" } else { "~" };
+                ui.label(format!("{} {}", icon, fname));
+                for arg in args {
+                    if let Expr::Literal(Value::Parameter(param)) = arg {
+                        slider_from_parameter(param, false, ui);
+                    }
+                }
+            }
+            _ => {
+                ui.label("no matching ui for generator");
+            }
+        }
+    })
+    .response
+}
+
+fn show_record_arm(armed: &atomic::Bool, ui: &mut egui::Ui) {
+    let mut value = armed.load();
+    if ui
+        .toggle_value(&mut value, "⏺")
+        .on_hover_text("Arm for recording")
+        .changed()
+    {
+        armed.store(value);
+    }
+}
+
+fn show_overlap_mode(id: usize, overlap: &data::SharedOverlapMode, ui: &mut egui::Ui) {
+    let mut mode = overlap.get();
+    egui::ComboBox::from_id_source(("overlap mode", id))
+        .selected_text(mode.to_string())
+        .show_ui(ui, |ui| {
+            for m in data::OverlapMode::ALL {
+                ui.selectable_value(&mut mode, m, m.to_string());
+            }
+        })
+        .response
+        .on_hover_text("How overlapping regions are mixed");
+    if mode != overlap.get() {
+        overlap.set(mode);
+    }
+}
+
+/// The layout is applied to the audio thread on the next play.
+fn show_channel_layout(id: usize, layout: &data::SharedChannelLayout, ui: &mut egui::Ui) {
+    let mut current = layout.get();
+    egui::ComboBox::from_id_source(("channel layout", id))
+        .selected_text(current.to_string())
+        .show_ui(ui, |ui| {
+            let ambisonics =
+                data::ChannelLayout::AMBISONIC_ORDERS.map(data::ChannelLayout::Ambisonic);
+            for l in data::ChannelLayout::PRESETS.into_iter().chain(ambisonics) {
+                ui.selectable_value(&mut current, l, l.to_string());
+            }
+        })
+        .response
+        .on_hover_text("Channel layout of the track");
+    if current != layout.get() {
+        layout.set(current);
+    }
+}
+
+/// Ambisonic tracks are not panned, as they have no pair of the speakers.
+fn show_pan(
+    id: usize,
+    pan: &data::PanParam,
+    layout: &data::SharedChannelLayout,
+    ui: &mut egui::Ui,
+) {
+    if layout.get().is_ambisonic() {
+        return;
+    }
+    ui.push_id(("track pan", id), |ui| {
+        egui::menu::menu_button(ui, "pan", |ui| {
+            gui::parameter::pan_controls(("track pan", id), pan, ui);
+        });
+    });
+}
+
+/// Marks the zones where the regions are crossfaded, with the lines of fading in and out.
+fn draw_crossfade_zones(
+    regions: &[data::Region],
+    to_x: impl Fn(f64) -> f32,
+    top: f32,
+    painter: &egui::Painter,
+) {
+    let bottom = top + gui::TRACK_HEIGHT;
+    let stroke = egui::Stroke::new(1.5, egui::Color32::YELLOW);
+    for a in regions.iter() {
+        for b in regions.iter() {
+            if let Some((start, end)) = data::crossfade_zone(a.range.get_pair(), b.range.get_pair())
+            {
+                let (left, right) = (to_x(start), to_x(end));
+                let rect = egui::Rect::from_x_y_ranges(left..=right, top..=bottom);
+                painter.rect_filled(rect, 0.0, egui::Color32::from_white_alpha(40));
+                painter.line_segment([rect.left_top(), rect.right_bottom()], stroke);
+                painter.line_segment([rect.left_bottom(), rect.right_top()], stroke);
+            }
+        }
+    }
+}
+
 impl<'a> Model<'a> {
     pub fn new(
         id: usize,
@@ -52,7 +169,7 @@ impl<'a> Model<'a> {
     }
     fn get_position_to_add(&self) -> f64 {
         match &self.track {
-            data::Track::Regions(r) => r
+            data::Track::Regions(r, _) => r
                 .iter()
                 .fold(0.0, |acc, region| acc.max(region.range.end())),
             _ => unreachable!(),
@@ -60,7 +177,7 @@ impl<'a> Model<'a> {
     }
 
     fn sync_state(&mut self) {
-        self.state.regions = get_region_from_param(self.track);
+        self.state.regions = get_region_from_param(self.track, self.state.sample_rate);
     }
 }
 
@@ -68,7 +185,14 @@ impl<'a> egui::Widget for Model<'a> {
     fn ui(mut self, ui: &mut egui::Ui) -> egui::Response {
         let height = gui::TRACK_HEIGHT + 30.0;
         let response = match self.track {
-            data::Track::Regions(ref region_params) => {
+            data::Track::Regions(ref region_params, ref param) => {
+                ui.horizontal(|ui| {
+                    show_record_arm(&param.armed, ui);
+                    show_overlap_mode(self.id, &param.overlap, ui);
+                    show_channel_layout(self.id, &param.layout, ui);
+                    show_pan(self.id, &param.pan, &param.layout, ui);
+                    ui.add(gui::meter::Meter::new(&param.meter));
+                });
                 let w = ui.available_size().x;
                 let top = ui.available_rect_before_wrap().top();
 
@@ -95,7 +219,11 @@ impl<'a> egui::Widget for Model<'a> {
                                     ui.put(rect, super::region::Model::new(region_param, region))
                                 })
                                 .last()
-                        })
+                        });
+                        if param.overlap.get() == data::OverlapMode::Crossfade {
+                            let to_x = |sec: f64| area.left() + scale(sec);
+                            draw_crossfade_zones(region_params, to_x, top, ui.painter());
+                        }
                     });
                     Some(regions)
                 } else {
@@ -117,7 +245,8 @@ impl<'a> egui::Widget for Model<'a> {
                     ui.set_height(gui::TRACK_HEIGHT);
                     let position = self.get_position_to_add();
                     ui.centered_and_justified(|ui| {
-                        menu::add_region_button(self.id, position, &self.action_tx, ui);
+                        let layout = param.layout.get();
+                        menu::add_region_button(self.id, position, layout, &self.action_tx, ui);
                     })
                 });
 
@@ -131,7 +260,14 @@ impl<'a> egui::Widget for Model<'a> {
                 }
             }
 
-            data::Track::Generator(_) => todo!(),
+            data::Track::Generator(ref generator, ref param) => {
+                ui.horizontal(|ui| {
+                    show_channel_layout(self.id, &param.layout, ui);
+                    show_pan(self.id, &param.pan, &param.layout, ui);
+                    ui.add(gui::meter::Meter::new(&param.meter));
+                });
+                show_generator_track(generator, ui)
+            }
             data::Track::Transformer() => todo!(),
         };
 
@@ -28,28 +28,57 @@ impl egui::Widget for &mut Toggle {
     }
 }
 
+/// How the current time is displayed in the transport bar.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TimeDisplay {
+    /// minutes : seconds : microseconds
+    Clock,
+    /// bars : beats : ticks
+    Musical,
+}
+
 pub struct Model {
     pub param: Arc<data::Transport>,
     pub sample_rate: u64,
+    pub tempo: data::TempoMap,
+    pub display: TimeDisplay,
     playbutton: Toggle,
     // pub play_button: egui::Texture,
 }
 impl Model {
-    pub fn new(param: Arc<data::Transport>, sample_rate: u64) -> Self {
+    pub fn new(param: Arc<data::Transport>, sample_rate: u64, tempo: data::TempoMap) -> Self {
         // egui::paint::
         Self {
             param: param.clone(),
             sample_rate,
+            tempo,
+            display: TimeDisplay::Clock,
             playbutton: Toggle::new(param),
             // play_button,
         }
     }
+    pub fn set_tempo_map(&mut self, tempo: &data::TempoMap) {
+        if &self.tempo != tempo {
+            self.tempo = tempo.clone();
+        }
+    }
     fn get_time_in_sample(&self) -> u64 {
         self.param.time.load()
     }
     fn get_time(&self) -> f64 {
         self.get_time_in_sample() as f64 / self.sample_rate as f64
     }
+    fn format_time(&self) -> String {
+        match self.display {
+            TimeDisplay::Clock => {
+                let time = std::time::Duration::from_secs_f64(self.get_time());
+                let min = time.div_f64(60.0).as_secs();
+                let secs = time.as_secs() % 60;
+                format!("{:02} : {:02} : {:06}", min, secs, time.subsec_micros())
+            }
+            TimeDisplay::Musical => self.tempo.sec_to_bbt(self.get_time()).to_string(),
+        }
+    }
     // fn is_playing(&self) -> bool {
     //     self.param.is_playing()
     // }
@@ -61,23 +90,40 @@ impl egui::Widget for &mut Model {
             font_id.size = 24.0 // whatever size you want here
         }
         ui.horizontal(|ui| {
-            let time = std::time::Duration::from_secs_f64(self.get_time());
-
             if ui.button("⏮").clicked() {
-                self.param.time.store(0);
+                self.param.seek(0);
             }
             if ui.button("⏹").clicked() {
                 self.param.request_play(data::PlayOp::Halt);
             }
             ui.add(&mut self.playbutton);
-            let min = time.div_f64(60.0).as_secs();
-            let secs = time.as_secs() % 60;
-            ui.label(format!(
-                "{:02} : {:02} : {:06}",
-                min,
-                secs,
-                time.subsec_micros()
-            ));
+            let recording = self.param.is_recording();
+            if ui
+                .selectable_label(recording, "⏺")
+                .on_hover_text("Record the input into the armed tracks")
+                .clicked()
+            {
+                let op = if recording {
+                    data::PlayOp::Pause
+                } else {
+                    data::PlayOp::Record
+                };
+                self.param.request_play(op);
+            }
+            let time = ui
+                .add(egui::Label::new(self.format_time()).sense(egui::Sense::click()))
+                .on_hover_text("Click to switch time / bars:beats:ticks");
+            if time.clicked() {
+                self.display = match self.display {
+                    TimeDisplay::Clock => TimeDisplay::Musical,
+                    TimeDisplay::Musical => TimeDisplay::Clock,
+                };
+            }
+            ui.label(self.tempo.to_string());
+            let latency_ms = self.param.latency.load() as f64 * 1000.0 / self.sample_rate as f64;
+            ui.label(format!("{:.1} ms", latency_ms))
+                .on_hover_text("Latency from the input to the output");
+            ui.add(super::meter::Meter::new(&self.param.meter).with_true_peak());
         })
         .response
     }
@@ -19,6 +19,10 @@ pub mod utils;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod cli;
 
+#[cfg(feature = "alloc-check")]
+#[global_allocator]
+static ALLOCATOR: utils::alloc_check::CheckedAllocator = utils::alloc_check::CheckedAllocator;
+
 #[cfg(target_arch = "wasm32")]
 use console_error_panic_hook;
 #[cfg(target_arch = "wasm32")]
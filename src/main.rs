@@ -8,11 +8,19 @@ use crate::cli::{self, Parser};
 
 #[cfg(not(target_arch = "wasm32"))]
 fn main() {
+    let args = cli::Args::parse();
+    if let Some(cli::Command::Render(render_args)) = &args.command {
+        if let Err(e) = cli::render(render_args) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
     let native_options = eframe::NativeOptions {
         initial_window_size: Some(egui::vec2(1200., 900.)),
         ..Default::default()
     };
-    let arg: crate::data::LaunchArg = cli::Args::parse().into();
+    let arg: crate::data::LaunchArg = args.into();
     eframe::run_native(
         "otopoiesis",
         native_options,
@@ -12,7 +12,8 @@ pub use {
     expr::{EvalError, Expr},
     value::Value,
 };
-// mod test;
+#[cfg(test)]
+mod test;
 // use serde::{Deserialize, Serialize};
 pub trait ExtFunT: std::fmt::Debug {
     fn exec(&self, app: &mut Option<&mut data::AppModel>, v: &[Value]) -> Result<Value, EvalError>;
@@ -1,4 +1,5 @@
-use crate::data::AppModel;
+use crate::data::{AppModel, TempoMap};
+use crate::parameter::Parameter;
 
 use super::{EvalError, ExtFun, ExtFunT, Value};
 use std::collections::HashMap;
@@ -38,6 +39,80 @@ impl ExtFunT for Print {
 #[derive(Clone, Debug)]
 pub struct Generator {}
 
+fn as_number(v: &Value) -> Option<f64> {
+    match v {
+        Value::Number(n) => Some(*n),
+        Value::Parameter(p) => Some(p.get() as f64),
+        _ => None,
+    }
+}
+/// Musical functions refer the tempo map of the current project, or the default tempo when evaluated without app.
+fn get_tempo_map(app: &Option<&mut AppModel>) -> TempoMap {
+    app.as_ref()
+        .map_or_else(TempoMap::default, |a| a.project.tempo.clone())
+}
+
+/// `beats(n)` makes a musical duration of n beats.
+#[derive(Clone, Debug)]
+pub struct Beats {}
+
+impl ExtFunT for Beats {
+    fn exec(&self, _app: &mut Option<&mut AppModel>, v: &[Value]) -> Result<Value, EvalError> {
+        if v.len() != 1 {
+            return Err(EvalError::InvalidNumArgs(1, v.len()));
+        }
+        as_number(&v[0])
+            .map(Value::Beats)
+            .ok_or(EvalError::TypeMismatch("Not a number".into()))
+    }
+}
+
+/// `bars(n)` makes a musical duration of n bars, using the time signature at the beginning of the project.
+#[derive(Clone, Debug)]
+pub struct Bars {}
+
+impl ExtFunT for Bars {
+    fn exec(&self, app: &mut Option<&mut AppModel>, v: &[Value]) -> Result<Value, EvalError> {
+        if v.len() != 1 {
+            return Err(EvalError::InvalidNumArgs(1, v.len()));
+        }
+        let beats_per_bar = get_tempo_map(app).initial().signature.numerator as f64;
+        as_number(&v[0])
+            .map(|n| Value::Beats(n * beats_per_bar))
+            .ok_or(EvalError::TypeMismatch("Not a number".into()))
+    }
+}
+
+/// `seconds(duration, [from_beat])` converts a musical duration into seconds.
+/// The optional second argument is the position in beats where the duration starts, which matters when the tempo changes.
+#[derive(Clone, Debug)]
+pub struct Seconds {}
+
+impl ExtFunT for Seconds {
+    fn exec(&self, app: &mut Option<&mut AppModel>, v: &[Value]) -> Result<Value, EvalError> {
+        if v.is_empty() || v.len() > 2 {
+            return Err(EvalError::InvalidNumArgs(2, v.len()));
+        }
+        let from = match v.get(1) {
+            Some(f) => as_number(f).ok_or(EvalError::TypeMismatch("Not a number".into()))?,
+            None => 0.0,
+        };
+        match &v[0] {
+            Value::Beats(b) => Ok(Value::Number(
+                get_tempo_map(app).beats_duration_to_sec(from, *b),
+            )),
+            other => as_number(other)
+                .map(Value::Number)
+                .ok_or(EvalError::TypeMismatch("Not a duration".into())),
+        }
+    }
+}
+
 pub fn gen_default_functions() -> HashMap<&'static str, ExtFun> {
-    HashMap::from([("reverse", ExtFun::new(ArrayReverse {}))])
+    HashMap::from([
+        ("reverse", ExtFun::new(ArrayReverse {})),
+        ("beats", ExtFun::new(Beats {})),
+        ("bars", ExtFun::new(Bars {})),
+        ("seconds", ExtFun::new(Seconds {})),
+    ])
 }
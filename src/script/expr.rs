@@ -15,6 +15,18 @@ pub enum EvalError {
     InvalidNumArgs(usize, usize), //expected,actual
 }
 
+impl std::fmt::Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalError::TypeMismatch(msg) => write!(f, "type mismatch: {}", msg),
+            EvalError::NotFound => write!(f, "not found"),
+            EvalError::InvalidNumArgs(expected, actual) => {
+                write!(f, "expected {} arguments, got {}", expected, actual)
+            }
+        }
+    }
+}
+
 impl Expr {
     pub fn eval(
         &self,
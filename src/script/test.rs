@@ -1,8 +1,74 @@
+use super::*;
+use crate::data::{self, GlobalSetting, LaunchArg, Transport};
+use data::AppModel;
+
+fn new_app() -> AppModel {
+    AppModel::new(
+        Transport::new(),
+        GlobalSetting::default(),
+        LaunchArg::default(),
+    )
+}
+fn call(fname: &str, args: Vec<Expr>) -> Expr {
+    Expr::App(
+        Expr::Literal(Value::ExtFunction(fname.to_string())).into(),
+        args,
+    )
+}
+fn number(n: f64) -> Expr {
+    Expr::Literal(Value::Number(n))
+}
+fn eval_number(expr: &Expr, app: &mut AppModel) -> f64 {
+    match expr.eval(Arc::new(Environment::new()), &mut Some(app)) {
+        Ok(Value::Number(n)) | Ok(Value::Beats(n)) => n,
+        Ok(v) => panic!("not a number: {:?}", v),
+        Err(e) => panic!("{}", e),
+    }
+}
+
 #[test]
 fn eval_expr() {
-    use super::*;
-    use crate::data::{GlobalSetting, LaunchArg, Transport};
-    use data::AppModel;
-    let mut app = AppModel::new(Transport::new(), GlobalSetting, LaunchArg::default());
-    
+    let mut app = new_app();
+    let array = Value::Array(vec![Value::Number(1.0), Value::Number(2.0)], Type::Number);
+    let res = call("reverse", vec![Expr::Literal(array)])
+        .eval(Arc::new(Environment::new()), &mut Some(&mut app));
+    match res {
+        Ok(Value::Array(a, _)) => assert!(matches!(
+            a.as_slice(),
+            [Value::Number(2.0), Value::Number(1.0)]
+        )),
+        _ => panic!("not reversed"),
+    }
+}
+#[test]
+fn musical_duration() {
+    let mut app = new_app();
+    // 2 bars of 4/4 at 120bpm (4 sec), then 3/4 at 60bpm.
+    app.project.tempo =
+        data::TempoMap::default().with_change(2, 60.0, data::TimeSignature::new(3, 4));
+    assert_eq!(
+        eval_number(&call("beats", vec![number(3.0)]), &mut app),
+        3.0
+    );
+    // the bars follow the time signature at the beginning.
+    assert_eq!(eval_number(&call("bars", vec![number(2.0)]), &mut app), 8.0);
+    let seconds = |duration, from: Option<f64>| {
+        call(
+            "seconds",
+            std::iter::once(duration).chain(from.map(number)).collect(),
+        )
+    };
+    assert_eq!(
+        eval_number(&seconds(call("bars", vec![number(1.0)]), None), &mut app),
+        2.0
+    );
+    // 1 beat at 120bpm and 1 beat at 60bpm across the change.
+    let across = seconds(call("beats", vec![number(2.0)]), Some(7.0));
+    assert_eq!(eval_number(&across, &mut app), 1.5);
+    let after = seconds(call("beats", vec![number(2.0)]), Some(8.0));
+    assert_eq!(eval_number(&after, &mut app), 2.0);
+    // the plain numbers are already in seconds.
+    assert_eq!(eval_number(&seconds(number(0.5), None), &mut app), 0.5);
+    let invalid = call("seconds", vec![]).eval(Arc::new(Environment::new()), &mut Some(&mut app));
+    assert!(matches!(invalid, Err(EvalError::InvalidNumArgs(2, 0))));
 }
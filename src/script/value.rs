@@ -10,9 +10,15 @@ pub enum Value {
     Function(Vec<Id>, Box<Expr>),
     Closure(Vec<Id>, Arc<Environment<Value>>, Box<Expr>),
     ExtFunction(Id),
-    Track(Box<Value>, Type),                //input type, output type
-    Region(f64, f64, Box<Value>, Id, Type), //start,dur,content,label,type
-    Project(f64, Vec<Value>),               //todo:reducer
+    Track(Box<Value>, Type, #[serde(default)] data::TrackParam), //input type, output type
+    Region(f64, f64, Box<Value>, Id, Type),                      //start,dur,content,label,type
+    Project(
+        f64,
+        Vec<Value>,
+        #[serde(default)] data::TempoMap,
+        #[serde(default)] data::Markers,
+    ), //todo:reducer
+    Beats(f64),                                                  //musical duration counted in beats
 }
 
 impl Value {
@@ -32,15 +38,19 @@ impl Value {
             Rate::Audio,
         );
         let generator = Value::None;
-        Self::Track(generator.into(), t)
+        Self::Track(generator.into(), t, data::TrackParam::default())
     }
     pub fn midi_track() -> Self {
-        Self::Track(Value::None.into(), Type::Vec(Type::midi_note().into()))
+        Self::Track(
+            Value::None.into(),
+            Type::Vec(Type::midi_note().into()),
+            data::TrackParam::default(),
+        )
     }
     pub fn get_type(&self) -> Type {
         match self {
             Value::None => Type::Unit,
-            Value::Number(_) | Value::Parameter(_) => Type::Number,
+            Value::Number(_) | Value::Parameter(_) | Value::Beats(_) => Type::Number,
             Value::String(_) => Type::String,
             Value::Array(v, t) => {
                 // let _t_elem = v.get(0).map_or(Type::Unknown, |v| v.get_type()).into();
@@ -50,9 +60,9 @@ impl Value {
             Value::Function(_a, _v) => todo!(),
             Value::Closure(_, _, _) => todo!(),
             Value::ExtFunction(_f) => Type::Function(Type::Unknown.into(), Type::Unknown.into()), //cannot infer?
-            Value::Track(_input, _output) => todo!(),
+            Value::Track(_input, _output, _param) => todo!(),
             Value::Region(_start, _dur, _, _label, _) => todo!(),
-            Value::Project(_sr, _tracks) => todo!(),
+            Value::Project(_sr, _tracks, _tempo, _markers) => todo!(),
         }
     }
 }
@@ -1,4 +1,6 @@
 //! Misc utilities such as Atomic Structure.
+#[cfg(feature = "alloc-check")]
+pub mod alloc_check;
 pub mod atomic;
 use serde::{Deserialize, Serialize};
 
@@ -0,0 +1,79 @@
+//! Detects allocations on the audio thread. Enabled by `alloc-check` feature.
+//!
+//! The global allocator counts allocations made while [`AudioThreadGuard`] is alive on the current thread,
+//! and the guard reports them when dropped.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+thread_local! {
+    static IN_AUDIO_CALLBACK: Cell<bool> = const { Cell::new(false) };
+}
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+fn count() {
+    // `try_with` because the allocator may be called while the thread local is destroyed.
+    if IN_AUDIO_CALLBACK
+        .try_with(|flag| flag.get())
+        .unwrap_or(false)
+    {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub struct CheckedAllocator;
+
+unsafe impl GlobalAlloc for CheckedAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc(layout)
+    }
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc_zeroed(layout)
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        System.realloc(ptr, layout, new_size)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        count();
+        System.dealloc(ptr, layout)
+    }
+}
+
+/// Total count of allocations and deallocations detected on the audio thread.
+pub fn get_allocations() -> usize {
+    ALLOCATIONS.load(Ordering::Relaxed)
+}
+
+/// Marks the current thread as in the audio callback while alive.
+pub struct AudioThreadGuard {
+    count_at_start: usize,
+}
+
+impl AudioThreadGuard {
+    pub fn new() -> Self {
+        IN_AUDIO_CALLBACK.with(|flag| flag.set(true));
+        Self {
+            count_at_start: get_allocations(),
+        }
+    }
+}
+
+impl Default for AudioThreadGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for AudioThreadGuard {
+    fn drop(&mut self) {
+        IN_AUDIO_CALLBACK.with(|flag| flag.set(false));
+        let n = get_allocations() - self.count_at_start;
+        if n > 0 {
+            log::error!("{} allocations in the audio callback", n);
+        }
+    }
+}